@@ -0,0 +1,60 @@
+//! 语法高亮的基准测试：对比每行重新加载语法集/主题集与共享高亮上下文
+//! 运行：cargo bench --bench highlight
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
+
+#[allow(dead_code)]
+#[path = "../src/utils/highlight.rs"]
+mod highlight;
+
+use highlight::{DEFAULT_THEME, FileHighlighter};
+
+const SOURCE: &str = include_str!("../src/walk.rs");
+/// 旧实现每行都要加载一次语法集，行数太多时单次迭代耗时过长
+const LINES: usize = 20;
+
+/// 旧实现：每一行都重新加载语法集和主题集，并新建HighlightLines
+fn reload_per_line(source: &str) -> usize {
+    let mut total = 0;
+    for line in LinesWithEndings::from(source).take(LINES) {
+        let ps = SyntaxSet::load_defaults_newlines();
+        let ts = ThemeSet::load_defaults();
+        let syntax = ps.find_syntax_by_extension("rs").unwrap();
+        let mut highlighter = HighlightLines::new(syntax, &ts.themes[DEFAULT_THEME]);
+        let ranges = highlighter.highlight_line(line, &ps).unwrap_or_default();
+        total += as_24_bit_terminal_escaped(&ranges[..], true).len();
+    }
+    total
+}
+
+/// 新实现：共享高亮上下文，同一文件使用同一个高亮器
+fn shared_context(source: &str) -> usize {
+    let mut total = 0;
+    let mut highlighter = FileHighlighter::for_extension("rs").unwrap();
+    for line in LinesWithEndings::from(source).take(LINES) {
+        let ranges = highlighter.highlight_line(line);
+        total += as_24_bit_terminal_escaped(&ranges[..], true).len();
+    }
+    total
+}
+
+fn bench_highlight(c: &mut Criterion) {
+    let mut group = c.benchmark_group("highlight");
+    group.sample_size(10);
+    group.bench_function("reload_per_line", |b| {
+        b.iter(|| reload_per_line(black_box(SOURCE)))
+    });
+    // 预先加载共享上下文，只测量高亮本身
+    shared_context(SOURCE);
+    group.bench_function("shared_context", |b| {
+        b.iter(|| shared_context(black_box(SOURCE)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_highlight);
+criterion_main!(benches);
@@ -0,0 +1,351 @@
+use crate::ls::{TreeOptions, format_size, print_file_tree};
+use crate::utils::query::Query;
+use crate::walk::{WalkOptions, walk};
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 比例条的宽度（字符数）
+const BAR_WIDTH: usize = 10;
+
+/// 一个文件或目录占用的空间，目录为其中所有文件的合计，目录本身占用的块不计入
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskUsage {
+    /// 文件内容的字节数之和
+    pub apparent: u64,
+    /// 实际占用的磁盘空间，块数×512
+    pub disk: u64,
+    /// 包含的文件数，文件本身为1
+    pub files: u64,
+    /// 包含的子目录数（递归）
+    pub dirs: u64,
+    pub is_dir: bool,
+}
+
+impl DiskUsage {
+    fn add(&mut self, other: &DiskUsage) {
+        self.apparent += other.apparent;
+        self.disk += other.disk;
+        self.files += other.files;
+        self.dirs += other.dirs;
+    }
+}
+
+/// 并行遍历root，计算其中每个文件和目录占用的空间
+/// 指向同一个inode的硬链接只计算一次；筛选条件只作用于文件，不满足的文件不计入也不出现在结果中
+/// # 参数
+/// - `root`: 起始路径，结果中的路径都以它开头
+/// - `options`: 遍历选项，max_depth应为None，否则深层的文件不会被计入
+/// - `query`: 筛选条件
+pub fn compute_usage(
+    root: &Path,
+    options: &WalkOptions,
+    query: Option<&Query>,
+) -> HashMap<PathBuf, DiskUsage> {
+    let seen: Mutex<HashSet<(u64, u64)>> = Mutex::new(HashSet::new());
+    let entries: Mutex<Vec<(PathBuf, DiskUsage)>> = Mutex::new(Vec::new());
+    walk(root, options, |p, _| {
+        let Ok(metadata) = p.symlink_metadata() else {
+            return;
+        };
+        let own = if metadata.is_dir() {
+            DiskUsage {
+                is_dir: true,
+                ..DiskUsage::default()
+            }
+        } else {
+            if query.is_some_and(|query| !query.matches(p)) {
+                return;
+            }
+            let counted = metadata.nlink() <= 1
+                || seen
+                    .lock()
+                    .unwrap()
+                    .insert((metadata.dev(), metadata.ino()));
+            DiskUsage {
+                apparent: if counted { metadata.len() } else { 0 },
+                disk: if counted { metadata.blocks() * 512 } else { 0 },
+                files: 1,
+                ..DiskUsage::default()
+            }
+        };
+        entries.lock().unwrap().push((p.to_path_buf(), own));
+    });
+
+    let mut usage: HashMap<PathBuf, DiskUsage> = HashMap::new();
+    for (path, own) in entries.into_inner().unwrap() {
+        // 子目录计入上级目录的目录数
+        let counted = DiskUsage {
+            dirs: own.is_dir as u64,
+            ..own
+        };
+        if path != root {
+            for ancestor in path.ancestors().skip(1) {
+                let entry = usage.entry(ancestor.to_path_buf()).or_default();
+                entry.is_dir = true;
+                entry.add(&counted);
+                if ancestor == root {
+                    break;
+                }
+            }
+        }
+        let entry = usage.entry(path).or_default();
+        entry.is_dir = own.is_dir;
+        entry.add(&DiskUsage { dirs: 0, ..own });
+    }
+    usage
+}
+
+/// ls --dir-size使用的缓存，一次运行中每个目录只遍历一次
+/// 统计一个目录时其中所有子目录的结果也会被记录下来，ls -R进入子目录时不需要再次遍历
+/// 统计时包含隐藏文件和被忽略的文件，与目录实际占用的空间一致
+pub struct UsageCache {
+    options: WalkOptions,
+    usage: RefCell<HashMap<PathBuf, DiskUsage>>,
+}
+
+impl UsageCache {
+    pub fn new() -> Self {
+        UsageCache {
+            options: WalkOptions {
+                hidden: true,
+                no_ignore: true,
+                ..WalkOptions::new()
+            },
+            usage: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn options(&self) -> &WalkOptions {
+        &self.options
+    }
+
+    pub fn get(&self, path: &Path) -> DiskUsage {
+        if let Some(usage) = self.usage.borrow().get(path) {
+            return *usage;
+        }
+        let usage = compute_usage(path, &self.options, None);
+        let result = usage.get(path).copied().unwrap_or_default();
+        self.usage.borrow_mut().extend(usage);
+        result
+    }
+}
+
+/// 文件树中每一行前面的占用空间、比例条和百分比
+pub struct TreeSizes<'a> {
+    usage: &'a HashMap<PathBuf, DiskUsage>,
+    /// 是否按文件内容的字节数而不是占用的磁盘空间显示
+    apparent: bool,
+    /// 比例条以它为100%
+    total: u64,
+    /// 是否把子项按占用空间从大到小排列
+    pub sort_by_size: bool,
+}
+
+impl<'a> TreeSizes<'a> {
+    pub fn new(
+        usage: &'a HashMap<PathBuf, DiskUsage>,
+        root: &Path,
+        apparent: bool,
+        sort_by_size: bool,
+    ) -> Self {
+        let mut sizes = TreeSizes {
+            usage,
+            apparent,
+            total: 0,
+            sort_by_size,
+        };
+        sizes.total = sizes.size_of(root).unwrap_or(0);
+        sizes
+    }
+
+    /// 不在结果中的条目（被忽略或不满足筛选条件的文件）返回None
+    pub fn size_of(&self, path: &Path) -> Option<u64> {
+        self.usage.get(path).map(|usage| {
+            if self.apparent {
+                usage.apparent
+            } else {
+                usage.disk
+            }
+        })
+    }
+
+    /// 从大到小排列，大小相同时按名称
+    pub fn sort(&self, entries: &mut [PathBuf]) {
+        entries.sort_by(|a, b| self.size_of(b).cmp(&self.size_of(a)).then_with(|| a.cmp(b)));
+    }
+
+    pub fn column(&self, path: &Path, color: bool) -> String {
+        match self.size_of(path) {
+            Some(size) => format_column(size, self.total, color),
+            None => self.blank(),
+        }
+    }
+
+    /// 与column等宽的空白，用于省略提示等没有大小的行
+    pub fn blank(&self) -> String {
+        " ".repeat(9 + 1 + BAR_WIDTH + 1 + 6 + 2)
+    }
+}
+
+/// 格式化为"大小 比例条 百分比"，宽度固定
+fn format_column(size: u64, total: u64, color: bool) -> String {
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        size as f64 / total as f64
+    };
+    let bar = format!("{:<width$}", bar(ratio), width = BAR_WIDTH);
+    format!(
+        "{:>9} {} {:>5.1}%  ",
+        format_size(size),
+        if color { bar.cyan().to_string() } else { bar },
+        ratio * 100.0
+    )
+}
+
+/// 用八分之一宽度的方块字符画出比例条
+fn bar(ratio: f64) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let eighths = (ratio.clamp(0.0, 1.0) * (BAR_WIDTH * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(PARTIAL[eighths % 8]);
+    }
+    bar
+}
+
+/// du的统计和显示选项
+pub struct DuOptions {
+    /// 文件树显示的深度，0只显示总计
+    pub depth: usize,
+    /// 文件树每层最多显示的条目数，0表示不限制
+    pub max_items: usize,
+    /// 不为None时改为列出占用空间最大的top个文件
+    pub top: Option<usize>,
+    /// 按文件内容的字节数而不是占用的磁盘空间显示和排序
+    pub apparent: bool,
+    /// 子项的排列顺序，size或name
+    pub sort: String,
+    /// 不统计隐藏文件和隐藏目录
+    pub exclude_hidden: bool,
+    /// 不统计.gitignore等规则忽略的文件
+    pub git_ignore: bool,
+    /// 按color_config.json高亮显示
+    pub color: bool,
+}
+
+/// 统计目录占用的空间，以文件树或占用空间最大的文件列表显示
+/// 默认统计包括隐藏文件和被忽略的文件在内的所有文件，与目录实际占用的空间一致
+/// # 参数
+/// - `paths`: 要统计的目录或文件
+/// - `du_options`: 统计和显示选项
+/// - `filters`: (字段名, 选项值)形式的筛选条件，与search的--size、--file-type等相同
+/// - `where_expr`: 筛选表达式
+pub fn du_command(
+    paths: Vec<String>,
+    du_options: DuOptions,
+    filters: &[(&str, &Option<String>)],
+    where_expr: Option<String>,
+) -> Result<()> {
+    let DuOptions {
+        depth,
+        max_items,
+        top,
+        apparent,
+        sort,
+        exclude_hidden,
+        git_ignore,
+        color,
+    } = du_options;
+    let query = Query::from_options(filters, &where_expr)?;
+    let options = WalkOptions {
+        hidden: !exclude_hidden,
+        no_ignore: !git_ignore,
+        ..WalkOptions::new()
+    };
+
+    let mut roots = Vec::new();
+    for path in &paths {
+        let root = PathBuf::from(path);
+        if root.symlink_metadata().is_err() {
+            return Err(anyhow!("Path does not exist: {}", path));
+        }
+        let usage = compute_usage(&root, &options, query.as_ref());
+        roots.push((root, usage));
+    }
+
+    if let Some(top) = top {
+        print_top(&roots, top, apparent, color);
+        return Ok(());
+    }
+    for (i, (root, usage)) in roots.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let sizes = TreeSizes::new(usage, root, apparent, sort == "size");
+        print_file_tree(
+            root,
+            &TreeOptions {
+                max_depth: depth,
+                color,
+                max_items_per_dir: max_items,
+                hyperlink: false,
+                ignore: Some(&options),
+                query: query.as_ref(),
+                sizes: Some(&sizes),
+            },
+        );
+        print_summary(usage.get(root).copied().unwrap_or_default());
+    }
+    Ok(())
+}
+
+fn print_summary(total: DiskUsage) {
+    println!(
+        "共{}个文件，{}个目录，占用磁盘{}，实际大小{}",
+        total.files,
+        total.dirs,
+        format_size(total.disk).green(),
+        format_size(total.apparent)
+    );
+}
+
+/// 列出所有路径中占用空间最大的top个文件，比例条以所有路径的总计为100%
+fn print_top(
+    roots: &[(PathBuf, HashMap<PathBuf, DiskUsage>)],
+    top: usize,
+    apparent: bool,
+    color: bool,
+) {
+    let size = |usage: &DiskUsage| {
+        if apparent { usage.apparent } else { usage.disk }
+    };
+    let mut total = DiskUsage::default();
+    let mut files: Vec<(&PathBuf, u64)> = Vec::new();
+    for (root, usage) in roots {
+        if let Some(root_usage) = usage.get(root) {
+            total.add(root_usage);
+        }
+        files.extend(
+            usage
+                .iter()
+                .filter(|(_, usage)| !usage.is_dir)
+                .map(|(path, usage)| (path, size(usage))),
+        );
+    }
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    for (path, file_size) in files.iter().take(top) {
+        println!(
+            "{}{}",
+            format_column(*file_size, size(&total), color),
+            path.display()
+        );
+    }
+    print_summary(total);
+}
@@ -0,0 +1,448 @@
+pub mod similar;
+
+use crate::dupes::similar::{HashAlgorithm, similar_images_command};
+use crate::ls::format_size;
+use crate::rename::rename::wait_for_yes_no;
+use crate::utils::query::Query;
+use crate::walk::{WalkOptions, walk};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use filetime::FileTime;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::SystemTime;
+
+/// 部分哈希读取的文件头和文件尾的大小，不超过两倍大小的文件直接计算完整的哈希
+const PARTIAL_BLOCK: u64 = 16 * 1024;
+
+/// 一个参与比较的文件
+#[derive(Debug, Clone)]
+pub(super) struct Candidate {
+    pub(super) path: PathBuf,
+    pub(super) size: u64,
+    pub(super) modified: SystemTime,
+}
+
+/// 对每组重复文件中多余的文件的处理方式
+pub(super) enum DupesAction {
+    Delete,
+    Hardlink,
+    Symlink,
+    /// 移动到隔离目录
+    Quarantine(PathBuf),
+}
+
+impl DupesAction {
+    fn parse(action: &str, to: Option<String>) -> Result<Self> {
+        match action {
+            "delete" => Ok(DupesAction::Delete),
+            "hardlink" => Ok(DupesAction::Hardlink),
+            "symlink" => Ok(DupesAction::Symlink),
+            "quarantine" => to
+                .map(|to| DupesAction::Quarantine(PathBuf::from(to)))
+                .ok_or_else(|| anyhow!("quarantine需要用--to指定隔离目录")),
+            _ => Err(anyhow!("Invalid action: {}", action)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DupesAction::Delete => "删除",
+            DupesAction::Hardlink => "替换为硬链接",
+            DupesAction::Symlink => "替换为符号链接",
+            DupesAction::Quarantine(_) => "移动到隔离目录",
+        }
+    }
+}
+
+/// 找到重复文件后的处理方式，以及查找相似图片的选项
+pub struct DupesOptions {
+    /// 对多余文件的处理方式，None时只列出重复的文件
+    pub action: Option<String>,
+    /// 每组保留的文件，oldest、newest或largest，None时查找重复文件默认oldest，查找相似图片默认largest
+    pub keep: Option<String>,
+    /// quarantine的隔离目录
+    pub to: Option<String>,
+    /// 改为用感知哈希查找相似的图片
+    pub similar_images: bool,
+    /// 感知哈希算法，ahash、dhash或phash
+    pub hash: String,
+    /// 汉明距离不超过该值的两张图片视为相似
+    pub threshold: u32,
+}
+
+/// 查找重复文件：先按大小分组，再比较文件头尾的部分哈希，最后比较完整的哈希
+/// # 参数
+/// - `paths`: 要查找的目录或文件
+/// - `max_depth`: 最大深度，None表示不限制
+/// - `hidden`/`no_ignore`: 与search相同的遍历选项
+/// - `filters`: (字段名, 选项值)形式的筛选条件，与search的--size、--file-type等相同
+/// - `where_expr`: 筛选表达式
+/// - `dupes_options`: 处理方式和相似图片的选项
+pub fn dupes_command(
+    paths: Vec<String>,
+    max_depth: Option<usize>,
+    hidden: bool,
+    no_ignore: bool,
+    filters: &[(&str, &Option<String>)],
+    where_expr: Option<String>,
+    dupes_options: DupesOptions,
+) -> Result<()> {
+    let DupesOptions {
+        action,
+        keep,
+        to,
+        similar_images,
+        hash,
+        threshold,
+    } = dupes_options;
+    let query = Query::from_options(filters, &where_expr)?;
+    let action = action.map(|a| DupesAction::parse(&a, to)).transpose()?;
+    let options = WalkOptions {
+        max_depth,
+        hidden,
+        no_ignore,
+        ..WalkOptions::new()
+    };
+
+    let candidates = collect_candidates(&paths, &options, query.as_ref());
+    if similar_images {
+        let algorithm = HashAlgorithm::parse(&hash)?;
+        let keep = keep.unwrap_or_else(|| "largest".to_string());
+        return similar_images_command(candidates, algorithm, threshold, action, &keep);
+    }
+
+    let groups = find_duplicates(candidates);
+    if groups.is_empty() {
+        println!("没有找到重复的文件");
+        return Ok(());
+    }
+    print_groups(&groups);
+
+    let Some(action) = action else {
+        return Ok(());
+    };
+    // 每组按修改时间从旧到新排列，内容相同时largest与oldest相同
+    let newest = keep.as_deref() == Some("newest");
+    let plan: Vec<(&Candidate, &Candidate)> = groups
+        .iter()
+        .flat_map(|group| {
+            let kept = if newest {
+                group.last().unwrap()
+            } else {
+                group.first().unwrap()
+            };
+            group
+                .iter()
+                .filter(move |c| c.path != kept.path)
+                .map(move |c| (c, kept))
+        })
+        .collect();
+    apply_action(&plan, &action, true)
+}
+
+/// 遍历所有路径，收集满足筛选条件的非空普通文件
+/// 指向同一个inode的硬链接只保留一个，它们并不占用额外的空间
+fn collect_candidates(
+    paths: &[String],
+    options: &WalkOptions,
+    query: Option<&Query>,
+) -> Vec<Candidate> {
+    let seen: Mutex<HashSet<(u64, u64)>> = Mutex::new(HashSet::new());
+    let candidates = Mutex::new(Vec::new());
+    for root in paths {
+        walk(Path::new(root), options, |p, _| {
+            let Ok(metadata) = p.symlink_metadata() else {
+                return;
+            };
+            if !metadata.is_file() || metadata.len() == 0 {
+                return;
+            }
+            if query.is_some_and(|query| !query.matches(p)) {
+                return;
+            }
+            if !seen
+                .lock()
+                .unwrap()
+                .insert((metadata.dev(), metadata.ino()))
+            {
+                return;
+            }
+            candidates.lock().unwrap().push(Candidate {
+                path: p.to_path_buf(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        });
+    }
+    candidates.into_inner().unwrap()
+}
+
+/// 逐步缩小范围找出内容相同的文件组，每组按修改时间从旧到新排列，组按可释放的空间从大到小排列
+fn find_duplicates(candidates: Vec<Candidate>) -> Vec<Vec<Candidate>> {
+    let mut by_size: HashMap<u64, Vec<Candidate>> = HashMap::new();
+    for candidate in candidates {
+        by_size.entry(candidate.size).or_default().push(candidate);
+    }
+    let groups: Vec<Vec<Candidate>> = by_size.into_values().filter(|g| g.len() > 1).collect();
+
+    // 部分哈希对小文件就是完整的哈希，只有大文件还需要再比较一次
+    let groups = regroup(groups, partial_hash);
+    let (small, large): (Vec<_>, Vec<_>) = groups
+        .into_iter()
+        .partition(|group| group[0].size <= PARTIAL_BLOCK * 2);
+    let mut groups = small;
+    groups.extend(regroup(large, full_hash));
+
+    for group in &mut groups {
+        group.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.path.cmp(&b.path)));
+    }
+    groups.sort_by(|a, b| {
+        reclaimable(b)
+            .cmp(&reclaimable(a))
+            .then(a[0].path.cmp(&b[0].path))
+    });
+    groups
+}
+
+/// 删除一组中多余的文件后可以释放的空间
+fn reclaimable(group: &[Candidate]) -> u64 {
+    group[0].size * (group.len() as u64 - 1)
+}
+
+/// 计算每个文件的哈希，在组内按哈希重新分组，只保留有多个文件的组
+/// 读取失败的文件（如没有权限）会被跳过
+fn regroup(
+    groups: Vec<Vec<Candidate>>,
+    hash: fn(&Path, u64) -> io::Result<blake3::Hash>,
+) -> Vec<Vec<Candidate>> {
+    let files: Vec<Candidate> = groups.into_iter().flatten().collect();
+    let hashed = parallel_map(&files, |file| match hash(&file.path, file.size) {
+        Ok(h) => Some(h),
+        Err(e) => {
+            eprintln!("{}: {}", file.path.display(), e);
+            None
+        }
+    });
+
+    let mut by_hash: HashMap<(u64, blake3::Hash), Vec<Candidate>> = HashMap::new();
+    for (i, h) in hashed {
+        by_hash
+            .entry((files[i].size, h))
+            .or_default()
+            .push(files[i].clone());
+    }
+    by_hash.into_values().filter(|g| g.len() > 1).collect()
+}
+
+/// 用与CPU核数相同的线程对每一项执行f，返回（下标，结果），f返回None的项被丢弃，结果的顺序不固定
+pub(super) fn parallel_map<T: Sync, R: Send, F: Fn(&T) -> Option<R> + Sync>(
+    items: &[T],
+    f: F,
+) -> Vec<(usize, R)> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    let jobs = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    if let Some(r) = f(item) {
+                        results.lock().unwrap().push((i, r));
+                    }
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+/// 文件头和文件尾各PARTIAL_BLOCK字节的哈希，不超过两倍大小的文件读取全部内容
+fn partial_hash(path: &Path, size: u64) -> io::Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    if size <= PARTIAL_BLOCK * 2 {
+        hasher.update_reader(&mut file)?;
+        return Ok(hasher.finalize());
+    }
+    let mut block = vec![0; PARTIAL_BLOCK as usize];
+    file.read_exact(&mut block)?;
+    hasher.update(&block);
+    file.seek(SeekFrom::End(-(PARTIAL_BLOCK as i64)))?;
+    file.read_exact(&mut block)?;
+    hasher.update(&block);
+    Ok(hasher.finalize())
+}
+
+fn full_hash(path: &Path, _size: u64) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize())
+}
+
+pub(super) fn format_time(time: SystemTime) -> String {
+    let time: DateTime<Local> = time.into();
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn print_groups(groups: &[Vec<Candidate>]) {
+    let mut total_files = 0;
+    let mut total_reclaimable = 0;
+    for (i, group) in groups.iter().enumerate() {
+        println!(
+            "{} {}个文件，每个{}，可释放{}",
+            format!("[{}]", i + 1).bold(),
+            group.len(),
+            format_size(group[0].size),
+            format_size(reclaimable(group)).green()
+        );
+        for candidate in group {
+            println!(
+                "    {}  {}",
+                format_time(candidate.modified).dimmed(),
+                candidate.path.display()
+            );
+        }
+        total_files += group.len() - 1;
+        total_reclaimable += reclaimable(group);
+    }
+    println!(
+        "共{}组重复文件，{}个多余的文件，可释放{}",
+        groups.len(),
+        total_files,
+        format_size(total_reclaimable).green()
+    );
+}
+
+/// 预览并确认后执行处理，每一项为（要处理的文件，保留的文件）
+/// same_content为true时两个文件的内容应当完全相同，处理前会重新计算哈希确认
+pub(super) fn apply_action(
+    plan: &[(&Candidate, &Candidate)],
+    action: &DupesAction,
+    same_content: bool,
+) -> Result<()> {
+    println!("\n{}:", action.name());
+    for (file, kept) in plan {
+        let target = match action {
+            DupesAction::Quarantine(dir) => dir.display().to_string(),
+            _ => kept.path.display().to_string(),
+        };
+        println!(
+            "{} -> {}",
+            file.path.display().to_string().red(),
+            target.green()
+        );
+    }
+    if !wait_for_yes_no(action.name()) {
+        return Ok(());
+    }
+    if let DupesAction::Quarantine(dir) = action {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut failed = 0;
+    for (file, kept) in plan {
+        if let Err(e) = apply_one(file, kept, action, same_content) {
+            println!("{}失败: {}: {}", action.name(), file.path.display(), e);
+            failed += 1;
+        }
+    }
+    println!("已处理{}个文件，{}个失败", plan.len() - failed, failed);
+    Ok(())
+}
+
+fn apply_one(
+    file: &Candidate,
+    kept: &Candidate,
+    action: &DupesAction,
+    same_content: bool,
+) -> Result<()> {
+    // 查找之后要处理的文件或保留的文件被修改、删除过时不处理，避免删掉唯一的一份
+    if !is_unchanged(file) {
+        return Err(anyhow!("文件在查找之后被修改过，已跳过"));
+    }
+    if !is_unchanged(kept) {
+        return Err(anyhow!(
+            "保留的文件{}在查找之后被修改或删除过，已跳过",
+            kept.path.display()
+        ));
+    }
+    if same_content && full_hash(&file.path, file.size)? != full_hash(&kept.path, kept.size)? {
+        return Err(anyhow!("与保留的文件内容不再相同，已跳过"));
+    }
+    match action {
+        DupesAction::Delete => fs::remove_file(&file.path)?,
+        DupesAction::Hardlink => {
+            replace_with(&file.path, |tmp| fs::hard_link(&kept.path, tmp))?;
+        }
+        DupesAction::Symlink => {
+            let target = fs::canonicalize(&kept.path)?;
+            replace_with(&file.path, |tmp| std::os::unix::fs::symlink(&target, tmp))?;
+        }
+        DupesAction::Quarantine(dir) => {
+            let name = file.path.file_name().unwrap_or_default();
+            let mut dest = dir.join(name);
+            let mut n = 1;
+            while dest.symlink_metadata().is_ok() {
+                dest = dir.join(format!("{}.{}", name.to_string_lossy(), n));
+                n += 1;
+            }
+            // 跨文件系统时不能直接改名，先复制再删除
+            if fs::rename(&file.path, &dest).is_err() {
+                copy_preserving(&file.path, &dest)?;
+                fs::remove_file(&file.path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 文件是否还存在，并且大小和修改时间与查找时相同
+fn is_unchanged(file: &Candidate) -> bool {
+    file.path.symlink_metadata().is_ok_and(|metadata| {
+        metadata.len() == file.size && metadata.modified().ok() == Some(file.modified)
+    })
+}
+
+/// 复制文件并保留权限、访问时间和修改时间，失败时删除复制了一半的文件
+fn copy_preserving(from: &Path, to: &Path) -> Result<()> {
+    let copied = fs::copy(from, to).and_then(|_| {
+        let metadata = from.metadata()?;
+        fs::set_permissions(to, metadata.permissions())?;
+        filetime::set_file_times(
+            to,
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_last_modification_time(&metadata),
+        )
+    });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(to);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 先在同一目录下创建临时的链接，再改名覆盖原文件，中途失败时原文件不受影响
+fn replace_with<F: Fn(&Path) -> io::Result<()>>(path: &Path, create: F) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.rt-dupes", name));
+    create(&tmp)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use crate::dupes::{Candidate, DupesAction, apply_action, format_time, parallel_map};
+use crate::ls::format_size;
+use crate::utils::utils::check_file_type;
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use image::DynamicImage;
+use image::imageops::FilterType;
+use std::f64::consts::PI;
+
+/// 感知哈希算法，都生成64位的哈希
+#[derive(Debug, Clone, Copy)]
+pub enum HashAlgorithm {
+    /// 平均哈希：缩小到8x8，每个像素与平均值比较
+    Average,
+    /// 差异哈希：缩小到9x8，每个像素与右边的像素比较
+    Difference,
+    /// 感知哈希：缩小到32x32，取DCT的左上角8x8低频系数与中位数比较
+    Perceptual,
+}
+
+impl HashAlgorithm {
+    pub fn parse(hash: &str) -> Result<Self> {
+        match hash {
+            "ahash" => Ok(HashAlgorithm::Average),
+            "dhash" => Ok(HashAlgorithm::Difference),
+            "phash" => Ok(HashAlgorithm::Perceptual),
+            _ => Err(anyhow!("Invalid hash algorithm: {}", hash)),
+        }
+    }
+
+    fn hash(&self, image: &DynamicImage) -> u64 {
+        match self {
+            HashAlgorithm::Average => average_hash(image),
+            HashAlgorithm::Difference => difference_hash(image),
+            HashAlgorithm::Perceptual => perceptual_hash(image),
+        }
+    }
+}
+
+/// 一张参与比较的图片
+struct SimilarImage {
+    file: Candidate,
+    width: u32,
+    height: u32,
+    hash: u64,
+}
+
+impl SimilarImage {
+    fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// 用感知哈希查找相似的图片：只比较按文件类型属于image的文件
+/// 每组以要保留的图片为中心，组中其他图片与它的汉明距离都不超过threshold
+/// 组按删除多余图片后可释放的空间从大到小排列
+pub(super) fn similar_images_command(
+    candidates: Vec<Candidate>,
+    algorithm: HashAlgorithm,
+    threshold: u32,
+    action: Option<DupesAction>,
+    keep: &str,
+) -> Result<()> {
+    if matches!(
+        action,
+        Some(DupesAction::Hardlink) | Some(DupesAction::Symlink)
+    ) {
+        return Err(anyhow!(
+            "相似的图片内容并不相同，--similar-images只支持delete和quarantine"
+        ));
+    }
+
+    let candidates: Vec<Candidate> = candidates
+        .into_iter()
+        .filter(|c| check_file_type(&c.path, "image").unwrap_or(false))
+        .collect();
+    // 无法解码的图片（如svg、heic和相机的raw格式）被跳过
+    let decoded = parallel_map(&candidates, |file| {
+        let image = image::ImageReader::open(&file.path)
+            .ok()?
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()?;
+        Some((image.width(), image.height(), algorithm.hash(&image)))
+    });
+    let skipped = candidates.len() - decoded.len();
+    let mut images: Vec<SimilarImage> = decoded
+        .into_iter()
+        .map(|(i, (width, height, hash))| SimilarImage {
+            file: candidates[i].clone(),
+            width,
+            height,
+            hash,
+        })
+        .collect();
+    images.sort_by(|a, b| a.file.path.cmp(&b.file.path));
+
+    let clusters = cluster(images, threshold, keep);
+    if skipped > 0 {
+        println!("{}个图片无法解码，已跳过", skipped);
+    }
+    if clusters.is_empty() {
+        println!("没有找到相似的图片");
+        return Ok(());
+    }
+    print_clusters(&clusters);
+
+    let Some(action) = action else {
+        return Ok(());
+    };
+    // 每组的第一张是保留的图片，其他图片都与它相似
+    let plan: Vec<(&Candidate, &Candidate)> = clusters
+        .iter()
+        .flat_map(|cluster| {
+            cluster[1..]
+                .iter()
+                .map(|image| (&image.file, &cluster[0].file))
+        })
+        .collect();
+    apply_action(&plan, &action, false)
+}
+
+/// 按保留的优先顺序依次取出还没有分组的图片作为中心，把与它的汉明距离不超过threshold的图片归为一组
+/// 不按相似关系传递地连成组，组中每张图片都与保留的图片相似，只保留有多张图片的组
+/// 每组的第一张是保留的图片，其余按分辨率从高到低排列
+fn cluster(mut images: Vec<SimilarImage>, threshold: u32, keep: &str) -> Vec<Vec<SimilarImage>> {
+    let by_resolution = |a: &SimilarImage, b: &SimilarImage| {
+        b.pixels()
+            .cmp(&a.pixels())
+            .then(b.file.size.cmp(&a.file.size))
+            .then(a.file.path.cmp(&b.file.path))
+    };
+    match keep {
+        "oldest" => images.sort_by(|a, b| {
+            a.file
+                .modified
+                .cmp(&b.file.modified)
+                .then_with(|| by_resolution(a, b))
+        }),
+        "newest" => images.sort_by(|a, b| {
+            b.file
+                .modified
+                .cmp(&a.file.modified)
+                .then_with(|| by_resolution(a, b))
+        }),
+        _ => images.sort_by(by_resolution),
+    }
+
+    let mut assigned = vec![false; images.len()];
+    let mut members: Vec<Vec<usize>> = Vec::new();
+    for i in 0..images.len() {
+        if assigned[i] {
+            continue;
+        }
+        assigned[i] = true;
+        let mut cluster = vec![i];
+        for j in i + 1..images.len() {
+            if !assigned[j] && (images[i].hash ^ images[j].hash).count_ones() <= threshold {
+                assigned[j] = true;
+                cluster.push(j);
+            }
+        }
+        if cluster.len() > 1 {
+            members.push(cluster);
+        }
+    }
+
+    let mut images: Vec<Option<SimilarImage>> = images.into_iter().map(Some).collect();
+    let mut clusters: Vec<Vec<SimilarImage>> = members
+        .into_iter()
+        .map(|cluster| {
+            cluster
+                .into_iter()
+                .map(|i| images[i].take().unwrap())
+                .collect()
+        })
+        .collect();
+    for cluster in &mut clusters {
+        cluster[1..].sort_by(by_resolution);
+    }
+    clusters.sort_by(|a, b| {
+        reclaimable(b)
+            .cmp(&reclaimable(a))
+            .then(a[0].file.path.cmp(&b[0].file.path))
+    });
+    clusters
+}
+
+/// 只保留每组第一张图片时可以释放的空间
+fn reclaimable(cluster: &[SimilarImage]) -> u64 {
+    cluster[1..].iter().map(|image| image.file.size).sum()
+}
+
+fn print_clusters(clusters: &[Vec<SimilarImage>]) {
+    let mut total_files = 0;
+    let mut total_reclaimable = 0;
+    for (i, cluster) in clusters.iter().enumerate() {
+        println!(
+            "{} {}张相似的图片，可释放{}",
+            format!("[{}]", i + 1).bold(),
+            cluster.len(),
+            format_size(reclaimable(cluster)).green()
+        );
+        // 第一张是保留的图片，距离是与它的汉明距离
+        for image in cluster {
+            println!(
+                "    {:>11}  {:>9}  {}  距离{:<2}  {}",
+                format!("{}x{}", image.width, image.height),
+                format_size(image.file.size),
+                format_time(image.file.modified).dimmed(),
+                (image.hash ^ cluster[0].hash).count_ones(),
+                image.file.path.display()
+            );
+        }
+        total_files += cluster.len() - 1;
+        total_reclaimable += reclaimable(cluster);
+    }
+    println!(
+        "共{}组相似的图片，{}个多余的图片，可释放{}",
+        clusters.len(),
+        total_files,
+        format_size(total_reclaimable).green()
+    );
+}
+
+/// 缩小为width x height的灰度图，按行排列
+fn shrink(image: &DynamicImage, width: u32, height: u32) -> Vec<f64> {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+        .pixels()
+        .map(|p| p.0[0] as f64)
+        .collect()
+}
+
+/// 把64个布尔值按顺序放进u64的各个位
+fn to_bits<I: Iterator<Item = bool>>(bits: I) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | bit as u64)
+}
+
+fn average_hash(image: &DynamicImage) -> u64 {
+    let pixels = shrink(image, 8, 8);
+    let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+    to_bits(pixels.iter().map(|&p| p > mean))
+}
+
+fn difference_hash(image: &DynamicImage) -> u64 {
+    let pixels = shrink(image, 9, 8);
+    to_bits((0..8).flat_map(|y| {
+        let row = &pixels[y * 9..y * 9 + 9];
+        (0..8).map(move |x| row[x] < row[x + 1])
+    }))
+}
+
+fn perceptual_hash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+    let pixels = shrink(image, SIZE as u32, SIZE as u32);
+    // 只需要低频部分，分别对行和列做一维DCT-II
+    let cos: Vec<f64> = (0..LOW * SIZE)
+        .map(|i| {
+            let (u, x) = (i / SIZE, i % SIZE);
+            ((2 * x + 1) as f64 * u as f64 * PI / (2 * SIZE) as f64).cos()
+        })
+        .collect();
+    let mut rows = vec![0.0; SIZE * LOW];
+    for y in 0..SIZE {
+        for u in 0..LOW {
+            rows[y * LOW + u] = (0..SIZE)
+                .map(|x| pixels[y * SIZE + x] * cos[u * SIZE + x])
+                .sum();
+        }
+    }
+    let mut coefficients = vec![0.0; LOW * LOW];
+    for v in 0..LOW {
+        for u in 0..LOW {
+            coefficients[v * LOW + u] = (0..SIZE)
+                .map(|y| rows[y * LOW + u] * cos[v * SIZE + y])
+                .sum();
+        }
+    }
+    // 直流分量只反映整体亮度，不参与中位数的计算
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    to_bits(coefficients.iter().map(|&c| c > median))
+}
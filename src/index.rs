@@ -0,0 +1,477 @@
+use crate::search::reader::{is_binary, read_file};
+use crate::utils::utils::FileInfo;
+use crate::walk::RT_IGNORE_FILENAME;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// 索引文件格式的版本，格式变化后旧的索引需要重新建立
+const INDEX_VERSION: u32 = 2;
+/// 超过这个大小的文件不建立三元组索引
+const TRIGRAM_MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// 与search遍历目录时相同的忽略文件
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", RT_IGNORE_FILENAME];
+
+/// 一个目录树的索引：所有路径以及筛选条件用到的元数据
+#[derive(Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    /// 建立索引的根目录（绝对路径）
+    pub root: PathBuf,
+    /// 建立索引时是否包含隐藏文件
+    pub hidden: bool,
+    /// 建立索引时是否忽略.gitignore等规则
+    pub no_ignore: bool,
+    /// 是否为文件内容建立了三元组索引
+    pub trigram: bool,
+    pub updated_at: SystemTime,
+    /// 目录相对根目录的路径 -> 目录中的条目，根目录本身的路径为空
+    pub dirs: BTreeMap<PathBuf, IndexedDir>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedDir {
+    /// 读取目录内容时目录的修改时间，没有变化时更新索引会直接复用目录中的条目
+    mtime: Option<SystemTime>,
+    /// 按名称排序
+    pub entries: Vec<IndexedEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedEntry {
+    pub name: OsString,
+    pub info: FileInfo,
+    /// 文件内容中出现过的三元组，排序后按差值用变长整数编码
+    /// 为None表示没有建立（目录、二进制文件、过大或非UTF-8的文件），搜索时不能据此排除该文件
+    trigrams: Option<Vec<u8>>,
+}
+
+impl IndexedEntry {
+    /// 文件的大小和修改时间是否与建立索引时记录的一致，不一致时三元组已经过期
+    pub fn is_fresh(&self, path: &Path) -> bool {
+        fs::metadata(path).is_ok_and(|metadata| {
+            metadata.len() == self.info.size && metadata.modified().ok() == self.info.modified
+        })
+    }
+
+    /// 文件内容是否可能包含全部三元组，没有三元组索引时总是返回true
+    /// 只反映建立索引时的内容，调用前需要用is_fresh确认文件没有被修改过
+    pub fn may_contain(&self, trigrams: &[u32]) -> bool {
+        let Some(encoded) = &self.trigrams else {
+            return true;
+        };
+        let mut present = decode_trigrams(encoded).into_iter().peekable();
+        trigrams.iter().all(|t| {
+            while present.next_if(|p| p < t).is_some() {}
+            present.peek() == Some(t)
+        })
+    }
+}
+
+/// 建立索引，已有的索引会被覆盖
+pub fn build_index(path: &str, trigram: bool, hidden: bool, no_ignore: bool) -> Result<()> {
+    let root = fs::canonicalize(path)?;
+    let start = Instant::now();
+    let mut scanner = Scanner::new(hidden, no_ignore, trigram, BTreeMap::new());
+    scanner.scan(&root, Path::new(""));
+    let index = Index {
+        version: INDEX_VERSION,
+        root,
+        hidden,
+        no_ignore,
+        trigram,
+        updated_at: SystemTime::now(),
+        dirs: scanner.dirs,
+    };
+    index.save()?;
+    println!(
+        "已建立索引：{}，{}个目录，{}个文件，用时{:.2}s",
+        index.root.display(),
+        index.dirs.len(),
+        index.file_count(),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// 增量更新索引：只重新读取修改时间变化了的目录
+/// 目录的修改时间只在其中的条目增删、改名时变化，复用的目录中大小或修改时间变化了的文件会单独重新读取
+pub fn update_index(path: &str) -> Result<()> {
+    let index = find_index(Path::new(path))?.ok_or_else(|| no_index_error(path))?;
+    let start = Instant::now();
+    let mut scanner = Scanner::new(index.hidden, index.no_ignore, index.trigram, index.dirs);
+    scanner.scan(&index.root, Path::new(""));
+    let index = Index {
+        updated_at: SystemTime::now(),
+        dirs: scanner.dirs,
+        ..index
+    };
+    index.save()?;
+    println!(
+        "已更新索引：{}，重新读取{}个目录，复用{}个目录，用时{:.2}s",
+        index.root.display(),
+        scanner.scanned,
+        scanner.reused,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// 显示索引的状态，以及自上次更新后有变化的目录数量
+pub fn index_status(path: &str) -> Result<()> {
+    let index = find_index(Path::new(path))?.ok_or_else(|| no_index_error(path))?;
+    let index_file = index_path(&index.root)?;
+    let size = fs::metadata(&index_file)?.len();
+    let changed = index
+        .dirs
+        .iter()
+        .filter(|(rel, dir)| {
+            let mtime = fs::metadata(index.root.join(rel))
+                .and_then(|m| m.modified())
+                .ok();
+            mtime.is_none() || mtime != dir.mtime
+        })
+        .count();
+    let updated_at: DateTime<Local> = index.updated_at.into();
+    println!("索引目录：{}", index.root.display());
+    println!("索引文件：{}（{}字节）", index_file.display(), size);
+    println!("更新时间：{}", updated_at.format("%Y-%m-%d %H:%M:%S"));
+    println!("目录：{}，文件：{}", index.dirs.len(), index.file_count());
+    println!(
+        "隐藏文件：{}，忽略规则：{}，三元组索引：{}",
+        if index.hidden { "包含" } else { "不包含" },
+        if index.no_ignore {
+            "不使用"
+        } else {
+            "使用"
+        },
+        if index.trigram { "有" } else { "无" }
+    );
+    if changed > 0 {
+        println!(
+            "有{}个目录在上次更新后发生了变化，可以运行rt index update",
+            changed
+        );
+    } else {
+        println!("索引是最新的");
+    }
+    Ok(())
+}
+
+fn no_index_error(path: &str) -> anyhow::Error {
+    anyhow!("{} 没有索引，请先运行 rt index build", path)
+}
+
+/// 查找包含path的索引：从path开始逐级向上查找建立过索引的目录
+pub fn find_index(path: &Path) -> Result<Option<Index>> {
+    let path = fs::canonicalize(path)?;
+    for dir in path.ancestors() {
+        let index_file = index_path(dir)?;
+        if index_file.exists() {
+            let index = Index::load(&index_file)?;
+            if index.root == dir {
+                return Ok(Some(index));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// 索引保存在可执行文件所在目录的index目录下，文件名由根目录路径的哈希值决定
+fn index_path(root: &Path) -> Result<PathBuf> {
+    let exe_path = std::env::current_exe()?;
+    let exe_dir = exe_path
+        .parent()
+        .ok_or(anyhow!("Failed to get executable directory"))?;
+    // FNV-1a，结果在不同版本的Rust之间保持稳定
+    let hash = root
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+    Ok(exe_dir.join("index").join(format!("{:016x}.bin", hash)))
+}
+
+impl Index {
+    fn load(index_file: &Path) -> Result<Self> {
+        let reader = BufReader::new(File::open(index_file)?);
+        let index: Index = bincode::deserialize_from(reader).map_err(|_| {
+            anyhow!(
+                "索引文件 {} 已损坏，请重新运行 rt index build",
+                index_file.display()
+            )
+        })?;
+        if index.version != INDEX_VERSION {
+            return Err(anyhow!("索引格式已过时，请重新运行 rt index build"));
+        }
+        Ok(index)
+    }
+
+    /// 先写入临时文件再改名，写入中途出错时不会破坏原有的索引
+    fn save(&self) -> Result<()> {
+        let index_file = index_path(&self.root)?;
+        if let Some(parent) = index_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_file = index_file.with_extension(format!("tmp{}", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&temp_file)?);
+        bincode::serialize_into(&mut writer, self)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temp_file, &index_file)?;
+        Ok(())
+    }
+
+    fn file_count(&self) -> usize {
+        self.dirs
+            .values()
+            .flat_map(|dir| &dir.entries)
+            .filter(|entry| !entry.info.is_dir)
+            .count()
+    }
+
+    /// 逐个目录列出base之下的条目，返回相对base的路径和深度（base中的条目深度为1）
+    pub fn entries_under(&self, base: &Path) -> Vec<(PathBuf, usize, &IndexedEntry)> {
+        let Ok(base) = base.strip_prefix(&self.root) else {
+            return Vec::new();
+        };
+        let base_depth = base.components().count();
+        // 路径按组成部分比较，base之下的目录在BTreeMap中是连续的
+        self.dirs
+            .range(base.to_path_buf()..)
+            .take_while(|(rel, _)| rel.starts_with(base))
+            .flat_map(|(rel, dir)| {
+                let depth = rel.components().count() - base_depth + 1;
+                let rel = rel.strip_prefix(base).unwrap_or(rel);
+                dir.entries
+                    .iter()
+                    .map(move |entry| (rel.join(&entry.name), depth, entry))
+            })
+            .collect()
+    }
+}
+
+/// 遍历目录树建立索引，old中修改时间没有变化的目录直接复用
+struct Scanner {
+    hidden: bool,
+    no_ignore: bool,
+    trigram: bool,
+    old: BTreeMap<PathBuf, IndexedDir>,
+    dirs: BTreeMap<PathBuf, IndexedDir>,
+    /// 从根目录到当前目录的忽略规则
+    ignores: Vec<Gitignore>,
+    scanned: usize,
+    reused: usize,
+}
+
+impl Scanner {
+    fn new(
+        hidden: bool,
+        no_ignore: bool,
+        trigram: bool,
+        old: BTreeMap<PathBuf, IndexedDir>,
+    ) -> Self {
+        Scanner {
+            hidden,
+            no_ignore,
+            trigram,
+            old,
+            dirs: BTreeMap::new(),
+            ignores: Vec::new(),
+            scanned: 0,
+            reused: 0,
+        }
+    }
+
+    fn scan(&mut self, root: &Path, rel: &Path) {
+        let dir = root.join(rel);
+        let mtime = fs::metadata(&dir).and_then(|m| m.modified()).ok();
+
+        let has_ignore = !self.no_ignore && self.push_ignore(&dir);
+        let entries = match self.old.remove(rel) {
+            Some(old) if mtime.is_some() && old.mtime == mtime => {
+                self.reused += 1;
+                self.refresh(&dir, old.entries)
+            }
+            _ => {
+                self.scanned += 1;
+                self.read_dir(&dir)
+            }
+        };
+        // 不进入符号链接指向的目录，避免循环
+        let subdirs: Vec<PathBuf> = entries
+            .iter()
+            .filter(|entry| entry.info.is_dir && !entry.info.is_symlink)
+            .map(|entry| rel.join(&entry.name))
+            .collect();
+        for subdir in subdirs {
+            self.scan(root, &subdir);
+        }
+        if has_ignore {
+            self.ignores.pop();
+        }
+        self.dirs
+            .insert(rel.to_path_buf(), IndexedDir { mtime, entries });
+    }
+
+    /// 读取目录中的忽略文件，有规则时加入ignores
+    fn push_ignore(&mut self, dir: &Path) -> bool {
+        let mut builder = GitignoreBuilder::new(dir);
+        for name in IGNORE_FILES {
+            let file = dir.join(name);
+            if file.is_file() {
+                builder.add(file);
+            }
+        }
+        match builder.build() {
+            Ok(gitignore) if !gitignore.is_empty() => {
+                self.ignores.push(gitignore);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 离得越近的忽略文件优先级越高
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in self.ignores.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// 重新读取复用的目录中内容被修改过的文件，更新元数据和三元组
+    fn refresh(&self, dir: &Path, entries: Vec<IndexedEntry>) -> Vec<IndexedEntry> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                let path = dir.join(&entry.name);
+                if entry.info.is_dir || entry.is_fresh(&path) {
+                    return entry;
+                }
+                match FileInfo::from_path(&path) {
+                    Ok(info) => IndexedEntry {
+                        trigrams: self.trigrams(&path, &info),
+                        info,
+                        ..entry
+                    },
+                    Err(_) => entry,
+                }
+            })
+            .collect()
+    }
+
+    fn trigrams(&self, path: &Path, info: &FileInfo) -> Option<Vec<u8>> {
+        if self.trigram && !info.is_dir && info.size <= TRIGRAM_MAX_FILE_SIZE {
+            file_trigrams(path)
+        } else {
+            None
+        }
+    }
+
+    fn read_dir(&self, dir: &Path) -> Vec<IndexedEntry> {
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut entries: Vec<IndexedEntry> = read_dir
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                if !self.hidden && name.as_encoded_bytes().starts_with(b".") {
+                    return None;
+                }
+                let path = entry.path();
+                let info = FileInfo::from_path(&path).ok()?;
+                if self.is_ignored(&path, info.is_dir) {
+                    return None;
+                }
+                let trigrams = self.trigrams(&path, &info);
+                Some(IndexedEntry {
+                    name,
+                    info,
+                    trigrams,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+}
+
+/// 文件内容的三元组，只处理UTF-8文本文件
+fn file_trigrams(path: &Path) -> Option<Vec<u8>> {
+    let bytes = read_file(path).ok()?;
+    if is_binary(&bytes) || std::str::from_utf8(&bytes).is_err() {
+        return None;
+    }
+    Some(encode_trigrams(&trigrams_of(&bytes)))
+}
+
+/// 内容中出现过的所有三元组（连续的3个字节，ASCII字母转为小写），已排序去重
+pub fn trigrams_of(bytes: &[u8]) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = bytes
+        .windows(3)
+        .map(|w| {
+            let [a, b, c] = [w[0], w[1], w[2]].map(|b| b.to_ascii_lowercase() as u32);
+            (a << 16) | (b << 8) | c
+        })
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// 把有序的三元组编码为相邻两项差值的LEB128变长整数
+fn encode_trigrams(trigrams: &[u32]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(trigrams.len() * 2);
+    let mut last = 0;
+    for &t in trigrams {
+        let mut delta = t - last;
+        last = t;
+        loop {
+            let byte = (delta & 0x7f) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                encoded.push(byte);
+                break;
+            }
+            encoded.push(byte | 0x80);
+        }
+    }
+    encoded
+}
+
+fn decode_trigrams(encoded: &[u8]) -> Vec<u32> {
+    let mut trigrams = Vec::with_capacity(encoded.len());
+    let mut last = 0;
+    let mut delta = 0;
+    let mut shift = 0;
+    for &byte in encoded {
+        delta |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            last += delta;
+            trigrams.push(last);
+            delta = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+        }
+    }
+    trigrams
+}
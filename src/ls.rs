@@ -1,68 +1,119 @@
+use crate::du::{TreeSizes, UsageCache, compute_usage};
+use crate::utils::query::Query;
 use crate::utils::utils::get_extension;
+use crate::walk::{WalkOptions, list_dir};
 use chrono::DateTime;
 use chrono::Local;
 use colored::{Color, Colorize};
 // use fancy_regex::Regex;
 // use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-pub fn ls_command(
-    directory: PathBuf,
-    author: bool,
-    long: bool,
-    all: bool,
-    recursive: bool,
-    human_readable: bool,
-    time_sort: bool,
-    reverse: bool,
-    hyperlink: bool,
-    inode: bool,
-    ext_sort: bool,
-    size_sort: bool,
-    directories_only: bool,
-    files_only: bool,
-    color: bool,
-    differentiated: bool,
-    header: bool,
-    custom_show: &Vec<String>,
-    show_full_path: bool,
-    tree: usize,
-    max_tree_lines: usize,
-) {
+/// ls的显示选项，与命令行参数一一对应
+pub struct LsOptions {
+    pub author: bool,
+    pub long: bool,
+    pub all: bool,
+    pub recursive: bool,
+    pub human_readable: bool,
+    pub time_sort: bool,
+    pub reverse: bool,
+    pub hyperlink: bool,
+    pub inode: bool,
+    pub ext_sort: bool,
+    pub size_sort: bool,
+    pub directories_only: bool,
+    pub files_only: bool,
+    pub color: bool,
+    pub differentiated: bool,
+    pub header: bool,
+    pub custom_show: Vec<String>,
+    pub show_full_path: bool,
+    /// 大于0时以文件树显示，值为树的深度
+    pub tree: usize,
+    /// 文件树每个目录最多显示的条目数
+    pub max_tree_lines: usize,
+    /// 按.gitignore、.ignore和.rtignore规则过滤条目
+    pub git_ignore: bool,
+    /// 筛选表达式
+    pub where_expr: Option<String>,
+    /// 统计目录中所有文件的大小
+    pub dir_size: bool,
+}
+
+pub fn ls_command(directory: PathBuf, options: LsOptions) {
+    let LsOptions {
+        all,
+        hyperlink,
+        color,
+        ref custom_show,
+        tree,
+        max_tree_lines,
+        git_ignore,
+        ref where_expr,
+        dir_size,
+        ..
+    } = options;
+    // 筛选表达式只解析一次，格式错误时直接报错
+    let query = match Query::from_options(&[], where_expr) {
+        Ok(query) => query,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    // 开启git_ignore时按.gitignore、.ignore和.rtignore规则过滤条目
+    let ignore = if git_ignore {
+        Some(WalkOptions {
+            hidden: all || tree > 0,
+            ..WalkOptions::new()
+        })
+    } else {
+        None
+    };
+    // 显示item_count或total_size列时也需要统计目录
+    let dir_size = dir_size
+        || custom_show
+            .iter()
+            .any(|name| name == "item_count" || name == "total_size");
+    let dir_sizes = if dir_size {
+        Some(UsageCache::new())
+    } else {
+        None
+    };
     if tree == 0 {
         list_directory(
             directory,
-            author,
-            long,
-            all,
-            recursive,
-            human_readable,
-            time_sort,
-            reverse,
-            hyperlink,
-            inode,
-            ext_sort,
-            size_sort,
-            directories_only,
-            files_only,
-            color,
-            differentiated,
-            header,
-            custom_show,
-            show_full_path,
+            &options,
+            ignore.as_ref(),
+            query.as_ref(),
+            dir_sizes.as_ref(),
         );
     } else {
+        // 文件树只需要遍历一次根目录
+        let usage = dir_sizes.map(|cache| {
+            compute_usage(directory.as_path(), cache.options(), None)
+        });
+        let sizes = usage
+            .as_ref()
+            .map(|usage| TreeSizes::new(usage, directory.as_path(), true, false));
         print_file_tree(
             directory.as_path(),
-            tree,
-            color,
-            max_tree_lines,
-            hyperlink,
+            &TreeOptions {
+                max_depth: tree,
+                color,
+                max_items_per_dir: max_tree_lines,
+                hyperlink,
+                ignore: ignore.as_ref(),
+                query: query.as_ref(),
+                sizes: sizes.as_ref(),
+            },
         );
     }
 }
@@ -72,6 +123,9 @@ pub fn ls_command(
 struct ColorConfig {
     file_ext_colors: Vec<(String, String)>,
     special_dir_colors: Vec<(String, String)>,
+    /// 扩展-t/--file-type的类型表，由utils读取，这里只负责在保存配置时保留
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    file_types: serde_json::Map<String, serde_json::Value>,
 }
 
 impl ColorConfig {
@@ -142,6 +196,7 @@ impl ColorConfig {
                 ("draft".to_string(), "#DEB887".to_string()),    // 实木色
                 ("unfinished".to_string(), "#F4A460".to_string()), // 沙棕色
             ],
+            file_types: serde_json::Map::new(),
         }
     }
     fn get_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
@@ -210,7 +265,7 @@ impl ColorConfig {
 }
 
 /// 将字节数转换为人类可读的格式
-fn format_size(bytes: u64) -> String {
+pub fn format_size(bytes: u64) -> String {
     const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
     let mut size = bytes as f64;
     let mut unit_index = 0;
@@ -319,25 +374,32 @@ fn apply_color(
 
 fn list_directory(
     directory: PathBuf,
-    author: bool,
-    long: bool,
-    all: bool,
-    recursive: bool,
-    human_readable: bool,
-    time_sort: bool,
-    reverse: bool,
-    hyperlink: bool,
-    inode: bool,
-    ext_sort: bool,
-    size_sort: bool,
-    directories_only: bool,
-    files_only: bool,
-    color: bool,
-    differentiated: bool,
-    header: bool,
-    custom_show: &Vec<String>,
-    show_full_path: bool,
+    options: &LsOptions,
+    ignore: Option<&WalkOptions>,
+    query: Option<&Query>,
+    dir_sizes: Option<&UsageCache>,
 ) {
+    let LsOptions {
+        author,
+        long,
+        all,
+        recursive,
+        human_readable,
+        time_sort,
+        reverse,
+        hyperlink,
+        inode,
+        ext_sort,
+        size_sort,
+        directories_only,
+        files_only,
+        color,
+        differentiated,
+        header,
+        ref custom_show,
+        show_full_path,
+        ..
+    } = *options;
     let color_config = ColorConfig::load_from_file();
     // let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
@@ -359,6 +421,11 @@ fn list_directory(
             });
         }
 
+        if let Some(options) = ignore {
+            let allowed: HashSet<PathBuf> = list_dir(&directory, options).into_iter().collect();
+            entries.retain(|entry| allowed.contains(&entry.path()));
+        }
+
         // 先按是否为目录排序，目录在前，文件在后，再按原有规则排序
         entries.sort_by(|a, b| {
             let a_is_dir = a.path().is_dir();
@@ -384,8 +451,8 @@ fn list_directory(
                 let b_ext = get_extension(&b.path());
                 a_ext.cmp(&b_ext)
             } else if size_sort {
-                let a_size = a.metadata().ok().map(|m| m.len()).unwrap_or(0);
-                let b_size = b.metadata().ok().map(|m| m.len()).unwrap_or(0);
+                let a_size = entry_size(a, dir_sizes);
+                let b_size = entry_size(b, dir_sizes);
                 a_size.cmp(&b_size)
             } else {
                 a.file_name().cmp(&b.file_name())
@@ -404,7 +471,13 @@ fn list_directory(
             if files_only && full_path.is_dir() {
                 continue;
             }
-            file_info_manager.add_file_info(entry, human_readable, show_full_path);
+            // 筛选表达式只影响显示，-R时仍然进入不满足条件的子目录
+            if let Some(query) = query
+                && !query.matches(&full_path)
+            {
+                continue;
+            }
+            file_info_manager.add_file_info(entry, human_readable, show_full_path, dir_sizes);
         }
         if long {
             file_info_manager.set_long_show(author, inode);
@@ -426,27 +499,7 @@ fn list_directory(
                 if full_path.is_dir() {
                     // let relative_path = get_relative_path(&current_dir, &full_path);
                     println!("\n{}:", full_path.display());
-                    list_directory(
-                        full_path,
-                        author,
-                        long,
-                        all,
-                        recursive,
-                        human_readable,
-                        time_sort,
-                        reverse,
-                        hyperlink,
-                        inode,
-                        ext_sort,
-                        size_sort,
-                        directories_only,
-                        files_only,
-                        color,
-                        differentiated,
-                        header,
-                        custom_show,
-                        show_full_path,
-                    );
+                    list_directory(full_path, options, ignore, query, dir_sizes);
                 }
             }
         }
@@ -458,6 +511,17 @@ fn list_directory(
     }
 }
 
+/// 排序用的大小，开启--dir-size时目录为其中所有文件的大小之和
+fn entry_size(entry: &fs::DirEntry, dir_sizes: Option<&UsageCache>) -> u64 {
+    let Ok(metadata) = entry.metadata() else {
+        return 0;
+    };
+    match dir_sizes {
+        Some(cache) if metadata.is_dir() => cache.get(&entry.path()).apparent,
+        _ => metadata.len(),
+    }
+}
+
 /// 文件信息结构体
 #[derive(Debug)]
 struct FileInfo {
@@ -477,6 +541,10 @@ struct FileInfo {
     is_executable: bool,
     permission: std::fs::Permissions,
     group: u32,
+    /// 目录下直接包含的条目数，只在--dir-size时统计
+    item_count: Option<u64>,
+    /// 目录下所有文件的大小之和，文件为其本身的大小，只在--dir-size时统计
+    total_size: Option<String>,
 }
 
 impl FileInfo {
@@ -484,8 +552,18 @@ impl FileInfo {
         entry: &fs::DirEntry,
         human_readable: bool,
         show_full_path: bool,
+        dir_sizes: Option<&UsageCache>,
     ) -> Option<Self> {
         let metadata = entry.metadata().ok()?;
+        let (item_count, total_size) = match dir_sizes {
+            Some(cache) if metadata.is_dir() => (
+                fs::read_dir(entry.path()).ok().map(|entries| entries.count() as u64),
+                Some(cache.get(&entry.path()).apparent),
+            ),
+            Some(_) => (None, Some(metadata.len())),
+            None => (None, None),
+        };
+        let size = total_size.unwrap_or(metadata.len());
         let relative_path = entry
             .path()
             .to_string_lossy()
@@ -508,11 +586,7 @@ impl FileInfo {
         };
 
         Some(Self {
-            size: if human_readable {
-                format_size(metadata.len())
-            } else {
-                format!("{} B", metadata.len())
-            },
+            size: FileInfo::format_bytes(size, human_readable),
             file_name: file_name,
             modified: metadata.modified().ok().map_or_else(
                 || "unknown".to_string(),
@@ -534,9 +608,19 @@ impl FileInfo {
             is_executable: metadata.permissions().mode() & 0o111 != 0,
             permission: metadata.permissions(),
             group: metadata.gid(),
+            item_count,
+            total_size: total_size.map(|size| FileInfo::format_bytes(size, human_readable)),
         })
     }
 
+    fn format_bytes(size: u64, human_readable: bool) -> String {
+        if human_readable {
+            format_size(size)
+        } else {
+            format!("{} B", size)
+        }
+    }
+
     fn get_info_vec(&self) -> Vec<String> {
         let mut info_vec = Vec::new();
         info_vec.push(self.size.clone());
@@ -581,6 +665,8 @@ impl FileInfo {
                 .map(|u| u.name().to_string_lossy().into_owned())
                 .unwrap_or_else(|| self.group.to_string()),
         ); // 显示组名或回退到GID
+        info_vec.push(self.item_count.map_or_else(|| "-".to_string(), |n| n.to_string()));
+        info_vec.push(self.total_size.clone().unwrap_or_else(|| "-".to_string()));
         info_vec
     }
 
@@ -616,6 +702,9 @@ impl FileInfo {
             "mtime",
             "is_executable",
             "permission",
+            "group",
+            "item_count",
+            "total_size",
         ]
         .iter()
         .map(|s| s.to_string())
@@ -638,8 +727,16 @@ impl FileInfoManager {
         }
     }
 
-    fn add_file_info(&mut self, entry: &fs::DirEntry, human_readable: bool, show_full_path: bool) {
-        if let Some(file_info) = FileInfo::from_metadata(entry, human_readable, show_full_path) {
+    fn add_file_info(
+        &mut self,
+        entry: &fs::DirEntry,
+        human_readable: bool,
+        show_full_path: bool,
+        dir_sizes: Option<&UsageCache>,
+    ) {
+        if let Some(file_info) =
+            FileInfo::from_metadata(entry, human_readable, show_full_path, dir_sizes)
+        {
             self.file_infos.push(file_info);
             if self.show_vec.is_empty() {
                 self.show_vec = vec![false; self.file_infos[0].name_vec().len()];
@@ -845,18 +942,42 @@ impl FileInfoManager {
 }
 
 
+/// 打印文件树的选项
+pub struct TreeOptions<'a> {
+    /// 最大深度
+    pub max_depth: usize,
+    /// 按color_config.json着色
+    pub color: bool,
+    /// 每个目录最多显示的条目数，0表示不限制
+    pub max_items_per_dir: usize,
+    /// 文件名显示为超链接
+    pub hyperlink: bool,
+    /// 不为None时按.gitignore等规则过滤条目
+    pub ignore: Option<&'a WalkOptions>,
+    /// 筛选表达式，只作用于文件
+    pub query: Option<&'a Query>,
+    /// 不为None时在每一行前显示占用空间和比例条，只显示其中有记录的条目
+    pub sizes: Option<&'a TreeSizes<'a>>,
+}
+
 /// 递归打印文件树
 fn print_tree_recursive(
     path: &Path,
     current_depth: usize,
-    max_depth: usize,
-    color: bool,
-    color_config: &ColorConfig,
-    max_items_per_dir: usize,
     is_last: bool,
     parent_prefix: &str,
-    hyperlink: bool,
+    options: &TreeOptions,
+    color_config: &ColorConfig,
 ) {
+    let TreeOptions {
+        max_depth,
+        color,
+        max_items_per_dir,
+        hyperlink,
+        ignore,
+        query,
+        sizes,
+    } = *options;
     if current_depth > max_depth {
         return;
     }
@@ -864,9 +985,11 @@ fn print_tree_recursive(
     let name = if hyperlink{
         format_file_hyperlink(path)
     }else{
+        // "."和".."没有文件名，显示路径本身
         path.file_name()
         .and_then(|s| s.to_str())
-        .unwrap_or("").to_string()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| path.display().to_string())
     };
     
     let colored_name = if color {
@@ -890,17 +1013,36 @@ fn print_tree_recursive(
         name.to_string()
     };
 
+    let size_column = sizes.map(|sizes| sizes.column(path, color)).unwrap_or_default();
     // 打印当前条目 - 第一层不显示任何缩进或连接线
     if current_depth == 0 {
-        println!("{}", colored_name);
+        println!("{}{}", size_column, colored_name);
     } else {
-        println!("{}{}── {}", parent_prefix, if is_last { "└" } else { "├" }, colored_name);
+        println!("{}{}{}── {}", size_column, parent_prefix, if is_last { "└" } else { "├" }, colored_name);
     }
 
     // 如果是目录且未达到最大深度，递归处理子项
     if path.is_dir() && current_depth < max_depth {
-        if let Ok(entries) = fs::read_dir(path) {
-            let entries: Vec<_> = entries.filter_map(Result::ok).collect();
+        let entries: Option<Vec<PathBuf>> = match ignore {
+            Some(options) => Some(list_dir(path, options)),
+            None => fs::read_dir(path)
+                .ok()
+                .map(|entries| entries.filter_map(Result::ok).map(|e| e.path()).collect()),
+        };
+        // 筛选表达式只作用于文件，目录总是显示
+        let entries = entries.map(|entries| {
+            entries
+                .into_iter()
+                .filter(|entry| entry.is_dir() || query.is_none_or(|query| query.matches(entry)))
+                .filter(|entry| sizes.is_none_or(|sizes| sizes.size_of(entry).is_some()))
+                .collect::<Vec<PathBuf>>()
+        });
+        if let Some(mut entries) = entries {
+            if let Some(sizes) = sizes
+                && sizes.sort_by_size
+            {
+                sizes.sort(&mut entries);
+            }
             let count = entries.len();
             let items_to_show = if max_items_per_dir > 0 {
                 std::cmp::min(max_items_per_dir, count)
@@ -918,15 +1060,12 @@ fn print_tree_recursive(
                     )
                 };
                 print_tree_recursive(
-                    &entry.path(),
+                    entry,
                     current_depth + 1,
-                    max_depth,
-                    color,
-                    color_config,
-                    max_items_per_dir,
                     i == items_to_show - 1,
                     &new_prefix,
-                    hyperlink,
+                    options,
+                    color_config,
                 );
             }
 
@@ -936,24 +1075,15 @@ fn print_tree_recursive(
                     parent_prefix, 
                     if is_last { "    " } else { "│   " }
                 );
-                println!("{}... ({} more items)", new_prefix, count - max_items_per_dir);
+                let blank = sizes.map(|sizes| sizes.blank()).unwrap_or_default();
+                println!("{}{}... ({} more items)", blank, new_prefix, count - max_items_per_dir);
             }
         }
     }
 }
 
-fn print_file_tree(path: &Path, max_depth: usize, color: bool, max_items_per_dir: usize, hyperlink: bool) {
+/// 打印文件树，du通过sizes在每一行前显示占用空间
+pub fn print_file_tree(path: &Path, options: &TreeOptions) {
     let color_config = ColorConfig::load_from_file();
-    print_tree_recursive(
-        path, 
-        0, 
-        max_depth, 
-        color, 
-        &color_config, 
-        max_items_per_dir,
-        true,
-        "",
-        hyperlink,
-        
-    );
+    print_tree_recursive(path, 0, true, "", options, &color_config);
 }
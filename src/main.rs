@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use show::show::show_command;
 use std::path::PathBuf;
 mod merge;
@@ -6,17 +6,33 @@ mod show;
 use merge::merge::merge_command;
 mod search;
 mod utils;
-use search::search::search_command;
+use search::search::{
+    ActionOptions, ContentOptions, FilterOptions, InputOptions, PatternOptions, TraversalOptions,
+    search_command,
+};
 mod rename;
-use rename::rename::rename_command;
+use rename::rename::{RenameOptions, fix_extensions_command, rename_command};
 mod ls;
-use crate::ls::ls_command;
+use crate::ls::{LsOptions, ls_command};
+mod du;
+mod dupes;
+mod index;
+mod touch;
+mod walk;
+use du::{DuOptions, du_command};
+use dupes::{DupesOptions, dupes_command};
+use index::{build_index, index_status, update_index};
+use touch::touch_command;
+use utils::utils::{set_time_zone, set_type_detection};
 /// **功能**：
 /// 1. 列出目录内容：支持彩色突出显示不同文件/文件夹类型，支持按大小、修改时间等排序
 /// 2. 搜索文件、文件夹和文件内容：支持正则表达式（含Perl扩展）
 /// 3. 合并文本文件
 /// 4. 打开文本文件并高亮显示前 n 行
 /// 5. 批量重命名：支持正则表达式、多种高级模板匹配
+/// 6. 根据元数据或文件名批量设置文件时间
+/// 7. 查找重复的文件
+/// 8. 统计目录占用的磁盘空间
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -103,15 +119,48 @@ struct Args {
 
     /// 自定义显示的条目\
     /// 可选条目：size,file_name,modified,is_dir,author,inode,link_count,
-    /// block_size,blocks,device,atime,ctime,mtime,is_executable,permission,group
+    /// block_size,blocks,device,atime,ctime,mtime,is_executable,permission,group,
+    /// item_count（目录下直接包含的条目数）,total_size（目录下所有文件的大小之和），后两项会自动开启--dir-size
     #[arg(short = 'C', long, value_delimiter = ',')]
     custom_show: Vec<String>,
 
+    /// 递归统计目录的大小（其中所有文件的大小之和，包含隐藏文件），代替目录本身的大小显示，-S按它排序；
+    /// 对文件树同样有效，每个目录只遍历一次
+    #[arg(long, default_value_t = false)]
+    dir_size: bool,
+
+    /// 按.gitignore、.ignore和.rtignore规则过滤条目，对-R和文件树同样有效
+    #[arg(long, default_value_t = false)]
+    git_ignore: bool,
+
+    /// 筛选表达式，可以用and、or、not和括号组合各种筛选条件，与其他筛选选项同时使用时取交集
+    /// 例如："(type:image or type:video) and size>10m and not owner:root and mtime:this_month"
+    /// 字段：size、type、mtime、atime、ctime、perm、kind、is、nlink、owner、group、name（支持*?通配符）、ext
+    /// kind为条目类型（file、dir、symlink、socket、fifo、block、char、device），
+    /// is为属性（empty、broken、hardlink、setuid、setgid、sticky），如"is:empty or is:broken"
+    /// 操作符：":"或"="，size和nlink还支持>、>=、<、<=
+    #[arg(long = "where")]
+    where_expr: Option<String>,
+
+    /// 按哪个时区计算日历时间（如mtime:2024y-3m、today），可以是utc、local或+08:00这样的偏移，默认为utc
+    /// 对所有子命令有效，相对时间（如<3d）不受影响
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    time_zone: Option<String>,
+
+    /// -t/--file-type和type:的识别方式：ext只看扩展名（默认），content读取文件头，
+    /// 按magic number、shebang和UTF-8/ASCII文本判断，没有扩展名或扩展名不对的文件也能识别，
+    /// 没有识别出具体格式（如普通文本）时再看扩展名
+    /// 可以在color_config.json的file_types中扩展类型表，如{"file_types": {"text": ["vue"], "notebook": ["ipynb"]}}
+    #[arg(long, global = true, value_parser = ["ext", "content"])]
+    detect: Option<String>,
+
     #[command(subcommand)]
     sub: Option<SubCommands>,
 }
 
 #[derive(Parser, Debug)]
+// 只在启动时解析一次，变体大小差异不影响性能
+#[allow(clippy::large_enum_variant)]
 enum SubCommands {
     /// 打开某个文件并高亮显示前 n 行
     Show {
@@ -140,7 +189,30 @@ enum SubCommands {
         #[arg(short, long, default_value = ".", value_delimiter = ',')]
         paths: Vec<String>,
         /// 要搜索的关键字
-        keyword: String,
+        #[arg(required_unless_present_any = ["undo_replace", "patterns"])]
+        keyword: Option<String>,
+        /// 更多要搜索的关键字，可以多次指定，与keyword一起按“或”匹配
+        #[arg(short = 'e', long = "regexp")]
+        patterns: Vec<String>,
+        /// 内容搜索：文件中必须同时出现的关键字，可以多次指定
+        #[arg(long = "and")]
+        and_patterns: Vec<String>,
+        /// 只匹配完整的单词
+        #[arg(short = 'w', long = "word-regexp", default_value_t = false)]
+        word: bool,
+        /// 关键字按字面量匹配（默认行为），不能与-r同时使用
+        #[arg(short = 'F', long, default_value_t = false, conflicts_with = "regex")]
+        fixed_strings: bool,
+        /// 内容搜索：对整个文件匹配，匹配可以跨行，如"fn main\(\)\s*\{\n\s*let"
+        #[arg(short = 'U', long, default_value_t = false)]
+        multiline: bool,
+        /// 内容搜索：只搜索指定语言的文件，如rust、python，也可以用扩展名如rs
+        #[arg(long)]
+        lang: Option<String>,
+        /// 内容搜索：只保留位于指定区域的匹配：comments、strings或code
+        /// 按文件的语法（或--lang指定的语言）解析，无法识别语法的文件会被跳过
+        #[arg(long = "in")]
+        region: Option<String>,
         /// 是否搜索文件内容
         #[arg(short, long, default_value_t = false)]
         search_content: bool,
@@ -150,9 +222,13 @@ enum SubCommands {
         /// 忽略大小写
         #[arg(short, long, default_value_t = false)]
         ignore_case: bool,
-        /// 递归搜索的深度，默认为1，即不递归搜索
-        #[arg(short = 'R', long, default_value_t = 1)]
-        recursive_depth: usize,
+        /// 最大搜索深度，起点下的直接条目深度为1；默认为1，即不递归搜索
+        #[arg(short = 'R', long = "max-depth", visible_alias = "recursive-depth", default_value_t = 1)]
+        max_depth: usize,
+
+        /// 最小搜索深度，深度小于它的条目不输出（仍会进入其中的目录），例如2表示跳过起点下的直接条目
+        #[arg(long, default_value_t = 0)]
+        min_depth: usize,
 
         /// 筛选条件：文件大小
         /// 格式：xx-yyZ、xxZ、-yyZ（需要用双引号包含）
@@ -201,7 +277,9 @@ enum SubCommands {
         /// 范围可以用逗号分隔以取并集，例如：2021:2022y,10m表示在2021年到2022年或者在10月份
         /// 单个时间范围内的不同时间单位用“-”分隔，例如：2021y-7:8m-10:20d-:10h表示在2021年7月或8月的10日到20日，并且在00:00到10:00之间的时间范围
         /// 可以用括号来约定时间点，例如: (2021y-7m-10d-0h):(2021y-8m-20d-10h)表示在2021年7月10日00:00到2021年8月20日10:00之间的时间范围
-        #[arg(short = 'm', long)]
+        /// 相对时间从当前时刻往前算：<3d表示3天以内，>2w表示2周以前，"1h..6h ago"表示1到6小时之前
+        /// 相对时间的单位：s、M或min、h、d、w、m（30天）、y（365天）
+        #[arg(short = 'm', long, allow_hyphen_values = true)]
         modified: Option<String>,
 
         /// 筛选条件：文件访问时间
@@ -214,6 +292,14 @@ enum SubCommands {
         #[arg(short = 'c', long)]
         created: Option<String>,
 
+        /// 筛选条件：修改时间比指定文件新
+        #[arg(long)]
+        newer: Option<String>,
+
+        /// 筛选条件：修改时间比指定文件旧
+        #[arg(long)]
+        older: Option<String>,
+
         /// 筛选条件：文件权限
         /// 格式：rwxrwxrwx
         /// r: 可读
@@ -221,9 +307,18 @@ enum SubCommands {
         /// x: 可执行
         /// -: 无权限
         /// 例如：r-xr-xr-x表示可读、可执行，不可写
-        #[arg(short = 'p', long)]
+        /// 也可以用八进制，如755；x的位置为s、t时表示setuid、setgid或sticky位
+        /// 前面加+表示包含：列出的权限都有即可，如+--x--x--x、+111；+s表示setuid或setgid，+t表示sticky
+        /// 短选项是大写的-P，小写的-p是--paths
+        #[arg(short = 'P', long, allow_hyphen_values = true)]
         permission: Option<String>,
 
+        /// 筛选条件：条目类型，可以用逗号分隔多个
+        /// file(f)、dir(d)、symlink(l)、socket(s)、fifo(p)、block(b)、char(c)、device
+        /// 符号链接只匹配symlink
+        #[arg(long)]
+        kind: Option<String>,
+
         /// 筛选条件：文件所有者
         /// uid或者用户名
         /// 优先搜索用户名，找不到再搜索uid
@@ -235,26 +330,194 @@ enum SubCommands {
         /// 优先搜索组名，找不到再搜索gid
         #[arg(short = 'g', long)]
         group: Option<String>,
+
+        /// 搜索隐藏文件和隐藏目录
+        #[arg(long, default_value_t = false)]
+        hidden: bool,
+
+        /// 不读取.gitignore、.ignore和.rtignore中的忽略规则
+        #[arg(long, default_value_t = false)]
+        no_ignore: bool,
+
+        /// 按路径顺序输出结果（单线程遍历），默认并行遍历，输出顺序不固定
+        #[arg(long, default_value_t = false)]
+        sort: bool,
+
+        /// 跟随符号链接进入目录，按设备号和inode检测循环，出现循环的链接会被跳过
+        /// 默认不跟随；-L已用于--files-without-match
+        #[arg(long, default_value_t = false)]
+        follow: bool,
+
+        /// 不进入其他文件系统中的目录（如挂载点）
+        #[arg(long, default_value_t = false)]
+        one_file_system: bool,
+
+        /// 找到n个匹配的条目后停止搜索（--fuzzy时使用--limit）
+        #[arg(long, conflicts_with = "fuzzy")]
+        max_results: Option<usize>,
+
+        /// 内容搜索：显示匹配行之后的n行
+        #[arg(short = 'A', long)]
+        after_context: Option<usize>,
+
+        /// 内容搜索：显示匹配行之前的n行
+        #[arg(short = 'B', long)]
+        before_context: Option<usize>,
+
+        /// 内容搜索：显示匹配行前后各n行，不连续的部分之间用--分隔
+        #[arg(short = 'C', long, default_value_t = 0)]
+        context: usize,
+
+        /// 只输出每个文件的匹配行数（隐含-s）
+        #[arg(long, default_value_t = false, conflicts_with_all = ["files_with_matches", "files_without_match"])]
+        count: bool,
+
+        /// 只输出内容有匹配的文件路径（隐含-s）
+        #[arg(short = 'l', long, default_value_t = false, conflicts_with = "files_without_match")]
+        files_with_matches: bool,
+
+        /// 只输出内容没有匹配的文件路径（隐含-s）
+        #[arg(short = 'L', long, default_value_t = false)]
+        files_without_match: bool,
+
+        /// 每个文件最多输出的匹配行数
+        #[arg(long)]
+        max_count: Option<usize>,
+
+        /// 只输出匹配的部分，每个匹配单独一行
+        #[arg(long, default_value_t = false)]
+        only_matching: bool,
+
+        /// 输出匹配所在的列号（从1开始）
+        #[arg(long, default_value_t = false)]
+        column: bool,
+
+        /// 每个匹配输出一行JSON，包含路径、行号、列号、字节偏移、捕获组和文件元数据
+        /// 文件名的匹配输出type为path的记录；不输出上下文行
+        #[arg(long, default_value_t = false, conflicts_with_all = ["count", "files_with_matches", "files_without_match", "only_matching"])]
+        json: bool,
+
+        /// 文件列表（文件名匹配、-l、-L）中的路径以NUL结尾，便于配合xargs -0
+        #[arg(short = '0', long, default_value_t = false, conflicts_with = "json")]
+        print0: bool,
+
+        /// 二进制文件（开头8KB内含有NUL字节）的处理方式
+        /// skip: 跳过（默认）
+        /// text: 当作文本搜索
+        /// summary: 只提示该文件有匹配
+        #[arg(long, default_value = "skip")]
+        binary: String,
+
+        /// 按指定编码解码文件内容后再搜索，如gbk、gb18030、utf-16le、latin1
+        /// 默认自动检测：优先使用BOM，其次是UTF-8，否则根据内容猜测编码
+        #[arg(long)]
+        encoding: Option<String>,
+
+        /// 搜索压缩包中的文件：zip、tar、tar.gz、tar.xz、tar.zst、tar.bz2，
+        /// 以及单个文件的gz、xz、zst、bz2压缩，结果路径形如archive.zip!/inner/path:行号
+        #[arg(long, default_value_t = false)]
+        search_archives: bool,
+
+        /// 与--search-archives一起使用，跳过解压后超过此大小的条目，支持k、m、g等单位
+        #[arg(long, default_value = "100m", requires = "search_archives")]
+        archive_max_size: String,
+
+        /// 模糊匹配路径（类似fzf）：关键字的字符按顺序出现即可匹配，
+        /// 位于单词开头、路径分隔符之后、驼峰边界处或连续的匹配得分更高，结果按得分排序
+        /// 关键字不含大写字母时不区分大小写
+        /// 只匹配路径，不能与内容搜索、-e、-w、--exec、--replace、--indexed等选项一起使用，限制结果数量用--limit
+        #[arg(long, default_value_t = false, conflicts_with_all = [
+            "regex", "search_content", "replace", "exec", "exec_batch", "indexed", "max_results",
+            "patterns", "and_patterns", "word", "fixed_strings", "multiline", "lang", "region",
+            "count", "files_with_matches", "files_without_match", "max_count", "only_matching",
+            "column", "search_archives",
+        ])]
+        fuzzy: bool,
+
+        /// 与--fuzzy一起使用，最多输出的结果数量
+        #[arg(long, requires = "fuzzy")]
+        limit: Option<usize>,
+
+        /// 使用rt index建立的索引回答文件名和筛选条件的查询，不再遍历目录
+        /// 内容搜索（-s）仍然读取文件，索引中有三元组时先排除不可能匹配的文件
+        /// 是否包含隐藏文件和被忽略的文件由建立索引时的选项决定
+        #[arg(long, default_value_t = false, conflicts_with_all = ["replace", "fuzzy", "search_archives"])]
+        indexed: bool,
+
+        /// 对每个匹配的路径执行命令（类似find -exec），命令以;结束或写在最后
+        /// 占位符：{}完整路径，{/}文件名，{//}所在目录，{.}去掉扩展名的路径，{/.}去掉扩展名的文件名
+        /// 没有占位符时把路径加在命令最后；有命令执行失败时汇总退出码，并以退出码1结束
+        /// 例如：rt search -R 5 "\.log$" -r --exec gzip {} \;
+        #[arg(short = 'x', long, num_args = 1.., allow_hyphen_values = true, value_terminator = ";",
+            conflicts_with_all = ["exec_batch", "replace", "fuzzy", "indexed", "search_archives", "json"])]
+        exec: Option<Vec<String>>,
+
+        /// 与--exec相同，但一次执行传入多个路径（类似xargs），含有占位符的参数按路径展开为多个参数
+        #[arg(short = 'X', long, num_args = 1.., allow_hyphen_values = true, value_terminator = ";",
+            conflicts_with_all = ["replace", "fuzzy", "indexed", "search_archives", "json"])]
+        exec_batch: Option<Vec<String>>,
+
+        /// 与--exec或--exec-batch一起使用，同时执行的命令数，默认为CPU核数
+        /// 大于1时每个命令的输出在其结束后一起写出，不会交错
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+
+        /// 把文件内容中匹配的部分替换为指定内容，修改前会显示差异并要求确认
+        /// 正则模式下支持$1、${name}等捕获组引用，$$表示$本身
+        /// 新内容先写入临时文件，再覆盖原文件，并保留原文件的权限
+        /// 同样遵循--lang和--and；按行替换，不能与-U和--in一起使用
+        #[arg(long, conflicts_with_all = ["multiline", "region"])]
+        replace: Option<String>,
+
+        /// 与--replace一起使用，逐处确认是否修改
+        #[arg(long, default_value_t = false, requires = "replace")]
+        interactive: bool,
+
+        /// 与--replace一起使用，备份被修改的文件，之后可用--undo-replace撤销
+        #[arg(long, default_value_t = false, requires = "replace")]
+        backup: bool,
+
+        /// 撤销上一次带--backup的替换
+        #[arg(long, default_value_t = false)]
+        undo_replace: bool,
+
+        /// 筛选表达式，可以用and、or、not和括号组合各种筛选条件，与其他筛选选项同时使用时取交集
+        /// 例如："(type:image or type:video) and size>10m and not owner:root and mtime:this_month"
+        /// 字段：size、type、mtime、atime、ctime、perm、kind、is、nlink、owner、group、name（支持*?通配符）、ext
+        /// kind为条目类型（file、dir、symlink、socket、fifo、block、char、device），
+        /// is为属性（empty、broken、hardlink、setuid、setgid、sticky），如"is:empty or is:broken"
+        /// 操作符：":"或"="，size和nlink还支持>、>=、<、<=
+        #[arg(long = "where")]
+        where_expr: Option<String>,
     },
     /// 批量重命名
     /// 支持正则表达式、多种高级模板匹配
     /// 默认通配符:
     /// - {source}: 整个文件名，包含前缀和后缀
     /// - {prefix}: 文件名前缀，比如 "example.txt" 中的 "example"
-    /// - {suffix}: 文件名后缀，比如 "example.txt" 中的 "txt"，"abc.c.d"中的"c.d"
+    /// - {suffix}: 文件名后缀，比如 "example.txt" 中的 "txt"，"abc.c.d"中的"d"
+    /// - {ext:real}: 根据文件内容识别的真实扩展名，并进行标准化，如JPEG->jpg
     /// - { n }: 序号，从0开始，如0, 1, 2...
     ///     - {n:start=1}: 起始值为1, 如1, 2...**默认起始值为1**
     ///     - {n:width=2}: 宽度为2，不足2位用0填充, 如001, 002...**默认宽度为0** <!-- 十六进制需要在0x后面补0-->
     ///     - {n:step=2}: 步长为2, 如1, 3, 5...**默认步长为1；步长只能是正数**
     ///     - {n:radix=16}: 进制为16, 如0x01, 0x02...**默认进制为10**
     ///     - {n:reverse}: 将生成的列表反向, **默认不反向, 并且不是十进制时无效**
-    /// - {rand:n}: 生成随机数，n为生成的随机数的长度，如{rand:3}->123 <!-- 尽量保证不重复-->
+    /// - {rand:n}: 生成随机数，n为生成的随机数的长度，如{rand:3}->123
+    /// - {uuid}、{uuid:v4}、{uuid:v7}: 生成UUID，默认为v4
+    /// - {ulid}: 生成ULID
+    /// - {nanoid:len:alphabet}: 生成NanoID，默认长度为21，如{nanoid:8:abc123}
+    /// - {now:format}: 当前时间，如{now:%Y%m%d}->20250101
+    /// - 随机生成的结果在同一批次内以及与目标目录中已有的文件都不会重名
     /// - 元数据：
     ///     - {image:width, height, make, model, create_date, location, ISO,
     ///     aperture, exposure_time, focal_length,
     ///     orientation, flash}: 获取图片的元数据，如{exif:width}->1920
     ///     - {audio:artist, album, title, year, genre, duration, disc, date_recorded, date_released}: 获取音乐的元数据，如{music:artist}->Artist
-    ///     - {video:width, height, duration, bitrate, frame_rate, codec, resolution, aspect_ratio}: 获取视频的元数据，如{video:width}->1920
+    ///     - {video:width, height, duration, bitrate, frame_rate, codec, resolution, aspect_ratio, creation_time}: 获取视频的元数据，如{video:width}->1920
+    /// - 算术表达式：对捕获的数字变量和序号n做整数运算，支持+、-、*、/、%和括号
+    ///     - {ep+12}: 如ep为01时->13
+    ///     - {(page-1)*2:width=3}: 支持与{n:...}相同的width和radix选项，如{n*2+1:radix=16}
     /// 特殊功能(只对模板或通配符有效)：
     /// - {+p}: 将p指定的内容转换成大写，如{+source}->ABC.TXT
     /// - {-p}: 将p指定的内容转换成小写，如{-source}->abc.txt
@@ -266,7 +529,8 @@ enum SubCommands {
         /// 要重命名的文件路径列表
         source: String,
         /// 重命名后的文件名
-        target: String,
+        #[arg(required_unless_present = "fix_extensions")]
+        target: Option<String>,
         /// 指定文件夹下面的文件进行重命名，默认为当前目录
         #[arg(short, long, default_value = ".")]
         directory: String,
@@ -274,7 +538,11 @@ enum SubCommands {
         #[arg(short, long, default_value_t = false)]
         regex: bool,
         /// 开启模式匹配模式，是正则表达式+自定义变量匹配功能
-        /// 基本语法：在正则表达式里添加用大括号包围的变量名，变量名可以是任意字母的组合，如{var}；但不能与通配符冲突！
+        /// 基本语法：在正则表达式里添加用大括号包围的变量名，变量名由字母、数字和下划线组成且不能以数字开头，如{var}、{ep_1}；但不能与通配符冲突！
+        /// 变量默认非贪婪匹配（表达式末尾的变量除外）；表达式只需匹配文件名的一部分，需要匹配完整文件名时在首尾加上^和$
+        /// 带类型的变量：{var:int}、{var:float}、{var:word}、{var:alnum}、{var:date}、{var:time}、{var:any}(贪婪)、{var:re(\d{3})}(自定义正则)
+        /// 可选变量：{var?}、{var?:int}，匹配不到时为空字符串
+        /// 相邻变量无法确定分界时（如{a}{b}）会报错，可以指定不重叠的类型，如{a:word}{b:int}
         /// target部分不是正则表达式；在target的变量前加上+表示转换为大写，加-表示转换为小写，如{+var1}、{-var1}
         /// 例子1： source: "{pre_name}-{other}\.{ext}", target: "{pre_name}.{ext}"，将会在匹配到的文件名中删除"-{other}"
         /// 例子2： source: "abc{test}\.{ext}", target: "{test}{n:start=1}.{ext}", 将会删除所有abc前缀，并且添加从1开始的序号
@@ -305,47 +573,387 @@ enum SubCommands {
         /// 显示详细信息，如变量对应的实际值等
         #[arg(short = 'i', long, default_value_t = false)]
         info: bool,
+
+        /// 随机数种子，指定后{rand}、{nanoid}、{uuid}（v4）的结果可复现；
+        /// {ulid}和{uuid:v7}包含当前时间，只有随机部分由种子决定
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// 只处理满足筛选表达式的文件，格式与search的--where相同
+        #[arg(long = "where")]
+        where_expr: Option<String>,
+
+        /// 根据文件内容(magic number)修正扩展名，此时source是筛选文件名的正则表达式，不需要target
+        /// 扩展名与内容不符的文件会被报告并改为真实的扩展名；与内容相符的扩展名保持不变，如.JPEG、.jpe不会被改成.jpg
+        #[arg(long, default_value_t = false)]
+        fix_extensions: bool,
+    },
+    /// 建立和维护目录的索引，配合search --indexed在大目录中快速搜索
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+    /// 根据元数据、文件名或固定值设置文件的修改时间和访问时间
+    /// 修改前会预览并确认，可以用--undo撤销上一次的修改
+    Touch {
+        /// 要处理的文件或目录，目录只处理其中的文件（不递归），默认为当前目录
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+
+        /// 时间来源，格式：[source][offset]
+        /// source:
+        /// - {image:create_date}、{image:datetime_original}: 图片拍摄时间
+        /// - {video:creation_time}: 视频创建时间
+        /// - {name}: 从文件名中解析日期，如IMG_20230501_123456.jpg
+        /// - 固定时间：如2024-01-01、2024-01-01 12:00:00
+        /// - 省略: 文件当前的修改时间
+        ///
+        /// offset: 时间偏移，如+2h、-1d30M，单位可以是w、d、h、M、s
+        /// 例如：{image:create_date}-8h 表示使用图片拍摄时间并往前调整8小时；+2h 表示把当前时间往后调整2小时
+        #[arg(short, long)]
+        from: Option<String>,
+
+        /// 只修改指定的时间：mtime或atime，默认两者都修改
+        #[arg(long)]
+        only: Option<String>,
+
+        /// 筛选条件：文件大小，格式与search相同
+        #[arg(short = 'S', long)]
+        size: Option<String>,
+
+        /// 筛选条件：文件类型，格式与search相同
+        #[arg(short = 't', long)]
+        file_type: Option<String>,
+
+        /// 筛选条件：文件修改时间，格式与search相同
+        #[arg(short = 'm', long)]
+        modified: Option<String>,
+
+        /// 筛选条件：文件访问时间，格式与search相同
+        #[arg(short = 'a', long)]
+        accessed: Option<String>,
+
+        /// 筛选条件：文件创建时间，格式与search相同
+        #[arg(short = 'c', long)]
+        created: Option<String>,
+
+        /// 撤销上一次的修改
+        #[arg(short, long, default_value_t = false)]
+        undo: bool,
+    },
+    /// 查找重复的文件：先按大小分组，再比较文件头尾的部分哈希，最后比较完整的哈希
+    /// 指向同一个文件的硬链接不算重复；找到后可以删除多余的文件、替换为链接或移动到隔离目录，执行前会预览并确认
+    Dupes {
+        /// 要查找的目录或文件，默认为当前目录
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+
+        /// 最大深度，起点下的直接条目深度为1，默认不限制
+        #[arg(short = 'R', long)]
+        max_depth: Option<usize>,
+
+        /// 包含隐藏文件和隐藏目录
+        #[arg(long, default_value_t = false)]
+        hidden: bool,
+
+        /// 不读取.gitignore、.ignore和.rtignore中的忽略规则
+        #[arg(long, default_value_t = false)]
+        no_ignore: bool,
+
+        /// 筛选条件：文件大小，格式与search相同，如1m-表示只比较1MB以上的文件
+        #[arg(short = 'S', long)]
+        size: Option<String>,
+
+        /// 筛选条件：文件类型，格式与search相同
+        #[arg(short = 't', long)]
+        file_type: Option<String>,
+
+        /// 筛选条件：文件修改时间，格式与search相同
+        #[arg(short = 'm', long, allow_hyphen_values = true)]
+        modified: Option<String>,
+
+        /// 筛选条件：文件访问时间，格式与search相同
+        #[arg(short = 'a', long)]
+        accessed: Option<String>,
+
+        /// 筛选条件：文件创建时间，格式与search相同
+        #[arg(short = 'c', long)]
+        created: Option<String>,
+
+        /// 筛选表达式，格式与search的--where相同
+        #[arg(long = "where")]
+        where_expr: Option<String>,
+
+        /// 对每组中多余的文件的处理：delete删除，hardlink替换为硬链接，symlink替换为符号链接，
+        /// quarantine移动到--to指定的隔离目录；不指定时只列出重复的文件
+        #[arg(long, value_parser = ["delete", "hardlink", "symlink", "quarantine"])]
+        action: Option<String>,
+
+        /// 每组保留哪个文件：oldest保留修改时间最早的，newest保留最新的，largest保留分辨率最高的图片；
+        /// 默认为oldest，--similar-images时默认为largest
+        #[arg(long, value_parser = ["oldest", "newest", "largest"])]
+        keep: Option<String>,
+
+        /// quarantine的隔离目录，不存在时会自动创建
+        #[arg(long, required_if_eq("action", "quarantine"))]
+        to: Option<String>,
+
+        /// 查找相似的图片而不是内容完全相同的文件：对类型为image的文件计算感知哈希，
+        /// 缩放或重新压缩过的副本也能找到，每组第一张是--keep选出的图片，其余图片都与它相似；--action只支持delete和quarantine
+        #[arg(long, default_value_t = false)]
+        similar_images: bool,
+
+        /// 感知哈希算法：ahash平均哈希，dhash差异哈希，phash基于DCT的感知哈希
+        #[arg(long, default_value = "phash", value_parser = ["ahash", "dhash", "phash"], requires = "similar_images")]
+        hash: String,
+
+        /// 两张图片的哈希的汉明距离（0-64）不超过该值时视为相似，越小越严格
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(0..=64), requires = "similar_images")]
+        threshold: u32,
+    },
+    /// 统计目录占用的空间：并行遍历，同时计算占用的磁盘空间（块数×512）和文件内容的字节数，硬链接只计算一次
+    /// 默认以文件树显示，子项按占用空间从大到小排列，每行带有比例条
+    Du {
+        /// 要统计的目录或文件，默认为当前目录
+        #[arg(default_value = ".")]
+        paths: Vec<String>,
+
+        /// 文件树显示的深度，0只显示总计；统计总是包含所有层级
+        #[arg(short = 'd', long, default_value_t = 1)]
+        depth: usize,
+
+        /// 文件树每层最多显示的条目数，0表示不限制
+        #[arg(short = 'n', long, default_value_t = 10)]
+        max_items: usize,
+
+        /// 不显示文件树，改为列出占用空间最大的N个文件
+        #[arg(long)]
+        top: Option<usize>,
+
+        /// 按文件内容的字节数而不是占用的磁盘空间显示和排序
+        #[arg(long, default_value_t = false)]
+        apparent: bool,
+
+        /// 文件树中子项的排列顺序：size按占用空间从大到小，name按名称
+        #[arg(long, default_value = "size", value_parser = ["size", "name"])]
+        sort: String,
+
+        /// 不统计隐藏文件和隐藏目录，默认统计所有文件，与目录实际占用的空间一致
+        #[arg(long, default_value_t = false)]
+        exclude_hidden: bool,
+
+        /// 不统计.gitignore、.ignore和.rtignore忽略的文件，默认统计所有文件
+        #[arg(long, default_value_t = false)]
+        git_ignore: bool,
+
+        /// 按color_config.json高亮显示文件名
+        #[arg(long, default_value_t = false)]
+        color: bool,
+
+        /// 筛选条件：文件大小，格式与search相同，只统计满足条件的文件
+        #[arg(short = 'S', long)]
+        size: Option<String>,
+
+        /// 筛选条件：文件类型，格式与search相同
+        #[arg(short = 't', long)]
+        file_type: Option<String>,
+
+        /// 筛选条件：文件修改时间，格式与search相同
+        #[arg(short = 'm', long, allow_hyphen_values = true)]
+        modified: Option<String>,
+
+        /// 筛选条件：文件访问时间，格式与search相同
+        #[arg(short = 'a', long)]
+        accessed: Option<String>,
+
+        /// 筛选条件：文件创建时间，格式与search相同
+        #[arg(short = 'c', long)]
+        created: Option<String>,
+
+        /// 筛选表达式，格式与search的--where相同，只作用于文件
+        #[arg(long = "where")]
+        where_expr: Option<String>,
     },
     //todo: 批量移动、压缩文件、整合文件
 }
 
+#[derive(Subcommand, Debug)]
+enum IndexAction {
+    /// 为目录建立索引，已有的索引会被重新建立
+    Build {
+        /// 要建立索引的目录，默认为当前目录
+        #[arg(default_value = ".")]
+        path: String,
+        /// 同时为文本文件的内容建立三元组索引，加速search --indexed -s
+        #[arg(long, default_value_t = false)]
+        trigram: bool,
+        /// 包含隐藏文件和隐藏目录
+        #[arg(long, default_value_t = false)]
+        hidden: bool,
+        /// 不读取.gitignore、.ignore和.rtignore中的忽略规则
+        #[arg(long, default_value_t = false)]
+        no_ignore: bool,
+    },
+    /// 增量更新索引，只重新读取修改时间变化了的目录和内容被修改过的文件
+    Update {
+        /// 已建立索引的目录或其子目录，默认为当前目录
+        #[arg(default_value = ".")]
+        path: String,
+    },
+    /// 显示索引的状态
+    Status {
+        /// 已建立索引的目录或其子目录，默认为当前目录
+        #[arg(default_value = ".")]
+        path: String,
+    },
+}
+
 // ** D:\docs\rust\bhw\big_homework\target\debug\rt.exe **
 // ** /home/chenwn/RustRush-CLIKit/target/debug/rt **
 
 fn main() {
     let args = Args::parse();
+    if let Some(time_zone) = &args.time_zone
+        && let Err(e) = set_time_zone(time_zone)
+    {
+        eprintln!("Error: {}", e);
+        return;
+    }
+    if let Some(detect) = &args.detect
+        && let Err(e) = set_type_detection(detect)
+    {
+        eprintln!("Error: {}", e);
+        return;
+    }
     match args.sub {
         Some(SubCommands::Search {
             paths,
             keyword,
+            patterns,
+            and_patterns,
+            word,
+            fixed_strings,
+            multiline,
+            lang,
+            region,
             search_content,
             regex,
             ignore_case,
-            recursive_depth,
+            max_depth,
+            min_depth,
             size,
             file_type,
             modified,
             accessed,
             created,
+            newer,
+            older,
             permission,
+            kind,
             owner,
             group,
+            hidden,
+            no_ignore,
+            sort,
+            follow,
+            one_file_system,
+            max_results,
+            after_context,
+            before_context,
+            context,
+            count,
+            files_with_matches,
+            files_without_match,
+            max_count,
+            only_matching,
+            column,
+            json,
+            print0,
+            binary,
+            encoding,
+            search_archives,
+            archive_max_size,
+            fuzzy,
+            limit,
+            indexed,
+            exec,
+            exec_batch,
+            jobs,
+            replace,
+            interactive,
+            backup,
+            undo_replace,
+            where_expr,
         }) => {
             search_command(
                 paths,
-                keyword,
-                search_content,
-                regex,
-                ignore_case,
-                recursive_depth,
-                size,
-                file_type,
-                modified,
-                accessed,
-                created,
-                permission,
-                owner,
-                group,
+                TraversalOptions {
+                    max_depth,
+                    min_depth,
+                    hidden,
+                    no_ignore,
+                    sort,
+                    follow,
+                    one_file_system,
+                    max_results,
+                    indexed,
+                },
+                FilterOptions {
+                    size,
+                    file_type,
+                    modified,
+                    accessed,
+                    created,
+                    newer,
+                    older,
+                    permission,
+                    kind,
+                    owner,
+                    group,
+                    where_expr,
+                },
+                PatternOptions {
+                    keyword,
+                    regex,
+                    ignore_case,
+                    patterns,
+                    and_patterns,
+                    fixed_strings,
+                    word,
+                    multiline,
+                    lang,
+                    region,
+                    fuzzy,
+                    limit,
+                },
+                ContentOptions {
+                    search_content,
+                    before_context: before_context.unwrap_or(context),
+                    after_context: after_context.unwrap_or(context),
+                    count,
+                    files_with_matches,
+                    files_without_match,
+                    max_count,
+                    only_matching,
+                    column,
+                    json,
+                    print0,
+                },
+                InputOptions {
+                    binary,
+                    encoding,
+                    search_archives,
+                    archive_max_size,
+                },
+                ActionOptions {
+                    exec,
+                    exec_batch,
+                    jobs,
+                    replace,
+                    interactive,
+                    backup,
+                    undo_replace,
+                },
             );
         }
         Some(SubCommands::Show { file_path, lines }) => {
@@ -368,32 +976,181 @@ fn main() {
             move_to,
             info,
             replace,
+            seed,
+            where_expr,
+            fix_extensions,
         }) => {
-            let _ = rename_command(source, target, directory, regex, pattern, wildcard, move_to);
+            if fix_extensions {
+                if let Err(e) = fix_extensions_command(source, directory, move_to, where_expr) {
+                    eprintln!("Error: {}", e);
+                }
+            } else {
+                let _ = rename_command(
+                    source,
+                    target.unwrap_or_default(),
+                    directory,
+                    regex,
+                    pattern,
+                    wildcard,
+                    RenameOptions {
+                        move_to,
+                        seed,
+                        where_expr,
+                    },
+                );
+            }
+        }
+        Some(SubCommands::Index { action }) => {
+            let result = match action {
+                IndexAction::Build {
+                    path,
+                    trigram,
+                    hidden,
+                    no_ignore,
+                } => build_index(&path, trigram, hidden, no_ignore),
+                IndexAction::Update { path } => update_index(&path),
+                IndexAction::Status { path } => index_status(&path),
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+            }
+        }
+        Some(SubCommands::Touch {
+            paths,
+            from,
+            only,
+            size,
+            file_type,
+            modified,
+            accessed,
+            created,
+            undo,
+        }) => {
+            let filters = [
+                ("size", &size),
+                ("type", &file_type),
+                ("mtime", &modified),
+                ("atime", &accessed),
+                ("ctime", &created),
+            ];
+            if let Err(e) = touch_command(paths, from, only, &filters, undo) {
+                eprintln!("Error: {}", e);
+            }
+        }
+        Some(SubCommands::Dupes {
+            paths,
+            max_depth,
+            hidden,
+            no_ignore,
+            size,
+            file_type,
+            modified,
+            accessed,
+            created,
+            where_expr,
+            action,
+            keep,
+            to,
+            similar_images,
+            hash,
+            threshold,
+        }) => {
+            let filters = [
+                ("size", &size),
+                ("type", &file_type),
+                ("mtime", &modified),
+                ("atime", &accessed),
+                ("ctime", &created),
+            ];
+            if let Err(e) = dupes_command(
+                paths,
+                max_depth,
+                hidden,
+                no_ignore,
+                &filters,
+                where_expr,
+                DupesOptions {
+                    action,
+                    keep,
+                    to,
+                    similar_images,
+                    hash,
+                    threshold,
+                },
+            ) {
+                eprintln!("Error: {}", e);
+            }
+        }
+        Some(SubCommands::Du {
+            paths,
+            depth,
+            max_items,
+            top,
+            apparent,
+            sort,
+            exclude_hidden,
+            git_ignore,
+            color,
+            size,
+            file_type,
+            modified,
+            accessed,
+            created,
+            where_expr,
+        }) => {
+            let filters = [
+                ("size", &size),
+                ("type", &file_type),
+                ("mtime", &modified),
+                ("atime", &accessed),
+                ("ctime", &created),
+            ];
+            if let Err(e) = du_command(
+                paths,
+                DuOptions {
+                    depth,
+                    max_items,
+                    top,
+                    apparent,
+                    sort,
+                    exclude_hidden,
+                    git_ignore,
+                    color,
+                },
+                &filters,
+                where_expr,
+            ) {
+                eprintln!("Error: {}", e);
+            }
         }
         None => {
             ls_command(
                 args.directory,
-                args.author,
-                args.long,
-                args.all,
-                args.recursive,
-                args.human_readable,
-                args.time_sort,
-                args.reverse,
-                args.hyperlink,
-                args.inode,
-                args.ext_sort,
-                args.size_sort,
-                args.directories_only,
-                args.files_only,
-                args.color,
-                args.differentiated,
-                args.header,
-                &args.custom_show,
-                args.full_path,
-                args.tree,
-                args.max_tree_lines,
+                LsOptions {
+                    author: args.author,
+                    long: args.long,
+                    all: args.all,
+                    recursive: args.recursive,
+                    human_readable: args.human_readable,
+                    time_sort: args.time_sort,
+                    reverse: args.reverse,
+                    hyperlink: args.hyperlink,
+                    inode: args.inode,
+                    ext_sort: args.ext_sort,
+                    size_sort: args.size_sort,
+                    directories_only: args.directories_only,
+                    files_only: args.files_only,
+                    color: args.color,
+                    differentiated: args.differentiated,
+                    header: args.header,
+                    custom_show: args.custom_show,
+                    show_full_path: args.full_path,
+                    tree: args.tree,
+                    max_tree_lines: args.max_tree_lines,
+                    git_ignore: args.git_ignore,
+                    where_expr: args.where_expr,
+                    dir_size: args.dir_size,
+                },
             );
         }
     }
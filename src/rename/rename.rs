@@ -1,12 +1,16 @@
 use anyhow::{Ok, Result, anyhow};
+use crate::utils::query::Query;
+use crate::utils::utils::{canonical_extension, detect_magic_type, get_extension};
 use colored::Colorize;
 use fancy_regex::Regex;
 use id3::{Tag, TagLike};
 use nom_exif;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt::Write as _;
 use std::collections::{HashMap, HashSet};
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::vec;
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
@@ -14,6 +18,16 @@ use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use symphonia::default::get_probe;
 
+/// 重命名的其他选项
+pub struct RenameOptions {
+    /// 重命名后移动到的文件夹
+    pub move_to: Option<String>,
+    /// 随机数种子
+    pub seed: Option<u64>,
+    /// 筛选表达式，只处理满足条件的文件
+    pub where_expr: Option<String>,
+}
+
 /// # 参数
 /// - ` source`: 原始名称
 /// - `target`: 目标名称
@@ -21,6 +35,7 @@ use symphonia::default::get_probe;
 /// - `regex`: 是否使用正则表达式
 /// - `pattern`: 是否使用模板
 /// - `wildcard`: 是否使用通配符
+/// - `options`: 移动到的文件夹、随机数种子和筛选表达式
 pub fn rename_command(
     source: String,
     target: String,
@@ -28,8 +43,21 @@ pub fn rename_command(
     regex: bool,
     pattern: bool,
     wildcard: bool,
-    move_to: Option<String>,
+    options: RenameOptions,
 ) -> Result<bool> {
+    let RenameOptions {
+        move_to,
+        seed,
+        where_expr,
+    } = options;
+    let query = match Query::from_options(&[], &where_expr) {
+        std::result::Result::Ok(query) => query,
+        Err(e) => {
+            println!("{}", e);
+            return Err(e);
+        }
+    };
+    let query_matches = |p: &Path| query.as_ref().is_none_or(|query| query.matches(p));
     // println!("Renaming '{}' to '{}'...", source, target);
     // 处理流程：
     // 将变量存储到列表中
@@ -57,20 +85,23 @@ pub fn rename_command(
                 .trim_start_matches("./")
                 .to_string()
         })
+        .filter(|p| query_matches(Path::new(p)))
         .collect::<Vec<String>>();
 
     // 通配符和模板匹配都不开启的情况，此时只需要判断source对应的文件是否存在即可
     if !pattern && !wildcard {
         if !regex {
             let path_entry = Path::new(source.as_str());
-            if path_entry.exists() {
+            if path_entry.exists() && query_matches(path_entry) {
                 return rename_single_file(&path_entry, &target, move_to);
             }
         } else {
             let re = Regex::new(&source)?;
             for p in path_entries {
                 if let std::result::Result::Ok(p) = p {
-                    if re.is_match(p.file_name().as_os_str().to_str().unwrap())? {
+                    if re.is_match(p.file_name().as_os_str().to_str().unwrap())?
+                        && query_matches(&p.path())
+                    {
                         return rename_single_file(&p.path(), &target, move_to);
                     }
                 }
@@ -80,7 +111,13 @@ pub fn rename_command(
 
     let value_map = if pattern {
         // 为模板变量赋值
-        extract_named_groups(&mut paths, &source).unwrap_or(vec![HashMap::new(); paths.len()])
+        match extract_named_groups(&mut paths, &source) {
+            std::result::Result::Ok(value_map) => value_map,
+            Err(e) => {
+                println!("{}", e);
+                return Err(e);
+            }
+        }
     } else {
         /////todo:添加正则表达式的匹配
         if regex {
@@ -106,7 +143,7 @@ pub fn rename_command(
                 .collect();
         }
 
-        vec![HashMap::new()]
+        vec![HashMap::new(); paths.len()]
     };
     println!("value_map: {:?}", value_map);
     println!("paths: {:?}", paths);
@@ -114,7 +151,7 @@ pub fn rename_command(
         println!("Can not find the file!");
         return Err(anyhow!("Can not find the file!"));
     }
-    let res = rename_batch(paths, value_map, target, wildcard, move_to);
+    let res = rename_batch(paths, value_map, target, wildcard, move_to, seed);
     match res {
         std::result::Result::Ok(_) => {
             // println!("Rename success");
@@ -127,10 +164,92 @@ pub fn rename_command(
     }
 }
 
-fn wait_for_yes_no() -> bool {
+/// 根据文件内容修正扩展名
+/// - 扩展名与内容不符的文件（如内容是PNG的a.jpg）改为真实的扩展名
+/// - 与内容相符的扩展名（如a.JPEG、b.jpe）和无法识别内容的文件保持不变
+/// # 参数
+/// - `source`: 正则表达式，筛选要处理的文件名
+/// - `directory`: 目录
+/// - `move_to`: 修正后移动到新文件夹
+/// - `where_expr`: 筛选表达式，只处理满足条件的文件
+pub fn fix_extensions_command(
+    source: String,
+    directory: String,
+    move_to: Option<String>,
+    where_expr: Option<String>,
+) -> Result<bool> {
+    let re = Regex::new(&source)?;
+    let query = Query::from_options(&[], &where_expr)?;
+    let mut paths = Vec::new();
+    let mut targets = Vec::new();
+    let mut entries = Path::new(directory.as_str())
+        .read_dir()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| query.as_ref().is_none_or(|query| query.matches(path)))
+        .collect::<Vec<PathBuf>>();
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        if !re.is_match(&file_name)? {
+            continue;
+        }
+        let ext = get_extension(&path);
+        let stem = if ext.is_empty() {
+            file_name.clone()
+        } else {
+            file_name[..file_name.len() - ext.len() - 1].to_string()
+        };
+        let new_ext = match detect_magic_type(&path) {
+            Some(magic) if !magic.accepts(&ext) => {
+                println!(
+                    "{}",
+                    format!(
+                        "扩展名与内容不符: {} (实际为 {})",
+                        path.display(),
+                        if magic.ext.is_empty() { "ELF" } else { magic.ext }
+                    )
+                    .red()
+                );
+                if magic.ext.is_empty() {
+                    // 没有标准扩展名的类型只报告，不修改
+                    continue;
+                }
+                magic.ext.to_string()
+            }
+            _ => continue,
+        };
+        let target = if new_ext.is_empty() {
+            stem
+        } else {
+            format!("{}.{}", stem, new_ext)
+        };
+        paths.push(path.to_string_lossy().trim_start_matches("./").to_string());
+        targets.push(target);
+    }
+    if paths.is_empty() {
+        println!("没有需要修正扩展名的文件");
+        return Ok(true);
+    }
+    let collisions = find_collisions(&paths, &targets, &move_to);
+    if !collisions.is_empty() {
+        let names = collisions
+            .iter()
+            .map(|&i| targets[i].clone())
+            .collect::<Vec<String>>();
+        return Err(anyhow!("目标文件名重复或已存在: {}", names.join(", ")));
+    }
+    rename_batch_files(&paths, &targets, move_to)
+}
+
+/// 等待用户确认操作
+/// # 参数
+/// - `action`: 操作名称，如"重命名"
+pub fn wait_for_yes_no(action: &str) -> bool {
     loop {
         // 提示用户确认操作
-        print!("确认执行重命名操作吗？(y 确认 / c 取消): ");
+        print!("确认执行{}操作吗？(y 确认 / c 取消): ", action);
         io::stdout().flush().unwrap(); // 确保立即输出（print! 不会自动刷新）
 
         // 读取用户输入
@@ -169,17 +288,19 @@ fn rename_single_file(path: &Path, target: &str, move_to: Option<String>) -> Res
             println!("并移动到目录: {}", move_to_path.display());
         }
     }
-    let yes_no = wait_for_yes_no();
+    let yes_no = wait_for_yes_no("重命名");
     if yes_no {
         let new_path = path.with_file_name(target);
         std::fs::rename(path, &new_path)?;
-        let move_to_path = Path::new(move_to.as_ref().unwrap()).with_file_name(target);
-        if move_to_path.exists() {
-            println!("目标文件夹已存在: {}", move_to_path.display());
-            return Err(anyhow!("目标文件夹已存在: {}", move_to_path.display()));
+        if let Some(move_to) = move_to.as_ref() {
+            let move_to_path = Path::new(move_to).join(target);
+            if move_to_path.exists() {
+                println!("目标文件夹已存在: {}", move_to_path.display());
+                return Err(anyhow!("目标文件夹已存在: {}", move_to_path.display()));
+            }
+            std::fs::copy(&new_path, move_to_path)?;
+            std::fs::remove_file(new_path)?;
         }
-        std::fs::copy(&new_path, move_to_path)?;
-        std::fs::remove_file(new_path)?;
         return Ok(true);
     }
     return Ok(false);
@@ -204,21 +325,22 @@ fn rename_batch_files(
             println!("并移动到目录: {}", move_to_path.display());
         }
     }
-    let yes_no = wait_for_yes_no();
+    let yes_no = wait_for_yes_no("重命名");
     if yes_no {
         for (i, path) in paths.iter().enumerate() {
             let new_path = Path::new(path).with_file_name(&target[i]);
             let res = std::fs::rename(path, &new_path);
             match res {
                 std::result::Result::Ok(_) => {
-                    let move_to_path =
-                        Path::new(move_to.as_ref().unwrap()).with_file_name(&target[i]);
-                    if move_to_path.exists() {
-                        println!("目标文件夹已存在: {}", move_to_path.display());
-                        return Err(anyhow!("目标文件夹已存在: {}", move_to_path.display()));
+                    if let Some(move_to) = move_to.as_ref() {
+                        let move_to_path = Path::new(move_to).join(&target[i]);
+                        if move_to_path.exists() {
+                            println!("目标文件夹已存在: {}", move_to_path.display());
+                            return Err(anyhow!("目标文件夹已存在: {}", move_to_path.display()));
+                        }
+                        std::fs::copy(&new_path, move_to_path)?;
+                        std::fs::remove_file(new_path)?;
                     }
-                    std::fs::copy(&new_path, move_to_path)?;
-                    std::fs::remove_file(new_path)?;
                 }
                 std::result::Result::Err(e) => {
                     println!("重命名失败: {}", e);
@@ -236,6 +358,7 @@ fn rename_batch_files(
 /// - `value_map`: Vec<HashMap<String, String>>, 包含模板变量和值的映射
 /// - `target`
 /// - `wildcard`: bool, 是否使用通配符
+/// - `seed`: 随机数种子，指定后{rand}、{nanoid}和{uuid:v4}的结果可复现；{ulid}和{uuid:v7}包含当前时间，只有随机部分可复现
 /// # 返回值
 /// 返回操作是否成功
 pub fn rename_batch(
@@ -244,6 +367,7 @@ pub fn rename_batch(
     target: String,
     wildcard: bool,
     move_to: Option<String>,
+    seed: Option<u64>,
 ) -> Result<bool> {
     if value_map.len() != paths.len() {
         return Err(anyhow!("Value map length does not match paths length"));
@@ -255,7 +379,14 @@ pub fn rename_batch(
     // if value_map[0].is_empty() {
     //     return Err(anyhow!("Value map is empty"));
     // }
-    let mut final_paths: Vec<String> = vec![String::new(); paths.len()];
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    // 每个部分对应的所有文件的值
+    let mut part_values: Vec<Vec<String>> = Vec::new();
+    // 随机生成器所在的部分，出现重名时只重新生成这些部分
+    let mut random_parts: Vec<usize> = Vec::new();
     let mut target_parser: Vec<String> = Vec::new();
     let mut start = 0;
     let mut is_pattern = false;
@@ -299,13 +430,16 @@ pub fn rename_batch(
     if start < target.len() {
         target_parser.push(target.as_str()[start..].to_string());
     }
-    for part in target_parser.iter() {
-        let mut part_type = 0; // 0: 普通字符串 1: 变量 2: 元数据 3: 通配符
+    for (part_index, part) in target_parser.iter().enumerate() {
+        let mut part_type = 0; // 0: 普通字符串 1: 变量 2: 元数据 3: 通配符 4: 算术表达式
         let mut var_name = "";
         let mut key_name = "";
-        if part.starts_with('{') && part.ends_with('}') {
+        if part.starts_with('{') && part.ends_with('}') && is_arith_expression(&part[1..part.len() - 1]) {
+            key_name = &part[1..part.len() - 1];
+            part_type = 4;
+        } else if part.starts_with('{') && part.ends_with('}') {
             key_name = &part[1..part.len() - 1];
-            let var_name_re = Regex::new(r"\{(\+)?(\w+)(:.+)?\}").unwrap();
+            let var_name_re = Regex::new(r"\{([+-])?(\w+)(:.+)?\}").unwrap();
             let var_name_caps = var_name_re.captures(part).unwrap().unwrap();
             var_name = var_name_caps.get(2).unwrap().as_str();
             if !value_map.is_empty() && value_map[0].contains_key(var_name) {
@@ -320,46 +454,56 @@ pub fn rename_batch(
         }
         match part_type {
             0 => {
-                for (i, _path) in paths.iter().enumerate() {
-                    let final_part = part.replace("\\{", "{").replace("\\{", "}");
-                    final_paths[i].push_str(&final_part);
-                }
+                let final_part = part.replace("\\{", "{").replace("\\{", "}");
+                part_values.push(vec![final_part; paths.len()]);
             }
             1 => {
                 let mut value_vec = vec![];
                 for (i, _path) in paths.iter().enumerate() {
                     let value = value_map[i].get(var_name).unwrap();
-                    // final_paths[i].push_str(&value);
                     value_vec.push(value.clone());
                 }
-                let final_value_vec = process_special_symbols(&value_vec, &key_name.to_string())?;
-                for (i, _path) in paths.iter().enumerate() {
-                    final_paths[i].push_str(&final_value_vec[i]);
-                }
+                part_values.push(process_special_symbols(&value_vec, &key_name.to_string())?);
             }
             2 => {
                 let mut value_vec = vec![];
                 for (_i, path) in paths.iter().enumerate() {
                     let value = get_metadata(Path::new(path.as_str()), part.as_str()).unwrap();
-                    // final_paths[i].push_str(&value);
                     value_vec.push(value.clone());
                 }
-                let final_value_vec = process_special_symbols(&value_vec, &key_name.to_string())?;
-                for (i, _path) in paths.iter().enumerate() {
-                    final_paths[i].push_str(&final_value_vec[i]);
-                }
+                part_values.push(process_special_symbols(&value_vec, &key_name.to_string())?);
             }
             3 => {
-                let wildcards = wildcard_to_target(&paths, part)?;
+                let wildcards = wildcard_to_target(&paths, part, &mut rng)?;
                 // println!("wildcards: {:?}", wildcards);
-                let final_wildcards = if part.contains("{n:") {
+                let final_wildcards = if part.contains("{n:") || has_wildcard_params(key_name) {
                     wildcards
                 } else {
                     process_special_symbols(&wildcards, &key_name.to_string())?
                 };
-                for (i, _path) in paths.iter().enumerate() {
-                    final_paths[i].push_str(&final_wildcards[i]);
+                if is_random_wildcard(key_name) {
+                    random_parts.push(part_index);
                 }
+                part_values.push(final_wildcards);
+            }
+            4 => {
+                let (expr, options) = key_name.split_once(':').unwrap_or((key_name, ""));
+                let mut value_vec = vec![];
+                for i in 0..paths.len() {
+                    let mut vars: HashMap<String, i64> = HashMap::new();
+                    if let Some(values) = value_map.get(i) {
+                        for (name, value) in values.iter() {
+                            if let std::result::Result::Ok(num) = value.parse::<i64>() {
+                                vars.insert(name.clone(), num);
+                            }
+                        }
+                    }
+                    vars.entry("n".to_string()).or_insert(i as i64);
+                    let num = eval_arith(expr, &vars)
+                        .map_err(|e| anyhow!("Invalid target: {}, error \"{}\": {}", target, part, e))?;
+                    value_vec.push(format_number(num, options)?);
+                }
+                part_values.push(value_vec);
             }
             _ => {
                 return Err(anyhow!("Invalid target: {}, error \"{}\"", target, part));
@@ -368,73 +512,319 @@ pub fn rename_batch(
         part_type = 0;
         _ = part_type;
     }
-    // for (path, final_path) in paths.iter().zip(final_paths.iter()) {
-    //     println!("Renaming '{}' to '{}'", path, final_path);
-    // }
+
+    // 检查重名：与同一批次的其他文件以及目标目录中已存在的文件比较
+    // 如果包含随机生成器，只为重名的文件重新生成
+    let mut final_paths = join_part_values(&part_values, paths.len());
+    let mut retry = 0;
+    loop {
+        let collisions = find_collisions(&paths, &final_paths, &move_to);
+        if collisions.is_empty() {
+            break;
+        }
+        if random_parts.is_empty() || retry >= MAX_REGENERATE_RETRY {
+            let names = collisions
+                .iter()
+                .map(|&i| final_paths[i].clone())
+                .collect::<Vec<String>>();
+            return Err(anyhow!("目标文件名重复或已存在: {}", names.join(", ")));
+        }
+        let colliding_paths = collisions
+            .iter()
+            .map(|&i| paths[i].clone())
+            .collect::<Vec<String>>();
+        for &part_index in random_parts.iter() {
+            let values = wildcard_to_target(&colliding_paths, &target_parser[part_index], &mut rng)?;
+            for (k, &i) in collisions.iter().enumerate() {
+                part_values[part_index][i] = values[k].clone();
+            }
+        }
+        final_paths = join_part_values(&part_values, paths.len());
+        retry += 1;
+    }
     rename_batch_files(&paths, &final_paths, move_to)
 }
 
+/// 随机生成器重名时的最大重新生成次数
+const MAX_REGENERATE_RETRY: usize = 100;
+
+fn join_part_values(part_values: &[Vec<String>], len: usize) -> Vec<String> {
+    (0..len)
+        .map(|i| part_values.iter().map(|values| values[i].as_str()).collect())
+        .collect()
+}
+
+/// 找出重名的文件下标：目标路径在本批次中重复，或者目标路径已存在且不会在此之前被重命名
+fn find_collisions(paths: &[String], targets: &[String], move_to: &Option<String>) -> Vec<usize> {
+    let sources: HashMap<PathBuf, usize> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (PathBuf::from(p), i))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut collisions = Vec::new();
+    for (i, (path, target)) in paths.iter().zip(targets.iter()).enumerate() {
+        let dest = match move_to {
+            Some(dir) => Path::new(dir).join(target),
+            None => Path::new(path).with_file_name(target),
+        };
+        let renamed_before = sources.get(&dest).is_some_and(|&j| j <= i);
+        let exists = dest.exists() && !renamed_before;
+        if !seen.insert(dest) || exists {
+            collisions.push(i);
+        }
+    }
+    collisions
+}
+
+/// 是否为随机生成的通配符：{rand:n}、{uuid}、{ulid}、{nanoid}
+fn is_random_wildcard(cont: &str) -> bool {
+    let name = cont.split(':').next().unwrap_or("");
+    matches!(name, "rand" | "uuid" | "ulid" | "nanoid")
+}
+
+/// 通配符的参数不是截取参数，不能交给process_special_symbols处理
+fn has_wildcard_params(cont: &str) -> bool {
+    is_random_wildcard(cont) || cont.starts_with("now:") || cont == "ext:real"
+}
+
 /// 使用命名捕获组批量处理扩展正则表达式
 /// # 参数
-/// - `inputs`: 待匹配的字符串列表
+/// - `inputs`: 待匹配的字符串列表，匹配不上的会被移除
 /// - `ext_regex`: 扩展正则表达式，包含 {varname} 标记
 /// # 返回值
-/// 包含捕获键值对的 HashMap 列表，每个元素对应一个输入
+/// 包含捕获键值对的 HashMap 列表，每个元素对应一个保留下来的输入
+///
+/// 变量语法：
+/// - {var}: 非贪婪匹配任意字符，变量名可以包含字母、数字和下划线，但不能以数字开头
+/// - {var:type}: 带类型的变量，type可以是：
+///     - int: 整数，如 007
+///     - float: 小数，如 3.14
+///     - word: 字母（包括中文等），不含数字和下划线
+///     - alnum: 字母和数字
+///     - date: 日期，如 20250101、2025-01-01
+///     - time: 时间，如 1230、12:30:00
+///     - any: 贪婪匹配任意字符
+///     - re(...): 自定义正则表达式，如 {x:re(\d{3})}
+/// - {var?}、{var?:type}: 可选变量，匹配不到时为空字符串
+///
+/// 和普通正则一样只要匹配文件名的一部分即可，需要匹配完整的文件名时在首尾加上^和$；
+/// 位于表达式末尾的{var}按贪婪匹配，否则只能匹配到一个字符
+/// 两个相邻变量无法确定分界时（如{a}{b}、{a:int}{b:int}）会返回错误
 pub fn extract_named_groups(
     inputs: &mut Vec<String>,
     ext_regex: &String,
-) -> Option<Vec<HashMap<String, String>>> {
-    // 预编译正则表达式（只执行一次）
-    let (re, var_names) = {
-        let re_var_name = Regex::new(r"\{([a-zA-Z]+)\}").unwrap();
-        let mut var_names = Vec::new();
-
-        // 构建最终正则表达式
-        let final_regex = re_var_name
-            .replace_all(ext_regex.as_str(), |caps: &fancy_regex::Captures| {
-                let var_name = caps.get(1).unwrap().as_str();
-                var_names.push(var_name.to_string());
-                format!("(?P<{}>.+)", var_name)
-            })
-            .to_string();
+) -> Result<Vec<HashMap<String, String>>> {
+    let tokens = parse_pattern_tokens(ext_regex)?;
+    check_pattern_ambiguity(&tokens)?;
+
+    // 构建最终正则表达式
+    let mut final_regex = String::new();
+    let mut var_names: Vec<String> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            PatternToken::Literal(literal) => final_regex.push_str(literal),
+            PatternToken::Var(var) => {
+                if var_names.contains(&var.name) {
+                    return Err(anyhow!("变量重复定义: {{{}}}", var.name));
+                }
+                var_names.push(var.name.clone());
+                let var_regex = if i == tokens.len() - 1 && var.var_type == VarType::Any {
+                    VarType::Greedy.to_regex()
+                } else {
+                    var.var_type.to_regex()
+                };
+                let group = format!("(?P<{}>{})", var.name, var_regex);
+                if var.optional {
+                    final_regex.push_str(&format!("(?:{})?", group));
+                } else {
+                    final_regex.push_str(&group);
+                }
+            }
+        }
+    }
+    let re = Regex::new(&final_regex)
+        .map_err(|e| anyhow!("Invalid pattern: {} ({})", ext_regex, e))?;
 
-        // 编译正则表达式
-        let re = Regex::new(&final_regex).ok()?;
-        (re, var_names)
-    };
-    let mut filtered_inputs: Vec<String> = Vec::new();
-    // 批量处理输入
-    let mut can_add = false;
     let mut results = vec![];
+    let mut matched_inputs = vec![];
     for input in inputs.iter() {
-        let mut groups = HashMap::new();
-        if let std::result::Result::Ok(Some(caps)) = re.captures(input.as_str()) {
+        if let Some(caps) = re.captures(input.as_str())? {
+            let mut groups = HashMap::new();
             for name in &var_names {
-                if let Some(value) = caps.name(name) {
-                    groups.insert(name.clone(), value.as_str().to_string());
-                    can_add = true;
-                } else {
-                    filtered_inputs.push(input.clone());
-                    can_add = false;
-                    // println!("Invalid input: {}", input);
-                    break;
+                let value = caps.name(name).map(|m| m.as_str()).unwrap_or("");
+                groups.insert(name.clone(), value.to_string());
+            }
+            results.push(groups);
+            matched_inputs.push(input.clone());
+        }
+    }
+    *inputs = matched_inputs;
+    Ok(results)
+}
+
+/// 模式变量的类型
+#[derive(Debug, Clone, PartialEq)]
+enum VarType {
+    Any,
+    Greedy,
+    Int,
+    Float,
+    Word,
+    Alnum,
+    Date,
+    Time,
+    Custom(String),
+}
+
+impl VarType {
+    fn parse(type_str: &str) -> Result<Self> {
+        if let Some(re) = type_str.strip_prefix("re(").and_then(|t| t.strip_suffix(')')) {
+            return Ok(VarType::Custom(re.to_string()));
+        }
+        match type_str {
+            "int" => Ok(VarType::Int),
+            "float" => Ok(VarType::Float),
+            "word" => Ok(VarType::Word),
+            "alnum" => Ok(VarType::Alnum),
+            "date" => Ok(VarType::Date),
+            "time" => Ok(VarType::Time),
+            "any" => Ok(VarType::Greedy),
+            _ => Err(anyhow!("不支持的变量类型: {}", type_str)),
+        }
+    }
+
+    fn to_regex(&self) -> String {
+        match self {
+            VarType::Any => r".+?".to_string(),
+            VarType::Greedy => r".+".to_string(),
+            VarType::Int => r"\d+".to_string(),
+            VarType::Float => r"\d+(?:\.\d+)?".to_string(),
+            VarType::Word => r"[^\W\d_]+".to_string(),
+            VarType::Alnum => r"[^\W_]+".to_string(),
+            VarType::Date => r"\d{4}[-_.]?\d{2}[-_.]?\d{2}".to_string(),
+            VarType::Time => r"\d{2}[-_.:]?\d{2}(?:[-_.:]?\d{2})?".to_string(),
+            VarType::Custom(re) => format!("(?:{})", re),
+        }
+    }
+
+    /// 字符类别，用于判断两个相邻变量是否有歧义
+    /// 0: 任意字符 1: 数字 2: 字母 3: 字母和数字 4: 自定义
+    fn char_class(&self) -> u8 {
+        match self {
+            VarType::Any | VarType::Greedy => 0,
+            VarType::Int | VarType::Float | VarType::Date | VarType::Time => 1,
+            VarType::Word => 2,
+            VarType::Alnum => 3,
+            VarType::Custom(_) => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PatternVar {
+    name: String,
+    var_type: VarType,
+    optional: bool,
+}
+
+#[derive(Debug)]
+enum PatternToken {
+    Literal(String),
+    Var(PatternVar),
+}
+
+/// 将扩展正则表达式拆分成普通正则片段和变量
+/// 不是合法变量的大括号（如正则中的\d{3}）按原样保留
+fn parse_pattern_tokens(ext_regex: &str) -> Result<Vec<PatternToken>> {
+    let var_re = Regex::new(r"^\{([A-Za-z_]\w*)(\?)?(?::(re\(.*\)|\w+))?\}")?;
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut rest = ext_regex;
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            // 转义字符原样保留
+            let escaped: String = rest.chars().take(2).collect();
+            literal.push_str(&escaped);
+            rest = &rest[escaped.len()..];
+            continue;
+        }
+        if c == '{' {
+            let close = find_var_end(rest);
+            if let Some(end) = close
+                && let Some(caps) = var_re.captures(&rest[..=end])?
+            {
+                if !literal.is_empty() {
+                    tokens.push(PatternToken::Literal(std::mem::take(&mut literal)));
                 }
+                tokens.push(PatternToken::Var(PatternVar {
+                    name: caps.get(1).unwrap().as_str().to_string(),
+                    var_type: match caps.get(3) {
+                        Some(t) => VarType::parse(t.as_str())?,
+                        None => VarType::Any,
+                    },
+                    optional: caps.get(2).is_some(),
+                }));
+                rest = &rest[end + 1..];
+                continue;
             }
-        } else {
-            filtered_inputs.push(input.clone());
-            can_add = false;
         }
-        if can_add {
-            // println!("Input: {}, Groups: {:?}", input, groups);
-            results.push(groups);
-            can_add = false;
+        literal.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !literal.is_empty() {
+        tokens.push(PatternToken::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+/// 找到与开头的'{'配对的'}'，re(...)中的括号和大括号会被跳过
+fn find_var_end(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' | '(' => depth += 1,
+            ')' => depth -= 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
         }
     }
-    inputs.retain(|x| !filtered_inputs.contains(x));
-    // println!("results: {:?}", results);
-    // println!("inputs: {:?}", inputs);
+    None
+}
 
-    Some(results)
+/// 检查相邻变量是否有歧义，如{a}{b}无法确定a和b的分界
+fn check_pattern_ambiguity(tokens: &[PatternToken]) -> Result<()> {
+    for pair in tokens.windows(2) {
+        if let (PatternToken::Var(a), PatternToken::Var(b)) = (&pair[0], &pair[1]) {
+            let (ca, cb) = (a.var_type.char_class(), b.var_type.char_class());
+            let ambiguous = if ca == 4 || cb == 4 {
+                false
+            } else {
+                ca == 0 || cb == 0 || ca == cb || ca == 3 || cb == 3
+            };
+            if ambiguous {
+                return Err(anyhow!(
+                    "模式有歧义: {{{}}} 和 {{{}}} 相邻，无法确定分界，请在中间加入分隔符或指定不重叠的类型，如{{{}:word}}{{{}:int}}",
+                    a.name,
+                    b.name,
+                    a.name,
+                    b.name
+                ));
+            }
+        }
+    }
+    Ok(())
 }
 
 /// 将通配符转换成目标字符串
@@ -446,15 +836,26 @@ pub fn extract_named_groups(
 /// 默认通配符:
 /// - {source}: 整个文件名，包含前缀和后缀
 /// - {prefix}: 文件名前缀，比如 "example.txt" 中的 "example"
-/// - {suffix}: 文件名后缀，比如 "example.txt" 中的 "txt"，"abc.c.d"中的"c.d"
+/// - {suffix}: 文件名后缀，比如 "example.txt" 中的 "txt"，"abc.c.d"中的"d"
+/// - {ext:real}: 根据文件内容(magic number)识别的真实扩展名，如内容是PNG的a.jpg->png；扩展名会被标准化，如JPEG->jpg
 /// - { n }: 序号，从0开始，如0, 1, 2...
 ///     - {n:start=1}: 起始值为1, 如1, 2...**默认起始值为1**
 ///     - {n:width=2}: 宽度为2，不足2位用0填充, 如001, 002...**默认宽度为0** <!-- 十六进制需要在0x后面补0-->
 ///     - {n:step=2}: 步长为2, 如1, 3, 5...**默认步长为1；步长只能是正数**
 ///     - {n:radix=16}: 进制为16, 如0x01, 0x02...**默认进制为10**
 ///     - {n:reverse}: 将生成的列表反向, **默认不反向, 并且不是十进制时无效**
-/// - {rand:n}: 生成随机数，n为生成的随机数的长度，如{rand:3}->123 <!-- 需要保证不重复-->
-pub fn wildcard_to_target(paths: &Vec<String>, pattern: &String) -> Result<Vec<String>> {
+/// - {rand:n}: 生成随机数，n为生成的随机数的长度，如{rand:3}->123
+/// - {uuid}、{uuid:v4}、{uuid:v7}: 生成UUID，默认为v4；v7按时间有序
+/// - {ulid}: 生成ULID，按时间有序
+/// - {nanoid:len:alphabet}: 生成NanoID，默认长度为21，默认字符集为A-Za-z0-9_-，如{nanoid:8:abc123}
+/// - {now:format}: 当前时间，format为chrono格式，如{now:%Y%m%d}->20250101，默认为%Y%m%d%H%M%S
+///
+/// 随机生成器的结果保证在同一批次内不重复，与目标目录中已存在的文件重名时会重新生成
+pub fn wildcard_to_target(
+    paths: &Vec<String>,
+    pattern: &String,
+    rng: &mut StdRng,
+) -> Result<Vec<String>> {
     let cont = pattern.trim_start_matches("{").trim_end_matches("}");
     if cont == "source" {
         return Ok(paths.clone());
@@ -477,12 +878,24 @@ pub fn wildcard_to_target(paths: &Vec<String>, pattern: &String) -> Result<Vec<S
         return Ok(new_paths);
     }
     if cont == "suffix" {
+        let mut new_paths = Vec::new();
+        for path in paths {
+            new_paths.push(get_extension(Path::new(path)));
+        }
+        return Ok(new_paths);
+    }
+    // 根据文件内容识别的真实扩展名，识别不了时使用标准化后的扩展名
+    if cont == "ext:real" {
         let mut new_paths = Vec::new();
         for path in paths {
             let path = Path::new(path);
-            let ext = path.extension().unwrap().to_str().unwrap_or("").to_string();
+            let ext = match detect_magic_type(path) {
+                Some(magic) if !magic.accepts(&get_extension(path)) => magic.ext.to_string(),
+                _ => canonical_extension(&get_extension(path)),
+            };
             new_paths.push(ext);
         }
+        return Ok(new_paths);
     }
 
     if cont == "n" {
@@ -559,7 +972,6 @@ pub fn wildcard_to_target(paths: &Vec<String>, pattern: &String) -> Result<Vec<S
     // 随机数
     if let Some(rand_part) = cont.strip_prefix("rand:") {
         let len = rand_part.parse::<usize>().unwrap_or(6);
-        let mut rng = rand::rng();
         let max_unique = 10usize.pow(len as u32);
         let check_uniqueness = paths.len() <= max_unique;
 
@@ -584,9 +996,198 @@ pub fn wildcard_to_target(paths: &Vec<String>, pattern: &String) -> Result<Vec<S
             .collect());
     }
 
+    // UUID
+    if cont == "uuid" || cont.starts_with("uuid:") {
+        let version = cont.strip_prefix("uuid:").unwrap_or("v4");
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        return (0..paths.len())
+            .map(|_| match version {
+                "v4" => Ok(uuid::Builder::from_random_bytes(rng.random())
+                    .into_uuid()
+                    .to_string()),
+                "v7" => Ok(uuid::Builder::from_unix_timestamp_millis(now, &rng.random())
+                    .into_uuid()
+                    .to_string()),
+                _ => Err(anyhow!("Unsupported uuid version: {}", version)),
+            })
+            .collect();
+    }
+
+    // ULID
+    if cont == "ulid" {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        return Ok((0..paths.len())
+            .map(|_| ulid::Ulid::from_parts(now, rng.random()).to_string())
+            .collect());
+    }
+
+    // NanoID
+    if cont == "nanoid" || cont.starts_with("nanoid:") {
+        let params = cont.strip_prefix("nanoid").unwrap_or("").trim_start_matches(':');
+        let (len_str, alphabet) = params.split_once(':').unwrap_or((params, ""));
+        let len = if len_str.is_empty() {
+            21
+        } else {
+            len_str.parse::<usize>()?
+        };
+        let alphabet: Vec<char> = if alphabet.is_empty() {
+            "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_-"
+                .chars()
+                .collect()
+        } else {
+            alphabet.chars().collect()
+        };
+        return Ok((0..paths.len())
+            .map(|_| {
+                (0..len)
+                    .map(|_| alphabet[rng.random_range(0..alphabet.len())])
+                    .collect()
+            })
+            .collect());
+    }
+
+    // 当前时间
+    if cont == "now" || cont.starts_with("now:") {
+        let format = cont.strip_prefix("now:").unwrap_or("%Y%m%d%H%M%S");
+        // 格式中有无效的说明符时to_string会panic，用write!检查
+        let mut now = String::new();
+        write!(now, "{}", chrono::Local::now().format(format))
+            .map_err(|_| anyhow!("Invalid time format: {}", format))?;
+        return Ok(vec![now; paths.len()]);
+    }
+
     Ok(vec![])
 }
 
+/// 判断target中的{}是否为算术表达式，如{ep+12}、{page*2-1:width=3}、{n*2}
+/// 开头的+和-表示大小写转换，不算作运算符
+fn is_arith_expression(key_name: &str) -> bool {
+    let expr = key_name.split(':').next().unwrap_or("");
+    let starts_with_operand = expr
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '(');
+    starts_with_operand && expr.chars().skip(1).any(|c| "+-*/%()".contains(c))
+}
+
+/// 计算整数算术表达式，支持+、-、*、/、%和括号
+/// # 参数
+/// - `expr`: 表达式，如 ep+12、(page-1)*2
+/// - `vars`: 变量的值，包括捕获的数字变量和序号n
+fn eval_arith(expr: &str, vars: &HashMap<String, i64>) -> Result<i64> {
+    let chars: Vec<char> = expr.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut pos = 0;
+    let value = parse_arith_sum(&chars, &mut pos, vars)?;
+    if pos != chars.len() {
+        return Err(anyhow!("表达式中有无法识别的字符: {}", chars[pos]));
+    }
+    Ok(value)
+}
+
+fn parse_arith_sum(chars: &[char], pos: &mut usize, vars: &HashMap<String, i64>) -> Result<i64> {
+    let mut value = parse_arith_product(chars, pos, vars)?;
+    while *pos < chars.len() && (chars[*pos] == '+' || chars[*pos] == '-') {
+        let op = chars[*pos];
+        *pos += 1;
+        let rhs = parse_arith_product(chars, pos, vars)?;
+        value = if op == '+' {
+            value.checked_add(rhs)
+        } else {
+            value.checked_sub(rhs)
+        }
+        .ok_or(anyhow!("计算结果溢出"))?;
+    }
+    Ok(value)
+}
+
+fn parse_arith_product(
+    chars: &[char],
+    pos: &mut usize,
+    vars: &HashMap<String, i64>,
+) -> Result<i64> {
+    let mut value = parse_arith_factor(chars, pos, vars)?;
+    while *pos < chars.len() && "*/%".contains(chars[*pos]) {
+        let op = chars[*pos];
+        *pos += 1;
+        let rhs = parse_arith_factor(chars, pos, vars)?;
+        if op != '*' && rhs == 0 {
+            return Err(anyhow!("除数不能为0"));
+        }
+        // i64::MIN / -1 同样会溢出
+        value = match op {
+            '*' => value.checked_mul(rhs),
+            '/' => value.checked_div(rhs),
+            _ => value.checked_rem(rhs),
+        }
+        .ok_or(anyhow!("计算结果溢出"))?;
+    }
+    Ok(value)
+}
+
+fn parse_arith_factor(chars: &[char], pos: &mut usize, vars: &HashMap<String, i64>) -> Result<i64> {
+    match chars.get(*pos) {
+        Some('-') => {
+            *pos += 1;
+            parse_arith_factor(chars, pos, vars)?
+                .checked_neg()
+                .ok_or(anyhow!("计算结果溢出"))
+        }
+        Some('(') => {
+            *pos += 1;
+            let value = parse_arith_sum(chars, pos, vars)?;
+            if chars.get(*pos) != Some(&')') {
+                return Err(anyhow!("缺少右括号"));
+            }
+            *pos += 1;
+            Ok(value)
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let start = *pos;
+            while *pos < chars.len() && chars[*pos].is_ascii_digit() {
+                *pos += 1;
+            }
+            Ok(chars[start..*pos].iter().collect::<String>().parse::<i64>()?)
+        }
+        Some(c) if c.is_alphabetic() || *c == '_' => {
+            let start = *pos;
+            while *pos < chars.len() && (chars[*pos].is_alphanumeric() || chars[*pos] == '_') {
+                *pos += 1;
+            }
+            let name: String = chars[start..*pos].iter().collect();
+            vars.get(&name)
+                .copied()
+                .ok_or(anyhow!("变量{}不存在或不是整数", name))
+        }
+        Some(c) => Err(anyhow!("表达式中有无法识别的字符: {}", c)),
+        None => Err(anyhow!("表达式不完整")),
+    }
+}
+
+/// 按照{n:width=..,radix=..}相同的规则格式化数字
+/// 例如：width=3 -> 007；radix=16 -> 0x1F
+fn format_number(num: i64, options: &str) -> Result<String> {
+    let re_width = Regex::new(r"width=(\d+)")?;
+    let re_radix = Regex::new(r"radix=(\d+)")?;
+    let mut width = 0;
+    let mut radix = 10;
+    if let Some(cap) = re_width.captures(options)? {
+        width = cap[1].parse::<usize>()?;
+    }
+    if let Some(cap) = re_radix.captures(options)? {
+        radix = cap[1].parse::<u32>()?;
+    }
+    let sign = if num < 0 { "-" } else { "" };
+    let abs = u32::try_from(num.unsigned_abs()).map_err(|_| anyhow!("数字超出范围: {}", num))?;
+    let (prefix, digits) = match radix {
+        10 => ("", abs.to_string()),
+        16 => ("0x", format_radix(abs, 16)?),
+        8 => ("0o", format_radix(abs, 8)?),
+        2 => ("0b", format_radix(abs, 2)?),
+        _ => ("", format_radix(abs, radix)?),
+    };
+    Ok(format!("{}{}{:0>width$}", sign, prefix, digits, width = width))
+}
+
 fn format_radix(mut x: u32, radix: u32) -> Result<String> {
     if !(2..=36).contains(&radix) {
         return Err(anyhow!("Radix {} out of range (2-36)", radix));
@@ -666,7 +1267,7 @@ fn process_special_symbols(sources: &Vec<String>, pattern: &String) -> Result<Ve
 /// - `key`: 元数据键，格式为 `{key_name}`
 /// # 返回值
 /// 返回 `Result<String, Error>`，包含请求的元数据值或错误
-fn get_metadata(path: &Path, key: &str) -> Option<String> {
+pub fn get_metadata(path: &Path, key: &str) -> Option<String> {
     let re = Regex::new(r"^\{(.+):.+\}$").unwrap();
     let cap = re.captures(key);
     match cap {
@@ -753,6 +1354,9 @@ fn get_audio_metadata(path: &Path, key: &str) -> Result<String> {
 /// # 返回值
 /// 返回 `Result<String, Error>`，包含请求的元数据值或错误
 fn get_video_metadata(path: &Path, key: &str) -> Result<String> {
+    if key == "{video:creation_time}" {
+        return get_mp4_creation_time(path);
+    }
     let file = std::fs::File::open(path)?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
     let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
@@ -783,6 +1387,78 @@ fn get_video_metadata(path: &Path, key: &str) -> Result<String> {
     Err(anyhow!("Metadata not found"))
 }
 
+/// 从MP4/MOV文件的moov/mvhd box中读取创建时间
+/// # 参数
+/// - `path`: 文件路径
+/// # 返回值
+/// 返回RFC3339格式的UTC时间字符串
+fn get_mp4_creation_time(path: &Path) -> Result<String> {
+    // mvhd中的时间是从1904-01-01开始的秒数
+    const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+    let mut file = std::fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let (moov_start, moov_end) =
+        find_mp4_box(&mut file, 0, file_len, b"moov")?.ok_or(anyhow!("moov box not found"))?;
+    let (mvhd_start, _) = find_mp4_box(&mut file, moov_start, moov_end, b"mvhd")?
+        .ok_or(anyhow!("mvhd box not found"))?;
+
+    let mut version = [0u8; 4];
+    file.seek(SeekFrom::Start(mvhd_start))?;
+    file.read_exact(&mut version)?;
+    let seconds = if version[0] == 1 {
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf)?;
+        u64::from_be_bytes(buf)
+    } else {
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf)?;
+        u32::from_be_bytes(buf) as u64
+    };
+    if seconds <= MP4_EPOCH_OFFSET {
+        return Err(anyhow!("Metadata not found"));
+    }
+    let time = chrono::DateTime::from_timestamp((seconds - MP4_EPOCH_OFFSET) as i64, 0)
+        .ok_or(anyhow!("Invalid creation time"))?;
+    Ok(time.to_rfc3339())
+}
+
+/// 在[start, end)范围内查找指定类型的MP4 box
+/// # 返回值
+/// 找到时返回box内容的范围(不含box头)
+fn find_mp4_box(
+    file: &mut std::fs::File,
+    start: u64,
+    end: u64,
+    box_type: &[u8; 4],
+) -> Result<Option<(u64, u64)>> {
+    let mut pos = start;
+    // box的大小来自文件内容，损坏的文件中可能大得离谱，相加时需要检查溢出
+    while pos.checked_add(8).is_some_and(|header_end| header_end <= end) {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            file.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - pos;
+        }
+        if size < header_len {
+            return Err(anyhow!("Invalid box size"));
+        }
+        let box_end = pos.checked_add(size).ok_or(anyhow!("Invalid box size"))?;
+        if &header[4..8] == box_type {
+            return Ok(Some((pos + header_len, box_end.min(end))));
+        }
+        pos = box_end;
+    }
+    Ok(None)
+}
+
 /// 获取图片元数据
 /// # 参数
 /// - `path`: 文件路径
@@ -796,151 +1472,208 @@ fn get_image_metadata(path: &Path, key: &str) -> Result<String> {
 
     let iter: nom_exif::ExifIter = parser.parse(ms)?;
     let exif: nom_exif::Exif = iter.into();
-    // let ct = exif.get(nom_exif::ExifTag::CreateDate).unwrap();
     let key = key.trim_start_matches("{image:").trim_end_matches('}');
-    match key {
-        "width" => return Ok(exif.get(nom_exif::ExifTag::ImageWidth).unwrap().to_string()),
-        "height" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ImageHeight)
-                .unwrap()
-                .to_string());
-        }
-        "create_date" => return Ok(exif.get(nom_exif::ExifTag::CreateDate).unwrap().to_string()),
-        "make" => {
-            return Ok(exif.get(nom_exif::ExifTag::Make).unwrap().to_string());
-        }
-        "model" => return Ok(exif.get(nom_exif::ExifTag::Model).unwrap().to_string()),
-        "software" => return Ok(exif.get(nom_exif::ExifTag::Software).unwrap().to_string()),
-        "orientation" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::Orientation)
-                .unwrap()
-                .to_string());
-        }
-        "exposure_time" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ExposureTime)
-                .unwrap()
-                .to_string());
-        }
-        "f_number" => return Ok(exif.get(nom_exif::ExifTag::FNumber).unwrap().to_string()),
-        "iso_speed_ratings" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ISOSpeedRatings)
-                .unwrap()
-                .to_string());
-        }
-        "exposure_program" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ExposureProgram)
-                .unwrap()
-                .to_string());
-        }
-        "aperture_value" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ApertureValue)
-                .unwrap()
-                .to_string());
-        }
-        "max_aperture_value" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::MaxApertureValue)
-                .unwrap()
-                .to_string());
-        }
-        "metering_mode" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::MeteringMode)
-                .unwrap()
-                .to_string());
-        }
-        "flash" => return Ok(exif.get(nom_exif::ExifTag::Flash).unwrap().to_string()),
-        "focal_length" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::FocalLength)
-                .unwrap()
-                .to_string());
-        }
-        "subject_distance" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::SubjectDistance)
-                .unwrap()
-                .to_string());
-        }
-        "color_space" => return Ok(exif.get(nom_exif::ExifTag::ColorSpace).unwrap().to_string()),
-        "datetime_original" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::DateTimeOriginal)
-                .unwrap()
-                .to_string());
-        }
-        "components_configuration" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ComponentsConfiguration)
-                .unwrap()
-                .to_string());
-        }
-        "compression" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::Compression)
-                .unwrap()
-                .to_string());
-        }
-        "shutter_speed_value" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ShutterSpeedValue)
-                .unwrap()
-                .to_string());
-        }
-        "brightness_value" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::BrightnessValue)
-                .unwrap()
-                .to_string());
-        }
-        "exposure_bias_value" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ExposureBiasValue)
-                .unwrap()
-                .to_string());
-        }
-        "GPSLatitude" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::GPSLatitude)
-                .unwrap()
-                .to_string());
-        }
-        "GPSLongitude" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::GPSLongitude)
-                .unwrap()
-                .to_string());
-        }
-        "GPSAltitude" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::GPSAltitude)
-                .unwrap()
-                .to_string());
-        }
-        "GPSAltitudeRef" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::GPSAltitudeRef)
-                .unwrap()
-                .to_string());
-        }
-        "GPSTimeStamp" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::GPSTimeStamp)
-                .unwrap()
-                .to_string());
+    let tag = match key {
+        "width" => nom_exif::ExifTag::ImageWidth,
+        "height" => nom_exif::ExifTag::ImageHeight,
+        "create_date" => nom_exif::ExifTag::CreateDate,
+        "make" => nom_exif::ExifTag::Make,
+        "model" => nom_exif::ExifTag::Model,
+        "software" => nom_exif::ExifTag::Software,
+        "orientation" => nom_exif::ExifTag::Orientation,
+        "exposure_time" => nom_exif::ExifTag::ExposureTime,
+        "f_number" => nom_exif::ExifTag::FNumber,
+        "iso_speed_ratings" => nom_exif::ExifTag::ISOSpeedRatings,
+        "exposure_program" => nom_exif::ExifTag::ExposureProgram,
+        "aperture_value" => nom_exif::ExifTag::ApertureValue,
+        "max_aperture_value" => nom_exif::ExifTag::MaxApertureValue,
+        "metering_mode" => nom_exif::ExifTag::MeteringMode,
+        "flash" => nom_exif::ExifTag::Flash,
+        "focal_length" => nom_exif::ExifTag::FocalLength,
+        "subject_distance" => nom_exif::ExifTag::SubjectDistance,
+        "color_space" => nom_exif::ExifTag::ColorSpace,
+        "datetime_original" => nom_exif::ExifTag::DateTimeOriginal,
+        "components_configuration" => nom_exif::ExifTag::ComponentsConfiguration,
+        "compression" => nom_exif::ExifTag::Compression,
+        "shutter_speed_value" => nom_exif::ExifTag::ShutterSpeedValue,
+        "brightness_value" => nom_exif::ExifTag::BrightnessValue,
+        "exposure_bias_value" => nom_exif::ExifTag::ExposureBiasValue,
+        "GPSLatitude" => nom_exif::ExifTag::GPSLatitude,
+        "GPSLongitude" => nom_exif::ExifTag::GPSLongitude,
+        "GPSAltitude" => nom_exif::ExifTag::GPSAltitude,
+        "GPSAltitudeRef" => nom_exif::ExifTag::GPSAltitudeRef,
+        "GPSTimeStamp" => nom_exif::ExifTag::GPSTimeStamp,
+        "ISO" => nom_exif::ExifTag::ISOSpeedRatings,
+        _ => return Err(anyhow!("Invalid key name")),
+    };
+    // 有EXIF但缺少这个标签的图片很常见，返回错误由调用方跳过该文件
+    exif.get(tag)
+        .map(|value| value.to_string())
+        .ok_or_else(|| anyhow!("Metadata not found: {}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(token: &PatternToken) -> &PatternVar {
+        match token {
+            PatternToken::Var(var) => var,
+            PatternToken::Literal(literal) => panic!("不是变量: {}", literal),
         }
-        "ISO" => {
-            return Ok(exif
-                .get(nom_exif::ExifTag::ISOSpeedRatings)
-                .unwrap()
-                .to_string());
+    }
+
+    #[test]
+    fn parse_var_types() {
+        let cases = [
+            ("{a}", VarType::Any),
+            ("{a:any}", VarType::Greedy),
+            ("{a:int}", VarType::Int),
+            ("{a:float}", VarType::Float),
+            ("{a:word}", VarType::Word),
+            ("{a:alnum}", VarType::Alnum),
+            ("{a:date}", VarType::Date),
+            ("{a:time}", VarType::Time),
+            (r"{a:re(\d{3})}", VarType::Custom(r"\d{3}".to_string())),
+        ];
+        for (pattern, expected) in cases {
+            let tokens = parse_pattern_tokens(pattern).unwrap();
+            assert_eq!(tokens.len(), 1, "{}", pattern);
+            assert_eq!(var(&tokens[0]).var_type, expected, "{}", pattern);
         }
-        _ => return Err(anyhow!("Invalid key name")),
+        assert!(parse_pattern_tokens("{a:foo}").is_err());
+    }
+
+    #[test]
+    fn parse_var_names_and_optional() {
+        let tokens = parse_pattern_tokens(r"ep{ep_1?:int}_{_title}\.{ext2}").unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert!(matches!(&tokens[0], PatternToken::Literal(l) if l == "ep"));
+        let ep = var(&tokens[1]);
+        assert_eq!(ep.name, "ep_1");
+        assert!(ep.optional);
+        assert_eq!(ep.var_type, VarType::Int);
+        assert_eq!(var(&tokens[3]).name, "_title");
+        assert!(!var(&tokens[3]).optional);
+        assert!(matches!(&tokens[4], PatternToken::Literal(l) if l == r"\."));
+        assert_eq!(var(&tokens[5]).name, "ext2");
+    }
+
+    #[test]
+    fn parse_keeps_non_var_braces() {
+        // 以数字开头的不是变量，正则的重复次数和转义的大括号原样保留
+        let tokens = parse_pattern_tokens(r"\d{3}{1a}\{b\}").unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(matches!(&tokens[0], PatternToken::Literal(l) if l == r"\d{3}{1a}\{b\}"));
+    }
+
+    #[test]
+    fn ambiguity() {
+        let check =
+            |pattern: &str| check_pattern_ambiguity(&parse_pattern_tokens(pattern).unwrap());
+        assert!(check("{a}{b}").is_err());
+        assert!(check("{a:int}{b:int}").is_err());
+        assert!(check("{a:word}{b:alnum}").is_err());
+        assert!(check("{a:any}{b:int}").is_err());
+        assert!(check("{a?}{b:int}").is_err());
+        assert!(check("{a:word}{b:int}").is_ok());
+        assert!(check("{a:date}{b:word}").is_ok());
+        assert!(check(r"{a:re(\d+)}{b:int}").is_ok());
+        assert!(check("{a}-{b}").is_ok());
+    }
+
+    #[test]
+    fn named_groups_match_substring() {
+        let mut inputs = vec![
+            "ep01_intro.mkv".to_string(),
+            "xep02_outro.mkv".to_string(),
+            "a.txt".to_string(),
+        ];
+        let groups =
+            extract_named_groups(&mut inputs, &r"ep{num:int}_{title}\.{ext}".to_string()).unwrap();
+        assert_eq!(inputs, ["ep01_intro.mkv", "xep02_outro.mkv"]);
+        assert_eq!(groups[1]["num"], "02");
+        assert_eq!(groups[1]["title"], "outro");
+        assert_eq!(groups[1]["ext"], "mkv");
+
+        let mut inputs = vec!["ep01_intro.mkv".to_string(), "xep02_outro.mkv".to_string()];
+        extract_named_groups(&mut inputs, &r"^ep{num:int}_{title}\.{ext}$".to_string()).unwrap();
+        assert_eq!(inputs, ["ep01_intro.mkv"]);
+
+        let mut inputs = vec!["a.txt".to_string()];
+        assert!(extract_named_groups(&mut inputs, &"{a}.{a}".to_string()).is_err());
+    }
+
+    fn eval(expr: &str) -> Result<i64> {
+        let vars = HashMap::from([
+            ("ep".to_string(), 7),
+            ("n".to_string(), 2),
+            ("page_1".to_string(), 10),
+        ]);
+        eval_arith(expr, &vars)
+    }
+
+    #[test]
+    fn arith_precedence() {
+        assert_eq!(eval("1+2*3").unwrap(), 7);
+        assert_eq!(eval("10-4-3").unwrap(), 3);
+        assert_eq!(eval("20/2/5").unwrap(), 2);
+        assert_eq!(eval("7%4*2").unwrap(), 6);
+        assert_eq!(eval("ep+12").unwrap(), 19);
+        assert_eq!(eval("page_1*2-1").unwrap(), 19);
+        assert_eq!(eval("-n+3").unwrap(), 1);
+        assert_eq!(eval("2*-n").unwrap(), -4);
+        assert_eq!(eval(" ep + n * 3 ").unwrap(), 13);
+    }
+
+    #[test]
+    fn arith_parentheses() {
+        assert_eq!(eval("(1+2)*3").unwrap(), 9);
+        assert_eq!(eval("(page_1-1)*2").unwrap(), 18);
+        assert_eq!(eval("((ep))").unwrap(), 7);
+        assert_eq!(eval("-(ep-10)").unwrap(), 3);
+        assert!(eval("(1+2").is_err());
+        assert!(eval("1+2)").is_err());
+        assert!(eval("()").is_err());
+    }
+
+    #[test]
+    fn arith_errors() {
+        assert!(eval("ep/0").is_err());
+        assert!(eval("ep%(n-2)").is_err());
+        assert!(eval("9223372036854775807+1").is_err());
+        assert!(eval("-9223372036854775807-2").is_err());
+        assert!(eval("4611686018427387904*2").is_err());
+        assert!(eval("99999999999999999999").is_err());
+        assert!(eval("missing+1").is_err());
+        assert!(eval("ep+").is_err());
+        assert!(eval("ep^2").is_err());
+    }
+
+    #[test]
+    fn arith_detection() {
+        assert!(is_arith_expression("ep+12"));
+        assert!(is_arith_expression("page*2-1:width=3"));
+        assert!(is_arith_expression("n*2"));
+        assert!(is_arith_expression("(ep-1)*2"));
+        // 开头的+和-是大小写转换
+        assert!(!is_arith_expression("+var"));
+        assert!(!is_arith_expression("-var"));
+        assert!(!is_arith_expression("ep"));
+        assert!(!is_arith_expression("n:width=3"));
+        assert!(!is_arith_expression("now:%Y-%m-%d"));
+    }
+
+    #[test]
+    fn number_format() {
+        assert_eq!(format_number(7, "").unwrap(), "7");
+        assert_eq!(format_number(7, "width=3").unwrap(), "007");
+        assert_eq!(format_number(1234, "width=3").unwrap(), "1234");
+        assert_eq!(format_number(-7, "width=3").unwrap(), "-007");
+        assert_eq!(format_number(31, "radix=16").unwrap(), "0x1F");
+        assert_eq!(format_number(31, "radix=16,width=4").unwrap(), "0x001F");
+        assert_eq!(format_number(5, "radix=2").unwrap(), "0b101");
+        assert_eq!(format_number(8, "radix=8").unwrap(), "0o10");
+        assert!(format_number(1, "radix=40").is_err());
     }
 }
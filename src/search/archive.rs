@@ -0,0 +1,145 @@
+use anyhow::Result;
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+
+/// 可以搜索内部文件的压缩包格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    TarBz2,
+    /// 单个文件的压缩，如log.gz
+    Gz,
+    Xz,
+    Zst,
+    Bz2,
+}
+
+impl ArchiveKind {
+    /// 按文件名判断压缩包格式，不是支持的压缩包时返回None
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        // 先判断.tar.gz这类双重扩展名，再判断单独的.gz
+        let kinds = [
+            (".tar.gz", ArchiveKind::TarGz),
+            (".tgz", ArchiveKind::TarGz),
+            (".tar.xz", ArchiveKind::TarXz),
+            (".txz", ArchiveKind::TarXz),
+            (".tar.zst", ArchiveKind::TarZst),
+            (".tzst", ArchiveKind::TarZst),
+            (".tar.bz2", ArchiveKind::TarBz2),
+            (".tbz2", ArchiveKind::TarBz2),
+            (".tar", ArchiveKind::Tar),
+            (".zip", ArchiveKind::Zip),
+            (".gz", ArchiveKind::Gz),
+            (".xz", ArchiveKind::Xz),
+            (".zst", ArchiveKind::Zst),
+            (".bz2", ArchiveKind::Bz2),
+        ];
+        kinds
+            .iter()
+            .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+            .map(|&(_, kind)| kind)
+    }
+}
+
+/// 依次解压压缩包中的每个普通文件，把文件在包内的路径和内容交给visit
+/// 单个文件的压缩（如log.gz）只有一个条目，名称为去掉压缩扩展名后的文件名
+/// 解压后超过max_size的条目不会读入内存，返回这些被跳过的条目名称
+pub fn for_each_entry<F: FnMut(&str, &[u8])>(
+    path: &Path,
+    kind: ArchiveKind,
+    max_size: u64,
+    mut visit: F,
+) -> Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = ZipArchive::new(reader)?;
+            let mut skipped = Vec::new();
+            for i in 0..archive.len() {
+                let entry = archive.by_index(i)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                match read_limited(entry, max_size)? {
+                    Some(data) => visit(&name, &data),
+                    None => skipped.push(name),
+                }
+            }
+            Ok(skipped)
+        }
+        ArchiveKind::Tar => for_each_tar_entry(reader, max_size, visit),
+        ArchiveKind::TarGz => for_each_tar_entry(MultiGzDecoder::new(reader), max_size, visit),
+        ArchiveKind::TarXz => for_each_tar_entry(XzDecoder::new(reader), max_size, visit),
+        ArchiveKind::TarZst => {
+            for_each_tar_entry(zstd::Decoder::with_buffer(reader)?, max_size, visit)
+        }
+        ArchiveKind::TarBz2 => for_each_tar_entry(BzDecoder::new(reader), max_size, visit),
+        ArchiveKind::Gz => visit_single(path, MultiGzDecoder::new(reader), max_size, visit),
+        ArchiveKind::Xz => visit_single(path, XzDecoder::new(reader), max_size, visit),
+        ArchiveKind::Zst => {
+            visit_single(path, zstd::Decoder::with_buffer(reader)?, max_size, visit)
+        }
+        ArchiveKind::Bz2 => visit_single(path, BzDecoder::new(reader), max_size, visit),
+    }
+}
+
+/// 读取一个条目的全部内容，最多读max_size+1个字节，超过max_size时返回None
+/// 不依赖压缩包头部记录的大小，头部可能与实际解压出的内容不符
+fn read_limited<R: Read>(reader: R, max_size: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    reader
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut data)?;
+    Ok((data.len() as u64 <= max_size).then_some(data))
+}
+
+fn for_each_tar_entry<R: Read, F: FnMut(&str, &[u8])>(
+    reader: R,
+    max_size: u64,
+    mut visit: F,
+) -> Result<Vec<String>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut skipped = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        match read_limited(entry, max_size)? {
+            Some(data) => visit(&name, &data),
+            None => skipped.push(name),
+        }
+    }
+    Ok(skipped)
+}
+
+fn visit_single<R: Read, F: FnMut(&str, &[u8])>(
+    path: &Path,
+    reader: R,
+    max_size: u64,
+    mut visit: F,
+) -> Result<Vec<String>> {
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match read_limited(reader, max_size)? {
+        Some(data) => {
+            visit(&name, &data);
+            Ok(Vec::new())
+        }
+        None => Ok(vec![name]),
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{Result, anyhow};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// 批量模式下一次命令的参数总长度上限（包括固定参数和展开后的每个参数），避免超过系统对命令行长度的限制
+const BATCH_MAX_ARGS_LEN: usize = 128 * 1024;
+
+/// 对搜索结果执行的命令，参数中可以使用以下占位符：
+/// {}: 完整路径，{/}: 文件名，{//}: 所在目录，{.}: 去掉扩展名的路径，{/.}: 去掉扩展名的文件名
+/// 参数中没有占位符时，在最后加上{}
+pub struct CommandTemplate {
+    args: Vec<String>,
+    /// 批量模式：一次执行传入多个路径，含有占位符的参数按路径展开为多个参数
+    batch: bool,
+}
+
+/// 命令执行的结果
+struct ExecResult {
+    /// 这次执行对应的路径，批量模式下为第一个路径
+    path: PathBuf,
+    /// 退出码，被信号终止时为None
+    code: Option<i32>,
+    error: Option<String>,
+}
+
+impl CommandTemplate {
+    pub fn new(mut args: Vec<String>, batch: bool) -> Result<Self> {
+        if args.is_empty() {
+            return Err(anyhow!("--exec需要指定要执行的命令"));
+        }
+        if !args.iter().any(|arg| has_placeholder(arg)) {
+            args.push("{}".to_string());
+        }
+        Ok(CommandTemplate { args, batch })
+    }
+
+    /// 对所有路径执行命令，jobs为同时执行的命令数
+    /// 全部执行完后汇总失败的命令，有失败时以退出码1结束进程
+    pub fn run(&self, paths: &[PathBuf], jobs: usize) {
+        let invocations: Vec<Vec<&Path>> = if self.batch {
+            self.batches(paths)
+        } else {
+            paths.iter().map(|p| vec![p.as_path()]).collect()
+        };
+        let results = run_parallel(&invocations, jobs.max(1), |paths| self.build(paths));
+
+        let mut failed: Vec<&ExecResult> = results
+            .iter()
+            .filter(|r| r.error.is_some() || r.code != Some(0))
+            .collect();
+        if failed.is_empty() {
+            return;
+        }
+        failed.sort_by(|a, b| a.path.cmp(&b.path));
+        eprintln!("共执行{}个命令，{}个失败：", results.len(), failed.len());
+        for result in &failed {
+            match (&result.error, result.code) {
+                (Some(error), _) => eprintln!("  {}: {}", result.path.display(), error),
+                (None, Some(code)) => eprintln!("  {}: 退出码 {}", result.path.display(), code),
+                (None, None) => eprintln!("  {}: 被信号终止", result.path.display()),
+            }
+        }
+        std::process::exit(1);
+    }
+
+    /// 把路径分成若干批，每批的参数总长度不超过上限
+    /// 每个路径的长度按它展开后的所有参数计算，一个参数中有多个占位符时每处都计入
+    fn batches<'a>(&self, paths: &'a [PathBuf]) -> Vec<Vec<&'a Path>> {
+        let fixed_len: usize = self
+            .args
+            .iter()
+            .filter(|arg| !has_placeholder(arg))
+            .map(|arg| arg.len() + 1)
+            .sum();
+        let mut batches = Vec::new();
+        let mut batch: Vec<&Path> = Vec::new();
+        let mut len = fixed_len;
+        for path in paths {
+            let path_len: usize = self
+                .args
+                .iter()
+                .filter(|arg| has_placeholder(arg))
+                .map(|arg| expand(arg, path).len() + 1)
+                .sum();
+            if !batch.is_empty() && len + path_len > BATCH_MAX_ARGS_LEN {
+                batches.push(std::mem::take(&mut batch));
+                len = fixed_len;
+            }
+            batch.push(path);
+            len += path_len;
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    /// 用路径替换占位符，生成要执行的命令
+    fn build(&self, paths: &[&Path]) -> Command {
+        let mut args: Vec<OsString> = Vec::new();
+        for arg in &self.args {
+            if !has_placeholder(arg) {
+                args.push(arg.into());
+            } else if self.batch {
+                args.extend(paths.iter().map(|p| expand(arg, p)));
+            } else {
+                args.push(expand(arg, paths[0]));
+            }
+        }
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]);
+        command
+    }
+}
+
+/// 用jobs个线程执行命令，返回每次执行的结果
+/// 并行执行时先收集每个命令的输出，执行完后再一起写出，避免不同命令的输出交错
+fn run_parallel<F>(invocations: &[Vec<&Path>], jobs: usize, build: F) -> Vec<ExecResult>
+where
+    F: Fn(&[&Path]) -> Command + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(invocations.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(paths) = invocations.get(i) else {
+                        break;
+                    };
+                    let mut command = build(paths);
+                    let result = if jobs == 1 {
+                        command.status().map(|status| status.code())
+                    } else {
+                        command
+                            .stdin(Stdio::null())
+                            .output()
+                            .map(|output| write_output(&output))
+                    };
+                    let result = match result {
+                        Ok(code) => ExecResult {
+                            path: paths[0].to_path_buf(),
+                            code,
+                            error: None,
+                        },
+                        Err(e) => ExecResult {
+                            path: paths[0].to_path_buf(),
+                            code: None,
+                            error: Some(e.to_string()),
+                        },
+                    };
+                    results.lock().unwrap().push(result);
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+fn write_output(output: &Output) -> Option<i32> {
+    let _ = io::stdout().lock().write_all(&output.stdout);
+    let _ = io::stderr().lock().write_all(&output.stderr);
+    output.status.code()
+}
+
+fn has_placeholder(arg: &str) -> bool {
+    ["{}", "{/}", "{//}", "{.}", "{/.}"]
+        .iter()
+        .any(|placeholder| arg.contains(placeholder))
+}
+
+/// 替换参数中的占位符，路径按原始字节传入，不是UTF-8的文件名也能正确传给命令
+fn expand(arg: &str, path: &Path) -> OsString {
+    let full = path.as_os_str();
+    let name = path.file_name().unwrap_or(full);
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.as_os_str(),
+        _ => OsStr::new("."),
+    };
+    let stem = path.file_stem().unwrap_or(name);
+    let without_ext = match path.extension() {
+        Some(ext) => OsStr::from_bytes(&full.as_bytes()[..full.len() - ext.len() - 1]),
+        None => full,
+    };
+
+    let placeholders = [
+        ("{//}", parent),
+        ("{/.}", stem),
+        ("{/}", name),
+        ("{.}", without_ext),
+        ("{}", full),
+    ];
+    let mut expanded = OsString::new();
+    let mut rest = arg;
+    'outer: while !rest.is_empty() {
+        for (placeholder, value) in placeholders {
+            if let Some(after) = rest.strip_prefix(placeholder) {
+                expanded.push(value);
+                rest = after;
+                continue 'outer;
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        expanded.push(&rest[..c.len_utf8()]);
+        rest = &rest[c.len_utf8()..];
+    }
+    expanded
+}
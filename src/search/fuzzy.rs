@@ -0,0 +1,213 @@
+// 模糊匹配的打分规则参考fzf：
+// 关键字的每个字符按顺序出现在候选路径中即可匹配，
+// 匹配位于单词开头、路径分隔符之后、驼峰边界处，或者与前一个匹配字符相连时得分更高，
+// 匹配之间的空隙会扣分
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+const BONUS_BOUNDARY_WHITE: i64 = BONUS_BOUNDARY + 2;
+const BONUS_BOUNDARY_DELIMITER: i64 = BONUS_BOUNDARY + 1;
+const BONUS_NON_WORD: i64 = SCORE_MATCH / 2;
+const BONUS_CAMEL: i64 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i64 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CharClass {
+    White,
+    NonWord,
+    Delimiter,
+    Lower,
+    Upper,
+    Number,
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        if c.is_whitespace() {
+            CharClass::White
+        } else if matches!(c, '/' | '\\' | ',' | ':' | ';' | '|') {
+            CharClass::Delimiter
+        } else if c.is_lowercase() {
+            CharClass::Lower
+        } else if c.is_uppercase() {
+            CharClass::Upper
+        } else if c.is_numeric() {
+            CharClass::Number
+        } else if c.is_alphabetic() {
+            // 没有大小写之分的文字（如汉字）按小写字母处理
+            CharClass::Lower
+        } else {
+            CharClass::NonWord
+        }
+    }
+
+    fn is_word(self) -> bool {
+        self > CharClass::Delimiter
+    }
+}
+
+/// 在prev之后的cur字符处匹配时的加分
+fn bonus_for(prev: CharClass, cur: CharClass) -> i64 {
+    if cur.is_word() {
+        match prev {
+            CharClass::White => return BONUS_BOUNDARY_WHITE,
+            CharClass::Delimiter => return BONUS_BOUNDARY_DELIMITER,
+            CharClass::NonWord => return BONUS_BOUNDARY,
+            _ => {}
+        }
+    }
+    match (prev, cur) {
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL,
+        (p, CharClass::Number) if p != CharClass::Number => BONUS_CAMEL,
+        (_, CharClass::White) => BONUS_BOUNDARY_WHITE,
+        (_, CharClass::NonWord | CharClass::Delimiter) => BONUS_NON_WORD,
+        _ => 0,
+    }
+}
+
+/// 一个候选项的匹配结果
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// 匹配到的字符在候选项中的位置（按字符计，不是字节）
+    pub positions: Vec<usize>,
+}
+
+/// 对候选项做模糊匹配，关键字的字符没有全部按顺序出现时返回None
+/// 关键字不含大写字母或ignore_case为true时不区分大小写
+pub fn fuzzy_match(pattern: &str, candidate: &str, ignore_case: bool) -> Option<FuzzyMatch> {
+    let ignore_case = ignore_case || !pattern.chars().any(char::is_uppercase);
+    let normalize = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(normalize).collect();
+    let text: Vec<char> = candidate.chars().collect();
+    let (m, n) = (pattern.len(), text.len());
+    if m == 0 {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    if m > n {
+        return None;
+    }
+
+    // 每个位置的加分，行首视为前面是分隔符
+    let mut bonus = Vec::with_capacity(n);
+    let mut prev = CharClass::Delimiter;
+    for &c in &text {
+        let class = CharClass::of(c);
+        bonus.push(bonus_for(prev, class));
+        prev = class;
+    }
+    let lowered: Vec<char> = text.iter().map(|&c| normalize(c)).collect();
+
+    // score[i][j]：关键字前i+1个字符匹配完、且第i个字符匹配在text[j]时的最高分
+    // from[i][j]：此时关键字第i-1个字符匹配的位置，用于回溯
+    const NONE: i64 = i64::MIN / 2;
+    let mut score = vec![vec![NONE; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+    for i in 0..m {
+        // 跳过若干字符之后在j处匹配时，前一个字符匹配结果的最高分（已扣除空隙）
+        let mut best_gap = NONE;
+        let mut best_gap_from = 0;
+        for j in 0..n {
+            if i > 0 && j >= 2 {
+                best_gap += SCORE_GAP_EXTENSION;
+                let candidate = score[i - 1][j - 2] + SCORE_GAP_START;
+                if candidate > best_gap {
+                    best_gap = candidate;
+                    best_gap_from = j - 2;
+                }
+            }
+            if lowered[j] != pattern[i] {
+                continue;
+            }
+            if i == 0 {
+                score[0][j] = SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER;
+                continue;
+            }
+            if j > 0 && score[i - 1][j - 1] > NONE {
+                score[i][j] = score[i - 1][j - 1] + SCORE_MATCH + bonus[j].max(BONUS_CONSECUTIVE);
+                from[i][j] = j - 1;
+            }
+            if best_gap > NONE && best_gap + SCORE_MATCH + bonus[j] > score[i][j] {
+                score[i][j] = best_gap + SCORE_MATCH + bonus[j];
+                from[i][j] = best_gap_from;
+            }
+        }
+    }
+
+    let (end, &best) = score[m - 1]
+        .iter()
+        .enumerate()
+        .filter(|&(_, &s)| s > NONE)
+        .max_by_key(|&(j, &s)| (s, std::cmp::Reverse(j)))?;
+    let mut positions = vec![end; m];
+    for i in (1..m).rev() {
+        positions[i - 1] = from[i][positions[i]];
+    }
+    Some(FuzzyMatch {
+        score: best,
+        positions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(pattern: &str, candidate: &str) -> i64 {
+        fuzzy_match(pattern, candidate, false).unwrap().score
+    }
+
+    #[test]
+    fn consecutive_beats_scattered() {
+        assert!(score("abc", "xabcx") > score("abc", "xaxbxcx"));
+        assert!(score("main", "src/main.rs") > score("main", "src/mxaxixn.rs"));
+        assert_eq!(
+            fuzzy_match("abc", "xabcx", false).unwrap().positions,
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn boundary_bonus() {
+        // 路径分隔符、单词开头和驼峰边界之后的匹配得分更高
+        assert!(score("b", "a/b") > score("b", "ab"));
+        assert!(score("b", "a b") > score("b", "ab"));
+        assert!(score("b", "a_b") > score("b", "ab"));
+        assert!(score("b", "aB") > score("b", "ab"));
+        assert!(score("fb", "foo/bar") > score("fb", "fxxbar"));
+        // 有多处可以匹配时选择得分最高的位置
+        assert_eq!(
+            fuzzy_match("sr", "users/src", false).unwrap().positions,
+            [6, 7]
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        assert!(fuzzy_match("abc", "acb", false).is_none());
+        assert!(fuzzy_match("abcd", "abc", false).is_none());
+        assert!(fuzzy_match("xyz", "", false).is_none());
+        assert_eq!(
+            fuzzy_match("", "abc", false).unwrap().positions,
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("readme", "README.md", false).is_some());
+        assert!(fuzzy_match("README", "readme.md", false).is_none());
+        assert!(fuzzy_match("README", "readme.md", true).is_some());
+    }
+}
@@ -1 +1,6 @@
+pub mod archive;
+pub mod exec;
+pub mod fuzzy;
+pub mod reader;
+pub mod replace;
 pub mod search;
\ No newline at end of file
@@ -0,0 +1,208 @@
+use anyhow::{Result, anyhow};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Deref;
+use std::path::Path;
+use syntect::util::LinesWithEndings;
+
+/// 超过这个大小的文件使用内存映射读取
+const MMAP_THRESHOLD: u64 = 64 * 1024;
+/// 检测二进制文件时检查的字节数
+const BINARY_DETECTION_BYTES: usize = 8 * 1024;
+/// 无法内存映射的大文件只读取开头这么多字节来检测编码
+const HEAD_BYTES: u64 = 64 * 1024;
+
+/// 遇到二进制文件时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryPolicy {
+    /// 跳过二进制文件（默认）
+    Skip,
+    /// 当作文本搜索
+    Text,
+    /// 只提示二进制文件有匹配，不输出具体内容
+    Summary,
+}
+
+impl BinaryPolicy {
+    pub fn parse(policy: &str) -> Result<Self> {
+        match policy.to_lowercase().as_str() {
+            "skip" => Ok(BinaryPolicy::Skip),
+            "text" => Ok(BinaryPolicy::Text),
+            "summary" => Ok(BinaryPolicy::Summary),
+            _ => Err(anyhow!(
+                "Invalid binary policy '{}', expected skip, text or summary",
+                policy
+            )),
+        }
+    }
+}
+
+/// 读取文件内容时的选项
+pub struct ReadOptions {
+    pub binary: BinaryPolicy,
+    /// 指定的编码，None表示自动检测
+    pub encoding: Option<&'static Encoding>,
+    /// 是否搜索压缩包中的文件
+    pub search_archives: bool,
+    /// 压缩包中单个条目解压后的最大字节数，超过的条目被跳过
+    pub archive_max_size: u64,
+}
+
+impl ReadOptions {
+    pub fn new(binary: &str, encoding: Option<&str>) -> Result<Self> {
+        let encoding = match encoding {
+            Some(label) => Some(parse_encoding(label)?),
+            None => None,
+        };
+        Ok(ReadOptions {
+            binary: BinaryPolicy::parse(binary)?,
+            encoding,
+            search_archives: false,
+            archive_max_size: u64::MAX,
+        })
+    }
+}
+
+/// 解析编码名称，支持WHATWG标准中的所有名称，如gbk、gb18030、utf-16le、latin1、shift_jis
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| anyhow!("Unsupported encoding: {}", label))
+}
+
+/// 文件的原始字节，大文件使用内存映射，小文件直接读入内存
+pub enum FileBytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBytes::Mapped(mmap) => mmap,
+            FileBytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// 读取文件的原始字节
+pub fn read_file(path: &Path) -> io::Result<FileBytes> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len >= MMAP_THRESHOLD {
+        // 文件在映射期间被截断会导致SIGBUS，这里和ripgrep一样接受这个风险；映射失败时退回普通读取
+        if let Ok(mmap) = unsafe { Mmap::map(&file) } {
+            return Ok(FileBytes::Mapped(mmap));
+        }
+    }
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.read_to_end(&mut bytes)?;
+    Ok(FileBytes::Owned(bytes))
+}
+
+/// 逐行搜索的内容来源
+pub enum Source<'a> {
+    /// 已经在内存中或映射到内存的字节
+    Bytes(&'a [u8]),
+    /// 无法映射的大文件，每次从文件按块读取
+    File(&'a Path),
+}
+
+/// 打开要逐行搜索的文件：小文件读入内存，大文件使用内存映射；映射失败时返回None，由调用方按块读取
+pub fn map_file(path: &Path) -> io::Result<Option<FileBytes>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len >= MMAP_THRESHOLD {
+        // 与read_file相同，接受文件在映射期间被截断的风险
+        return Ok(unsafe { Mmap::map(&file) }.ok().map(FileBytes::Mapped));
+    }
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(FileBytes::Owned(bytes)))
+}
+
+/// 读取文件开头的字节，用于检测编码和二进制
+pub fn read_head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    File::open(path)?.take(HEAD_BYTES).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// 按encoding逐行解码，每行（包含换行符）交给visit，visit返回false时停止
+/// UTF-8内容直接在原始字节上切分，不复制；其他编码和按块读取的文件通过解码器逐行解码
+pub fn for_each_line<F: FnMut(&str) -> bool>(
+    source: &Source,
+    encoding: &'static Encoding,
+    mut visit: F,
+) -> io::Result<()> {
+    if let Source::Bytes(bytes) = source
+        && encoding == UTF_8
+    {
+        let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            for line in LinesWithEndings::from(text) {
+                if !visit(line) {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+    }
+    let input: Box<dyn Read> = match source {
+        Source::Bytes(bytes) => Box::new(*bytes),
+        Source::File(path) => Box::new(File::open(path)?),
+    };
+    // 与detect_encoding一致，BOM优先于指定的编码；无效的字节替换为U+FFFD
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .strip_bom(true)
+        .build(input);
+    let mut reader = BufReader::new(decoder);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        if !visit(&String::from_utf8_lossy(&buf)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 根据开头的NUL字节判断是否为二进制内容
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let len = bytes.len().min(BINARY_DETECTION_BYTES);
+    bytes[..len].contains(&0)
+}
+
+/// 判断内容会按哪种编码解码：BOM > 指定的编码 > UTF-8 > 自动检测
+/// bytes可以只是文件的开头，末尾被截断的UTF-8字符不影响判断
+pub fn detect_encoding(bytes: &[u8], encoding: Option<&'static Encoding>) -> &'static Encoding {
+    if let Some((bom_encoding, _)) = Encoding::for_bom(bytes) {
+        return bom_encoding;
+    }
+    if let Some(encoding) = encoding {
+        return encoding;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => return UTF_8,
+        Err(e) if e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// 是否是UTF-16编码，UTF-16文本中本来就有大量NUL字节，不能用来判断二进制
+pub fn is_utf16(encoding: &'static Encoding) -> bool {
+    encoding == UTF_16LE || encoding == UTF_16BE
+}
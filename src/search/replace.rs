@@ -0,0 +1,497 @@
+use crate::rename::rename::wait_for_yes_no;
+use crate::search::reader::{BinaryPolicy, ReadOptions, detect_encoding, is_binary, read_file};
+use crate::search::search::{Matcher, SearchFilters, find_matches};
+use crate::walk::{WalkOptions, walk};
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use syntect::util::LinesWithEndings;
+
+/// 一处修改，对应文件中的一行
+struct Hunk {
+    /// 行号，从0开始
+    line_num: usize,
+    old: String,
+    new: String,
+    /// old中被替换部分的字节范围
+    old_ranges: Vec<(usize, usize)>,
+    /// new中替换结果的字节范围
+    new_ranges: Vec<(usize, usize)>,
+    /// 用户是否确认应用这处修改
+    accepted: bool,
+}
+
+/// 一个文件的全部修改
+struct FileChange {
+    path: PathBuf,
+    /// path解析符号链接后的实际文件，写回时替换它而不是链接本身
+    target: PathBuf,
+    /// 文件的每一行，包含行尾换行符
+    lines: Vec<String>,
+    hunks: Vec<Hunk>,
+    encoding: &'static Encoding,
+    /// 原文件开头的BOM，写回时保留
+    bom: Vec<u8>,
+}
+
+/// 撤销记录：原文件路径和备份路径
+#[derive(Serialize, Deserialize)]
+struct ReplaceRecord {
+    path: PathBuf,
+    backup: PathBuf,
+}
+
+/// 交互模式下对一处修改的选择
+enum HunkChoice {
+    Yes,
+    No,
+    /// 应用剩余的全部修改
+    All,
+    /// 放弃剩余的全部修改
+    Quit,
+}
+
+/// 替换的选项
+pub struct ReplaceOptions {
+    /// 替换模板，支持$1、${name}等捕获组引用
+    pub replacement: String,
+    /// 逐处确认修改
+    pub interactive: bool,
+    /// 备份原文件，之后可以用--undo-replace撤销
+    pub backup: bool,
+}
+
+/// 在文件中批量替换
+/// # 参数
+/// - `paths`: 要搜索的路径
+/// - `matcher`: 与搜索共用的匹配规则，--lang和--and同样只保留符合条件的文件
+/// - `replace_options`: 替换模板以及是否逐处确认、是否备份
+/// - `options`: 目录遍历选项
+/// - `filters`: 文件筛选条件
+/// - `read_options`: 二进制和编码的处理方式
+pub fn replace_command(
+    paths: &[String],
+    matcher: &Matcher,
+    replace_options: &ReplaceOptions,
+    options: &WalkOptions,
+    filters: &SearchFilters,
+    read_options: &ReadOptions,
+) -> Result<bool> {
+    let ReplaceOptions {
+        replacement,
+        interactive,
+        backup,
+    } = replace_options;
+    let files = Mutex::new(Vec::new());
+    for p in paths {
+        walk(Path::new(p), options, |p, _| {
+            if p.is_file() && matcher.accepts(p, true) && filters.matches(p) {
+                files.lock().unwrap().push(p.to_path_buf());
+            }
+        });
+    }
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    files.dedup();
+
+    let mut changes = Vec::new();
+    // 多个符号链接指向同一个文件时只修改一次
+    let mut targets = HashSet::new();
+    for file in files {
+        let target = match fs::canonicalize(&file) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("跳过 {}: {}", file.display(), e);
+                continue;
+            }
+        };
+        if !targets.insert(target.clone()) {
+            continue;
+        }
+        match plan_file(&file, target, matcher, replacement, read_options) {
+            Ok(Some(change)) => changes.push(change),
+            Ok(None) => {}
+            Err(e) => eprintln!("跳过 {}: {}", file.display(), e),
+        }
+    }
+    if changes.is_empty() {
+        println!("没有需要替换的内容");
+        return Ok(true);
+    }
+
+    if *interactive {
+        if !confirm_hunks(&mut changes) {
+            return Ok(false);
+        }
+    } else {
+        for change in changes.iter() {
+            print_file_header(change);
+            for hunk in change.hunks.iter() {
+                print_hunk(hunk);
+            }
+        }
+        let hunk_count: usize = changes.iter().map(|c| c.hunks.len()).sum();
+        println!("共{}个文件，{}处修改", changes.len(), hunk_count);
+        if !wait_for_yes_no("替换") {
+            return Ok(false);
+        }
+    }
+    changes.retain(|change| change.hunks.iter().any(|hunk| hunk.accepted));
+    if changes.is_empty() {
+        return Ok(true);
+    }
+
+    apply_changes(&changes, *backup)?;
+    println!("已修改{}个文件", changes.len());
+    Ok(true)
+}
+
+/// 计算一个文件的所有修改，没有修改时返回None
+/// 有无法解码的字节或替换结果无法用原编码表示时返回错误，避免写回时破坏文件内容
+fn plan_file(
+    path: &Path,
+    target: PathBuf,
+    matcher: &Matcher,
+    replacement: &str,
+    read_options: &ReadOptions,
+) -> Result<Option<FileChange>> {
+    let bytes = read_file(path)?;
+    let encoding = detect_encoding(&bytes, read_options.encoding);
+    if read_options.binary != BinaryPolicy::Text && is_binary(&bytes) {
+        return Ok(None);
+    }
+    let bom = match Encoding::for_bom(&bytes) {
+        Some((_, bom_len)) => bytes[..bom_len].to_vec(),
+        None => Vec::new(),
+    };
+    let (content, malformed) = encoding.decode_with_bom_removal(&bytes);
+    if malformed {
+        return Err(anyhow!("文件中有{}编码无法解码的字节", encoding.name()));
+    }
+    if !matcher.contains_all(&content) {
+        return Ok(None);
+    }
+    let pattern = &matcher.pattern;
+    let lines: Vec<String> = LinesWithEndings::from(&content)
+        .map(|line| line.to_string())
+        .collect();
+
+    let mut hunks = Vec::new();
+    for (line_num, line) in lines.iter().enumerate() {
+        let old = line.trim_end_matches(['\r', '\n']);
+        if find_matches(pattern, old).is_empty() {
+            continue;
+        }
+        let mut new = String::new();
+        let mut old_ranges = Vec::new();
+        let mut new_ranges = Vec::new();
+        let mut last_index = 0;
+        for caps in pattern.captures_iter(old).filter_map(|c| c.ok()) {
+            let Some(m) = caps.get(0) else {
+                continue;
+            };
+            new.push_str(&old[last_index..m.start()]);
+            let start = new.len();
+            caps.expand(replacement, &mut new);
+            old_ranges.push((m.start(), m.end()));
+            new_ranges.push((start, new.len()));
+            last_index = m.end();
+        }
+        new.push_str(&old[last_index..]);
+        if new != old {
+            if encoding.encode(&new).2 {
+                return Err(anyhow!(
+                    "第{}行的替换结果中有{}编码无法表示的字符",
+                    line_num + 1,
+                    encoding.name()
+                ));
+            }
+            hunks.push(Hunk {
+                line_num,
+                old: old.to_string(),
+                new,
+                old_ranges,
+                new_ranges,
+                accepted: true,
+            });
+        }
+    }
+    if hunks.is_empty() {
+        return Ok(None);
+    }
+    // encoding_rs不能编码UTF-16，写回时会变成UTF-8，这种文件不做修改
+    if encoding.output_encoding() != encoding {
+        return Err(anyhow!("不支持写回{}编码的文件", encoding.name()));
+    }
+    Ok(Some(FileChange {
+        path: path.to_path_buf(),
+        target,
+        lines,
+        hunks,
+        encoding,
+        bom,
+    }))
+}
+
+fn print_file_header(change: &FileChange) {
+    println!("{}", change.path.display().to_string().bold());
+}
+
+/// 以diff的形式打印一处修改，被替换和替换后的部分加上背景色
+fn print_hunk(hunk: &Hunk) {
+    println!(
+        "{:>5} {} {}",
+        hunk.line_num + 1,
+        "-".red(),
+        mark_ranges(&hunk.old, &hunk.old_ranges, false)
+    );
+    println!(
+        "{:>5} {} {}",
+        "",
+        "+".green(),
+        mark_ranges(&hunk.new, &hunk.new_ranges, true)
+    );
+}
+
+fn mark_ranges(text: &str, ranges: &[(usize, usize)], added: bool) -> String {
+    let mut marked = String::new();
+    let mut last_index = 0;
+    for &(start, end) in ranges {
+        let plain = &text[last_index..start];
+        let changed = &text[start..end];
+        if added {
+            marked.push_str(&plain.green().to_string());
+            marked.push_str(&changed.black().on_green().to_string());
+        } else {
+            marked.push_str(&plain.red().to_string());
+            marked.push_str(&changed.black().on_red().to_string());
+        }
+        last_index = end;
+    }
+    let rest = &text[last_index..];
+    if added {
+        marked.push_str(&rest.green().to_string());
+    } else {
+        marked.push_str(&rest.red().to_string());
+    }
+    marked
+}
+
+/// 逐处询问是否应用修改，返回false表示用户放弃了全部修改
+fn confirm_hunks(changes: &mut [FileChange]) -> bool {
+    let mut accept_rest = false;
+    let mut quit = false;
+    for change in changes.iter_mut() {
+        print_file_header(change);
+        for hunk in change.hunks.iter_mut() {
+            if quit {
+                hunk.accepted = false;
+                continue;
+            }
+            print_hunk(hunk);
+            if accept_rest {
+                continue;
+            }
+            match wait_for_hunk_choice() {
+                HunkChoice::Yes => {}
+                HunkChoice::No => hunk.accepted = false,
+                HunkChoice::All => accept_rest = true,
+                HunkChoice::Quit => {
+                    hunk.accepted = false;
+                    quit = true;
+                }
+            }
+        }
+    }
+    changes
+        .iter()
+        .any(|change| change.hunks.iter().any(|hunk| hunk.accepted))
+}
+
+fn wait_for_hunk_choice() -> HunkChoice {
+    loop {
+        print!("应用这处修改吗？(y 应用 / n 跳过 / a 应用剩余全部 / q 放弃剩余全部): ");
+        io::stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).expect("无法读取输入");
+        match input.trim().to_lowercase().as_str() {
+            "y" => return HunkChoice::Yes,
+            "n" => return HunkChoice::No,
+            "a" => return HunkChoice::All,
+            "q" => return HunkChoice::Quit,
+            _ => println!("无效输入，请输入 y、n、a 或 q"),
+        }
+    }
+}
+
+/// 生成文件修改后的内容，只应用用户确认过的修改
+fn render_change(change: &FileChange) -> Result<Vec<u8>> {
+    let mut lines = change.lines.clone();
+    for hunk in change.hunks.iter().filter(|hunk| hunk.accepted) {
+        let line = &lines[hunk.line_num];
+        let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+        lines[hunk.line_num] = format!("{}{}", hunk.new, ending);
+    }
+    let content = lines.concat();
+    let (encoded, _, unmappable) = change.encoding.encode(&content);
+    if unmappable {
+        return Err(anyhow!("有{}编码无法表示的字符", change.encoding.name()));
+    }
+    let mut bytes = change.bom.clone();
+    bytes.extend_from_slice(&encoded);
+    Ok(bytes)
+}
+
+/// 在原文件所在目录写入临时文件，并复制原文件的权限和所有者，返回临时文件路径
+/// path必须是解析过符号链接的实际文件；写入后读回校验，失败时删除临时文件
+fn write_temp_file(path: &Path, contents: &[u8]) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or(anyhow!("Invalid file path: {}", path.display()))?
+        .to_string_lossy();
+    let temp_path =
+        path.with_file_name(format!(".{}.rt-replace-{}", file_name, std::process::id()));
+    let metadata = fs::metadata(path)?;
+    let written = (|| -> Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::set_permissions(&temp_path, metadata.permissions())?;
+        // 非root用户通常不能修改所有者，失败时保持当前用户
+        let _ = std::os::unix::fs::chown(&temp_path, Some(metadata.uid()), Some(metadata.gid()));
+        if fs::read(&temp_path)? != contents {
+            return Err(anyhow!("临时文件的内容与写入的不一致"));
+        }
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(temp_path)
+}
+
+fn remove_temp_files(temp_paths: &[PathBuf]) {
+    for temp_path in temp_paths {
+        let _ = fs::remove_file(temp_path);
+    }
+}
+
+/// 应用全部修改：先写好并校验所有临时文件，任何一个失败都不修改原文件；全部成功后再逐个rename覆盖原文件
+/// rename中途失败时删除剩余的临时文件，并报告已经修改了哪些文件
+fn apply_changes(changes: &[FileChange], backup: bool) -> Result<()> {
+    let mut temp_paths = Vec::new();
+    for change in changes {
+        match render_change(change).and_then(|contents| write_temp_file(&change.target, &contents))
+        {
+            Ok(temp_path) => temp_paths.push(temp_path),
+            Err(e) => {
+                remove_temp_files(&temp_paths);
+                return Err(anyhow!(
+                    "写入临时文件失败: {}: {}",
+                    change.path.display(),
+                    e
+                ));
+            }
+        }
+    }
+
+    if backup && let Err(e) = backup_files(changes) {
+        remove_temp_files(&temp_paths);
+        return Err(e);
+    }
+
+    for (i, (change, temp_path)) in changes.iter().zip(temp_paths.iter()).enumerate() {
+        if let Err(e) = fs::rename(temp_path, &change.target) {
+            remove_temp_files(&temp_paths[i..]);
+            for done in &changes[..i] {
+                eprintln!("已修改: {}", done.path.display());
+            }
+            return Err(anyhow!(
+                "覆盖原文件失败: {}: {}，之前的{}个文件已经修改{}",
+                change.path.display(),
+                e,
+                i,
+                if backup {
+                    "，可以用--undo-replace撤销"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn get_backup_dir() -> Result<PathBuf> {
+    let exe_path = std::env::current_exe()?;
+    let exe_dir = exe_path
+        .parent()
+        .ok_or(anyhow!("Failed to get executable directory"))?;
+    Ok(exe_dir.join("replace_backup"))
+}
+
+fn get_journal_path() -> Result<PathBuf> {
+    Ok(get_backup_dir()?.join("replace_undo.json"))
+}
+
+/// 备份将要修改的文件，只保留最近一次替换的备份
+fn backup_files(changes: &[FileChange]) -> Result<()> {
+    let backup_dir = get_backup_dir()?;
+    if backup_dir.exists() {
+        fs::remove_dir_all(&backup_dir)?;
+    }
+    fs::create_dir_all(&backup_dir)?;
+    let mut journal = Vec::new();
+    for (i, change) in changes.iter().enumerate() {
+        let backup = backup_dir.join(i.to_string());
+        fs::copy(&change.target, &backup)?;
+        journal.push(ReplaceRecord {
+            path: change.target.clone(),
+            backup,
+        });
+    }
+    let json = serde_json::to_string_pretty(&journal)?;
+    fs::write(get_journal_path()?, json)?;
+    Ok(())
+}
+
+/// 撤销上一次带--backup的替换
+pub fn undo_last_replace() -> Result<bool> {
+    let journal_path = get_journal_path()?;
+    let contents = fs::read_to_string(&journal_path).map_err(|_| anyhow!("没有可撤销的记录"))?;
+    let journal: Vec<ReplaceRecord> = serde_json::from_str(&contents)?;
+    println!("恢复文件:");
+    for record in journal.iter() {
+        println!("{}", record.path.display().to_string().green());
+    }
+    if !wait_for_yes_no("撤销") {
+        return Ok(false);
+    }
+    let mut success = true;
+    for record in journal.iter() {
+        // 记录的路径在替换后可能被换成了符号链接，同样写到链接指向的文件
+        let restored = fs::canonicalize(&record.path)
+            .and_then(|target| Ok((target, fs::read(&record.backup)?)))
+            .map_err(anyhow::Error::from)
+            .and_then(|(target, contents)| {
+                let temp_path = write_temp_file(&target, &contents)?;
+                Ok(fs::rename(temp_path, &target)?)
+            });
+        if let Err(e) = restored {
+            println!("恢复失败: {}: {}", record.path.display(), e);
+            success = false;
+        }
+    }
+    if success {
+        fs::remove_dir_all(get_backup_dir()?)?;
+    }
+    Ok(success)
+}
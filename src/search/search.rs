@@ -1,330 +1,1515 @@
 use colored::Colorize;
 // use regex::Regex;
-use crate::utils::utils::get_extension;
+use crate::index::{find_index, trigrams_of};
+use crate::search::archive::{ArchiveKind, for_each_entry};
+use crate::search::exec::CommandTemplate;
+use crate::search::fuzzy::{FuzzyMatch, fuzzy_match};
+use crate::search::reader::{
+    BinaryPolicy, ReadOptions, Source, detect_encoding, for_each_line, is_binary, is_utf16,
+    map_file, read_head,
+};
+use crate::search::replace::{ReplaceOptions, replace_command, undo_last_replace};
+use crate::utils::highlight::{
+    CodeRegion, FileHighlighter, RegionClassifier, find_syntax, find_syntax_by_extension,
+};
+use crate::utils::query::Query;
+use crate::utils::utils::{FileInfo, get_extension, parse_size_unit};
+use crate::walk::{WalkOptions, walk, walk_while};
 use atty::Stream;
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
 use fancy_regex::Regex;
-use std::fs;
-use std::io::{self, BufRead};
-use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::as_24_bit_terminal_escaped;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use syntect::highlighting::Style;
+use syntect::parsing::SyntaxReference;
+use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
 
 pub fn search_command(
     paths: Vec<String>,
-    keyword: String,
-    search_content: bool,
-    regex: bool,
-    ignore_case: bool,
-    recursive_depth: usize, // 修改为usize类型
-    size: Option<String>,
-    file_type: Option<String>,
-    modified: Option<String>,
-    accessed: Option<String>,
-    created: Option<String>,
-    permission: Option<String>,
-    owner: Option<String>,
-    group: Option<String>,
+    traversal: TraversalOptions,
+    filter_options: FilterOptions,
+    pattern_options: PatternOptions,
+    mut content_options: ContentOptions,
+    input_options: InputOptions,
+    action_options: ActionOptions,
 ) {
-    // 检测是否有管道输入（标准输入非终端时视为管道）
-    let has_pipe_input = !atty::is(Stream::Stdin);
+    let TraversalOptions {
+        max_depth,
+        min_depth,
+        hidden,
+        no_ignore,
+        sort,
+        follow,
+        one_file_system,
+        max_results,
+        indexed,
+    } = traversal;
+    let InputOptions {
+        binary,
+        encoding,
+        search_archives,
+        archive_max_size,
+    } = input_options;
+    let ActionOptions {
+        exec,
+        exec_batch,
+        jobs,
+        replace,
+        interactive,
+        backup,
+        undo_replace,
+    } = action_options;
+    if undo_replace {
+        if let Err(e) = undo_last_replace() {
+            eprintln!("Error: {}", e);
+        }
+        return;
+    }
+    // 输出到管道或文件时不带颜色，JSON和以NUL分隔的输出总是不带颜色
+    // 输出到终端时不强制开启，仍然遵循NO_COLOR和CLICOLOR等环境变量
+    if !atty::is(Stream::Stdout) || content_options.json || content_options.print0 {
+        colored::control::set_override(false);
+    }
+    let read_options =
+        match ReadOptions::new(&binary, encoding.as_deref()).and_then(|read_options| {
+            Ok(ReadOptions {
+                search_archives,
+                archive_max_size: parse_size_unit(&archive_max_size.to_lowercase())?
+                    .unwrap_or(u64::MAX),
+                ..read_options
+            })
+        }) {
+            Ok(read_options) => read_options,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        };
 
-    if has_pipe_input {
-        // 从管道读取内容并直接搜索
-        let stdin = io::stdin().lock();
-        for line in stdin.lines().filter_map(|line| line.ok()) {
-            let (matched, highlighted) = highlight_keyword(&line, &keyword, ignore_case, "", regex);
-            if matched {
-                println!("{}", highlighted);
+    // -l、-L和--count只针对文件内容
+    content_options.search_content |= content_options.file_level();
+
+    // 关键字只编译一次，所有文件和行共用
+    let matcher = match Matcher::new(&pattern_options) {
+        Ok(matcher) => matcher,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    // 筛选条件只解析一次，格式错误时直接报错
+    let query = Query::from_options(&filter_options.fields(), &filter_options.where_expr);
+    let filters = match query {
+        Ok(query) => SearchFilters { query },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let options = WalkOptions {
+        // 起点深度为0，max_depth为1时只搜索起点下的直接条目
+        max_depth: Some(max_depth),
+        min_depth,
+        follow_links: follow,
+        same_file_system: one_file_system,
+        hidden,
+        no_ignore,
+        sort,
+    };
+
+    let result_limit = ResultLimit::new(max_results);
+
+    if pattern_options.fuzzy {
+        fuzzy_search(
+            &paths,
+            &pattern_options,
+            &options,
+            &filters,
+            &content_options,
+        );
+        return;
+    }
+
+    let context = SearchContext {
+        matcher: &matcher,
+        content_options: &content_options,
+        read_options: &read_options,
+        filters: &filters,
+        limit: &result_limit,
+    };
+
+    if indexed {
+        for p in &paths {
+            if let Err(e) = indexed_search(Path::new(p), &context, max_depth, min_depth) {
+                eprintln!("Error: {}", e);
             }
         }
         return;
     }
 
+    if let Some(replacement) = replace {
+        // 非正则模式下替换内容按字面量处理，不展开$1等捕获组引用
+        let replacement = if pattern_options.regex && !pattern_options.fixed_strings {
+            replacement
+        } else {
+            replacement.replace('$', "$$")
+        };
+        let replace_options = ReplaceOptions {
+            replacement,
+            interactive,
+            backup,
+        };
+        if let Err(e) = replace_command(
+            &paths,
+            &matcher,
+            &replace_options,
+            &options,
+            &filters,
+            &read_options,
+        ) {
+            eprintln!("Error: {}", e);
+        }
+        return;
+    }
+
+    let template = match (exec, exec_batch) {
+        (Some(args), _) => Some(CommandTemplate::new(args, false)),
+        (None, Some(args)) => Some(CommandTemplate::new(args, true)),
+        (None, None) => None,
+    };
+    let template = match template.transpose() {
+        Ok(template) => template,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    // 检测是否有管道输入（标准输入非终端时视为管道），执行命令时总是搜索路径
+    let has_pipe_input = !atty::is(Stream::Stdin) && template.is_none();
+
+    if has_pipe_input {
+        // 从管道逐行读取内容并直接搜索
+        if let Err(e) = search_stdin(&matcher, &content_options, &read_options) {
+            eprintln!("Error reading stdin: {}", e);
+        }
+        return;
+    }
+
+    // 执行命令时不输出搜索结果，而是收集匹配的路径
+    let matched = template.as_ref().map(|_| Mutex::new(Vec::new()));
+
     // 无管道时使用命令行传入的路径进行搜索
     for p in paths {
         let path = Path::new(&p);
-        search_and_highlight(
-            path,
-            &keyword,
-            search_content,
-            regex,
-            ignore_case,
-            recursive_depth,
-            true,
-            &size,
-            &file_type,
-            &modified,
-            &accessed,
-            &created,
-            &permission,
-            &owner,
-            &group,
-        );
+        search_and_highlight(path, &context, &options, matched.as_ref());
+        if result_limit.reached() {
+            break;
+        }
+    }
+
+    if let (Some(template), Some(matched)) = (template, matched) {
+        let mut matched = matched.into_inner().unwrap();
+        matched.sort();
+        let jobs = jobs.unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        template.run(&matched, jobs);
     }
 }
 
-fn search_and_highlight(
-    path: &Path,
-    keyword: &str,
-    search_content: bool,
-    regex: bool,
-    ignore_case: bool,
-    depth: usize, // 修改参数名和类型
-    match_filename: bool,
-    size: &Option<String>,
-    file_type: &Option<String>,
-    modified: &Option<String>,
-    accessed: &Option<String>,
-    created: &Option<String>,
-    permission: &Option<String>,
-    owner: &Option<String>,
-    group: &Option<String>,
-) {
-    let paths_to_search = match fs::read_dir(path) {
-        Ok(entries) => entries
-            .filter_map(|entry| entry.ok())
-            .map(|entry| entry.path())
-            .collect(),
-        Err(_) => {
-            // 处理文件搜索
-            if path.is_file() {
-                vec![path.to_path_buf()]
-            } else {
-                vec![]
-            }
+/// 关键字的匹配选项
+pub struct PatternOptions {
+    /// 位置参数的关键字
+    pub keyword: Option<String>,
+    /// 关键字是正则表达式
+    pub regex: bool,
+    /// 忽略大小写
+    pub ignore_case: bool,
+    /// -e指定的关键字，与位置参数的关键字一起按“或”匹配
+    pub patterns: Vec<String>,
+    /// 文件内容中必须全部出现的关键字，只影响文件是否参与内容搜索
+    pub and_patterns: Vec<String>,
+    /// 关键字按字面量匹配，即使同时指定了正则模式
+    pub fixed_strings: bool,
+    /// 只匹配完整的单词
+    pub word: bool,
+    /// 对整个文件内容匹配，匹配可以跨行
+    pub multiline: bool,
+    /// 只搜索指定语言的文件，如rust、python
+    pub lang: Option<String>,
+    /// 只保留位于指定区域的匹配：comments、strings或code
+    pub region: Option<String>,
+    /// 模糊匹配路径，结果按得分排序
+    pub fuzzy: bool,
+    /// 模糊匹配时最多输出的结果数
+    pub limit: Option<usize>,
+}
+
+/// 编译好的匹配规则，所有文件共用
+pub struct Matcher {
+    pub pattern: Regex,
+    /// 按字面量匹配时各个关键字的三元组，用于按索引排除文件；正则模式下为None
+    literal_trigrams: Option<Vec<Vec<u32>>>,
+    and_patterns: Vec<Regex>,
+    multiline: bool,
+    syntax: Option<&'static SyntaxReference>,
+    region: Option<CodeRegion>,
+}
+
+impl Matcher {
+    fn new(pattern_options: &PatternOptions) -> anyhow::Result<Self> {
+        let regex = pattern_options.regex && !pattern_options.fixed_strings;
+        let ignore_case = pattern_options.ignore_case;
+        let mut keywords: Vec<String> = pattern_options.keyword.iter().cloned().collect();
+        keywords.extend(pattern_options.patterns.iter().cloned());
+        let build = |keywords: &[String]| {
+            build_pattern(
+                keywords,
+                regex,
+                ignore_case,
+                pattern_options.word,
+                pattern_options.multiline,
+            )
+        };
+        let and_patterns = pattern_options
+            .and_patterns
+            .iter()
+            .map(|p| build(std::slice::from_ref(p)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let syntax = match &pattern_options.lang {
+            Some(lang) => Some(
+                find_syntax(lang).ok_or_else(|| anyhow::anyhow!("Unknown language '{}'", lang))?,
+            ),
+            None => None,
+        };
+        let region = pattern_options
+            .region
+            .as_deref()
+            .map(CodeRegion::parse)
+            .transpose()?;
+        // 非ASCII字符的大小写不能只按字节转换，忽略大小写时这样的关键字不用三元组排除文件
+        let literal_trigrams = (!regex).then(|| {
+            keywords
+                .iter()
+                .map(|keyword| {
+                    if ignore_case && !keyword.is_ascii() {
+                        Vec::new()
+                    } else {
+                        trigrams_of(keyword.as_bytes())
+                    }
+                })
+                .collect()
+        });
+        Ok(Matcher {
+            pattern: build(&keywords)?,
+            literal_trigrams,
+            and_patterns,
+            multiline: pattern_options.multiline,
+            syntax,
+            region,
+        })
+    }
+
+    /// 指定了--lang时，只搜索该语言的文件
+    /// is_file为false时表示目录等不是文件的条目
+    pub(crate) fn accepts(&self, p: &Path, is_file: bool) -> bool {
+        let Some(syntax) = self.syntax else {
+            return true;
+        };
+        let ext = get_extension(p);
+        is_file
+            && syntax
+                .file_extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(&ext))
+    }
+
+    /// 根据索引中的三元组判断文件内容是否可能匹配，任意一个关键字的三元组都出现时才可能匹配
+    fn may_match(&self, may_contain: impl Fn(&[u32]) -> bool) -> bool {
+        match &self.literal_trigrams {
+            Some(keywords) => keywords.iter().any(|trigrams| may_contain(trigrams)),
+            None => true,
         }
-    };
+    }
 
-    // todo: 按照ls部分的着色逻辑来改写这部分
-    for p in paths_to_search {
-        // 添加文件筛选条件
-        let mut should_skip = false;
-
-        if let Some(size_str) = size {
-            if let Ok(passed) = crate::utils::utils::check_size_condition(&p, size_str) {
-                if !passed {
-                    should_skip = true;
-                }
+    /// 文件内容是否包含全部--and关键字
+    pub(crate) fn contains_all(&self, content: &str) -> bool {
+        self.and_patterns
+            .iter()
+            .all(|re| re.is_match(content).unwrap_or(false))
+    }
+
+    /// 是否需要读入全部内容后才能匹配，此时标准输入不能逐行流式搜索
+    fn needs_whole_content(&self) -> bool {
+        self.multiline || !self.and_patterns.is_empty() || self.region.is_some()
+    }
+
+    /// 内容对应的语法，优先使用--lang指定的语言
+    fn syntax_for(&self, path: Option<&Path>) -> Option<&'static SyntaxReference> {
+        self.syntax
+            .or_else(|| path.and_then(|p| find_syntax_by_extension(&get_extension(p))))
+    }
+
+    /// 找出每一行中的匹配范围，范围不包含行尾换行符
+    fn line_matches(
+        &self,
+        path: Option<&Path>,
+        content: &str,
+        lines: &[&str],
+    ) -> Vec<Vec<(usize, usize)>> {
+        if !self.contains_all(content) {
+            return vec![Vec::new(); lines.len()];
+        }
+        let mut line_matches = if self.multiline {
+            split_by_lines(&find_matches(&self.pattern, content), lines)
+        } else {
+            lines
+                .iter()
+                .map(|line| find_matches(&self.pattern, line.trim_end_matches(['\r', '\n'])))
+                .collect()
+        };
+        if let Some(region) = self.region {
+            // 没有对应语法的文件无法区分注释和字符串，不参与按区域的搜索
+            let Some(syntax) = self.syntax_for(path) else {
+                return vec![Vec::new(); lines.len()];
+            };
+            let mut classifier = RegionClassifier::new(syntax);
+            for (line, matches) in lines.iter().zip(line_matches.iter_mut()) {
+                let regions = classifier.classify_line(line);
+                // 按匹配起点所在的区域判断
+                matches.retain(|&(start, _)| {
+                    regions
+                        .iter()
+                        .any(|&(from, to, r)| r == region && from <= start && start < to)
+                });
             }
         }
+        line_matches
+    }
+}
 
-        if let Some(file_type_str) = file_type {
-            if let Ok(passed) = crate::utils::utils::check_file_type(&p, file_type_str) {
-                if !passed {
-                    should_skip = true;
-                }
+/// 把整个内容中的匹配范围拆分到各行，跨行的匹配在每一行中各占一段
+fn split_by_lines(matches: &[(usize, usize)], lines: &[&str]) -> Vec<Vec<(usize, usize)>> {
+    let mut line_matches = vec![Vec::new(); lines.len()];
+    let mut line_start = 0;
+    let mut starts = Vec::with_capacity(lines.len());
+    for line in lines {
+        starts.push(line_start);
+        line_start += line.len();
+    }
+    for &(start, end) in matches {
+        let first = starts.partition_point(|&s| s <= start).saturating_sub(1);
+        for (i, line) in lines.iter().enumerate().skip(first) {
+            let line_start = starts[i];
+            if line_start >= end && i > first {
+                break;
+            }
+            let text_end = line_start + line.trim_end_matches(['\r', '\n']).len();
+            let from = start.max(line_start);
+            let to = end.min(text_end);
+            if from < to {
+                line_matches[i].push((from - line_start, to - line_start));
             }
         }
+    }
+    line_matches
+}
 
-        if let Some(modified_str) = modified {
-            if let Ok(passed) =
-                crate::utils::utils::check_datetime_condition(&p, modified_str, "mtime")
-            {
-                if !passed {
-                    should_skip = true;
-                }
-            }
+/// 目录遍历和结果数量的选项
+pub struct TraversalOptions {
+    /// 最大搜索深度，起点下的直接条目深度为1
+    pub max_depth: usize,
+    /// 最小搜索深度，更浅的条目不输出
+    pub min_depth: usize,
+    /// 包含隐藏文件和隐藏目录
+    pub hidden: bool,
+    /// 不读取.gitignore、.ignore和.rtignore中的忽略规则
+    pub no_ignore: bool,
+    /// 按路径排序后输出，结果顺序稳定
+    pub sort: bool,
+    /// 跟随符号链接进入目录
+    pub follow: bool,
+    /// 不进入其他文件系统
+    pub one_file_system: bool,
+    /// 最多输出的结果数
+    pub max_results: Option<usize>,
+    /// 使用rt index建立的索引，不遍历目录
+    pub indexed: bool,
+}
+
+/// 按元数据筛选的条件，格式与--where中对应的字段相同
+pub struct FilterOptions {
+    pub size: Option<String>,
+    pub file_type: Option<String>,
+    pub modified: Option<String>,
+    pub accessed: Option<String>,
+    pub created: Option<String>,
+    pub newer: Option<String>,
+    pub older: Option<String>,
+    pub permission: Option<String>,
+    pub kind: Option<String>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// 筛选表达式
+    pub where_expr: Option<String>,
+}
+
+impl FilterOptions {
+    /// (字段名, 选项值)形式的筛选条件，交给Query::from_options
+    fn fields(&self) -> [(&'static str, &Option<String>); 11] {
+        [
+            ("size", &self.size),
+            ("type", &self.file_type),
+            ("mtime", &self.modified),
+            ("atime", &self.accessed),
+            ("ctime", &self.created),
+            ("newer", &self.newer),
+            ("older", &self.older),
+            ("perm", &self.permission),
+            ("kind", &self.kind),
+            ("owner", &self.owner),
+            ("group", &self.group),
+        ]
+    }
+}
+
+/// 读取文件内容的选项，解析后得到ReadOptions
+pub struct InputOptions {
+    /// 二进制文件的处理方式：skip、text或summary
+    pub binary: String,
+    /// 指定的编码，None表示自动检测
+    pub encoding: Option<String>,
+    /// 搜索压缩包中的文件
+    pub search_archives: bool,
+    /// 压缩包中单个条目解压后的最大大小，带单位
+    pub archive_max_size: String,
+}
+
+/// 对搜索结果执行的操作：执行命令或批量替换
+pub struct ActionOptions {
+    /// 对每个结果执行的命令
+    pub exec: Option<Vec<String>>,
+    /// 一次传入多个结果执行的命令
+    pub exec_batch: Option<Vec<String>>,
+    /// 同时执行的命令数，默认为CPU核数
+    pub jobs: Option<usize>,
+    /// 替换模板
+    pub replace: Option<String>,
+    /// 逐处确认修改
+    pub interactive: bool,
+    /// 备份被修改的文件
+    pub backup: bool,
+    /// 撤销上一次带--backup的替换
+    pub undo_replace: bool,
+}
+
+/// 内容搜索的输出选项（类似grep）
+pub struct ContentOptions {
+    /// 搜索文件内容，而不只是文件名
+    pub search_content: bool,
+    /// 匹配行之前显示的上下文行数
+    pub before_context: usize,
+    /// 匹配行之后显示的上下文行数
+    pub after_context: usize,
+    /// 只输出每个文件的匹配行数
+    pub count: bool,
+    /// 只输出有匹配的文件
+    pub files_with_matches: bool,
+    /// 只输出没有匹配的文件
+    pub files_without_match: bool,
+    /// 每个文件最多输出的匹配行数
+    pub max_count: Option<usize>,
+    /// 只输出匹配的部分，每个匹配一行
+    pub only_matching: bool,
+    /// 输出匹配所在的列号（从1开始的字节偏移）
+    pub column: bool,
+    /// 每个匹配输出一行JSON记录
+    pub json: bool,
+    /// 文件列表中的路径以NUL结尾，而不是换行
+    pub print0: bool,
+}
+
+impl ContentOptions {
+    /// 是否只按文件输出结果，此时不输出文件名匹配和具体的行
+    fn file_level(&self) -> bool {
+        self.count || self.files_with_matches || self.files_without_match
+    }
+
+    /// 文件列表中路径的结尾
+    fn path_terminator(&self) -> char {
+        if self.print0 { '\0' } else { '\n' }
+    }
+
+    /// 是否输出上下文行，只输出匹配部分或JSON时不输出
+    fn has_context(&self) -> bool {
+        !self.only_matching && !self.json && (self.before_context > 0 || self.after_context > 0)
+    }
+}
+
+/// 是否输出颜色，由search_command根据标准输出是否为终端设置
+fn use_color() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+/// search的筛选条件，只影响结果是否输出，不影响目录的遍历
+pub struct SearchFilters {
+    query: Option<Query>,
+}
+
+impl SearchFilters {
+    pub fn matches(&self, p: &Path) -> bool {
+        self.query.as_ref().is_none_or(|query| query.matches(p))
+    }
+
+    fn matches_info(&self, p: &Path, info: &FileInfo) -> bool {
+        self.query
+            .as_ref()
+            .is_none_or(|query| query.matches_info(p, info))
+    }
+}
+
+/// 一次搜索中所有条目共用的匹配规则、输出选项和筛选条件
+struct SearchContext<'a> {
+    matcher: &'a Matcher,
+    content_options: &'a ContentOptions,
+    read_options: &'a ReadOptions,
+    filters: &'a SearchFilters,
+    limit: &'a ResultLimit,
+}
+
+/// --max-results：限制输出的匹配条目数，多个起点共用一个计数
+struct ResultLimit {
+    max: Option<usize>,
+    found: AtomicUsize,
+}
+
+impl ResultLimit {
+    fn new(max: Option<usize>) -> Self {
+        ResultLimit {
+            max,
+            found: AtomicUsize::new(0),
         }
+    }
 
-        if let Some(accessed_str) = accessed {
-            if let Ok(passed) =
-                crate::utils::utils::check_datetime_condition(&p, accessed_str, "atime")
-            {
-                if !passed {
-                    should_skip = true;
-                }
-            }
+    /// 记录一个匹配的条目，已经达到上限时返回false，这个条目不应输出
+    fn take(&self) -> bool {
+        let found = self.found.fetch_add(1, Ordering::SeqCst);
+        self.max.is_none_or(|max| found < max)
+    }
+
+    fn reached(&self) -> bool {
+        self.max
+            .is_some_and(|max| self.found.load(Ordering::SeqCst) >= max)
+    }
+}
+
+fn search_and_highlight(
+    path: &Path,
+    context: &SearchContext,
+    options: &WalkOptions,
+    matched: Option<&Mutex<Vec<PathBuf>>>,
+) {
+    let SearchContext {
+        matcher,
+        content_options,
+        read_options,
+        filters,
+        limit,
+    } = *context;
+    // todo: 按照ls部分的着色逻辑来改写这部分
+    walk_while(path, options, |p, depth| {
+        if limit.reached() {
+            return false;
+        }
+        // 作为起点的目录本身不参与匹配
+        if depth == 0 && p.is_dir() {
+            return true;
         }
+        if !filters.matches(p) {
+            return true;
+        }
+        let is_file = p.is_file();
+        let archive = if read_options.search_archives && is_file {
+            ArchiveKind::detect(p)
+        } else {
+            None
+        };
 
-        if let Some(created_str) = created {
-            if let Ok(passed) =
-                crate::utils::utils::check_datetime_condition(&p, created_str, "ctime")
-            {
-                if !passed {
-                    should_skip = true;
-                }
+        // 并行遍历时先收集一个条目的全部输出，再一次性写出，避免不同文件的输出交错
+        let mut output = String::new();
+
+        if matcher.accepts(p, is_file) {
+            // 1. 处理文件名匹配逻辑
+            match_name(p, matcher, content_options, &mut output);
+
+            // 2. 处理文件内容搜索（仅当是文件且需要搜索内容时），压缩包本身的字节不搜索
+            if is_file && content_options.search_content && archive.is_none() {
+                search_file(p, matcher, content_options, read_options, &mut output);
             }
         }
-        if let Some(permission_str) = permission {
-            if let Ok(passed) = crate::utils::utils::check_permission(&p, permission_str) {
-                if !passed {
-                    should_skip = true;
-                }
-            }
+
+        // 3. 进入压缩包，对其中的每个文件做同样的匹配
+        if let Some(kind) = archive {
+            search_archive(p, kind, matcher, content_options, read_options, &mut output);
         }
 
-        if let Some(owner_str) = owner {
-            if let Ok(passed) = crate::utils::utils::check_owner(&p, owner_str) {
-                if !passed {
-                    should_skip = true;
-                }
+        if output.is_empty() {
+            return true;
+        }
+        // 有输出即表示这个条目匹配（-L时表示没有匹配的文件）
+        if !limit.take() {
+            return false;
+        }
+        match matched {
+            Some(matched) => matched.lock().unwrap().push(p.to_path_buf()),
+            None => {
+                let _ = io::stdout().lock().write_all(output.as_bytes());
             }
         }
+        !limit.reached()
+    });
+}
 
-        if let Some(group_str) = group {
-            if let Ok(passed) = crate::utils::utils::check_group(&p, group_str) {
-                if !passed {
-                    should_skip = true;
-                }
+/// 从索引中查找path之下的条目，文件名和筛选条件只使用索引中的数据，逐个目录按名称顺序输出
+fn indexed_search(
+    path: &Path,
+    context: &SearchContext,
+    max_depth: usize,
+    min_depth: usize,
+) -> anyhow::Result<()> {
+    let SearchContext {
+        matcher,
+        content_options,
+        read_options,
+        filters,
+        limit,
+    } = *context;
+    let index = find_index(path)?
+        .ok_or_else(|| anyhow::anyhow!("{} 没有索引，请先运行 rt index build", path.display()))?;
+    let base = std::fs::canonicalize(path)?;
+    let mut out = io::stdout().lock();
+    for (rel, depth, entry) in index.entries_under(&base) {
+        // 与遍历目录时相同：起点下的直接条目深度为1
+        if depth > max_depth || depth < min_depth {
+            continue;
+        }
+        let p = path.join(&rel);
+        let is_file = !entry.info.is_dir;
+        if !matcher.accepts(&p, is_file) || !filters.matches_info(&p, &entry.info) {
+            continue;
+        }
+        let mut output = String::new();
+        match_name(&p, matcher, content_options, &mut output);
+        // 建立索引后被修改过的文件不能用三元组排除
+        if is_file
+            && content_options.search_content
+            && (!entry.is_fresh(&p) || matcher.may_match(|trigrams| entry.may_contain(trigrams)))
+        {
+            search_file(&p, matcher, content_options, read_options, &mut output);
+        }
+        if output.is_empty() {
+            continue;
+        }
+        if !limit.take() {
+            break;
+        }
+        out.write_all(output.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// 模糊匹配起点之下的路径，全部遍历完后按得分从高到低输出，limit限制输出的数量
+fn fuzzy_search(
+    paths: &[String],
+    pattern_options: &PatternOptions,
+    options: &WalkOptions,
+    filters: &SearchFilters,
+    content_options: &ContentOptions,
+) {
+    let results: Mutex<Vec<(FuzzyMatch, String)>> = Mutex::new(Vec::new());
+    for root in paths {
+        let root = Path::new(root);
+        walk(root, options, |p, depth| {
+            if depth == 0 && p.is_dir() {
+                return;
+            }
+            if !filters.matches(p) {
+                return;
             }
+            // 只对起点之下的相对路径打分，起点本身的路径不影响得分
+            let relative = match p.strip_prefix(root) {
+                Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                _ => p,
+            };
+            let path = p.display().to_string();
+            let relative_len = relative.to_string_lossy().chars().count();
+            let offset = path.chars().count() - relative_len;
+            if let Some(mut m) = fuzzy_match(
+                pattern_options.keyword.as_deref().unwrap_or_default(),
+                &path.chars().skip(offset).collect::<String>(),
+                pattern_options.ignore_case,
+            ) {
+                m.positions.iter_mut().for_each(|i| *i += offset);
+                results.lock().unwrap().push((m, path));
+            }
+        });
+    }
+
+    let mut results = results.into_inner().unwrap();
+    // 得分相同时路径短的在前
+    results.sort_by(|(a, a_path), (b, b_path)| {
+        b.score
+            .cmp(&a.score)
+            .then(a_path.len().cmp(&b_path.len()))
+            .then(a_path.cmp(b_path))
+    });
+    if let Some(limit) = pattern_options.limit {
+        results.truncate(limit);
+    }
+
+    let mut out = io::stdout().lock();
+    for (m, path) in results {
+        let line = if content_options.json {
+            let record = json!({
+                "type": "fuzzy",
+                "path": path,
+                "score": m.score,
+                "positions": m.positions,
+            });
+            format!("{}\n", record)
+        } else {
+            format!(
+                "{}{}",
+                highlight_matches(&path, &char_ranges(&path, &m.positions), None),
+                content_options.path_terminator()
+            )
+        };
+        if out.write_all(line.as_bytes()).is_err() {
+            return;
         }
+    }
+}
 
-        if should_skip {
+/// 把字符位置转换为字节范围，相邻的字符合并为一个范围
+fn char_ranges(text: &str, positions: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut positions = positions.iter().peekable();
+    for (i, (start, c)) in text.char_indices().enumerate() {
+        if positions.peek() != Some(&&i) {
             continue;
         }
+        positions.next();
+        let end = start + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.1 == start => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+}
 
-        // 1. 处理文件名匹配逻辑
-        if match_filename {
-            if let Some(name) = p.file_name().and_then(|s| s.to_str()) {
-                // 调用高亮函数，同时获取匹配状态和高亮结果
-                let (name_matched, highlighted_name) =
-                    highlight_keyword(name, keyword, ignore_case, "", regex);
-                if name_matched {
-                    //显示前缀，区分文件和目录
-                    let prefix = if p.is_dir() { "" } else { "" };
-                    println!("{}{}", prefix, p.with_file_name(highlighted_name).display());
-                }
-                if depth > 0 && p.is_dir() {
-                    // 修改递归条件
-                    // 递归搜索子目录，深度减1
-                    search_and_highlight(
-                        &p,
-                        keyword,
-                        search_content,
-                        regex,
-                        ignore_case,
-                        depth - 1, // 深度递减
-                        true,
-                        size,
-                        file_type,
-                        modified,
-                        accessed,
-                        created,
-                        permission,
-                        owner,
-                        group,
-                    );
-                }
+/// 按文件名匹配，-l、-L、--count模式下不输出文件名匹配
+fn match_name(p: &Path, matcher: &Matcher, content_options: &ContentOptions, output: &mut String) {
+    if content_options.file_level() {
+        return;
+    }
+    let Some(name) = p.file_name().and_then(|s| s.to_str()) else {
+        return;
+    };
+    let matches = find_matches(&matcher.pattern, name);
+    if !matches.is_empty() {
+        output.push_str(&path_result(p, name, &matches, content_options));
+    }
+}
+
+/// 搜索压缩包中的文件，结果中的路径形如`archive.zip!/inner/path`
+fn search_archive(
+    p: &Path,
+    kind: ArchiveKind,
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    read_options: &ReadOptions,
+    output: &mut String,
+) {
+    let result = for_each_entry(p, kind, read_options.archive_max_size, |name, data| {
+        let entry_path = PathBuf::from(format!("{}!/{}", p.display(), name));
+        if !matcher.accepts(&entry_path, true) {
+            return;
+        }
+        match_name(&entry_path, matcher, content_options, output);
+        if content_options.search_content {
+            search_bytes(
+                Some(&entry_path),
+                data,
+                matcher,
+                content_options,
+                read_options,
+                output,
+            );
+        }
+    });
+    match result {
+        Ok(skipped) => {
+            for name in skipped {
+                eprintln!(
+                    "跳过 {}!/{}: 解压后超过--archive-max-size",
+                    p.display(),
+                    name
+                );
             }
         }
+        Err(e) => eprintln!("Error reading archive {}: {}", p.display(), e),
+    }
+}
 
-        // 2. 处理文件内容搜索（仅当是文件且需要搜索内容时）
-        if p.is_file() && search_content {
-            if let Ok(content) = fs::read_to_string(&p) {
-                let ext = get_extension(&p);
-                for (line_num, line) in content.lines().enumerate() {
-                    let (line_matched, highlighted_line) =
-                        highlight_keyword(line, keyword, ignore_case, &ext, regex);
-                    if line_matched {
-                        println!("{}:{} - {}", p.display(), line_num + 1, highlighted_line);
-                    }
+/// 文件名匹配的输出
+fn path_result(
+    p: &Path,
+    name: &str,
+    matches: &[(usize, usize)],
+    content_options: &ContentOptions,
+) -> String {
+    if content_options.json {
+        let submatches: Vec<serde_json::Value> = matches
+            .iter()
+            .map(|&(start, end)| json!({"text": &name[start..end], "start": start, "end": end}))
+            .collect();
+        let record = json!({
+            "type": "path",
+            "path": p.display().to_string(),
+            "submatches": submatches,
+            "metadata": file_metadata(Some(p)),
+        });
+        format!("{}\n", record)
+    } else {
+        let highlighted_name = highlight_matches(name, matches, None);
+        format!(
+            "{}{}",
+            p.with_file_name(highlighted_name).display(),
+            content_options.path_terminator()
+        )
+    }
+}
+
+/// 读取文件并逐行搜索，按二进制策略决定是否搜索其内容
+/// 小文件读入内存，大文件使用内存映射，无法映射时按块读取，都不会把解码后的整个文件保存在内存中
+fn search_file(
+    p: &Path,
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    read_options: &ReadOptions,
+    output: &mut String,
+) {
+    let Ok(bytes) = map_file(p) else {
+        return;
+    };
+    match &bytes {
+        Some(bytes) => search_source(
+            Some(p),
+            &Source::Bytes(bytes),
+            bytes,
+            matcher,
+            content_options,
+            read_options,
+            output,
+        ),
+        None => {
+            let Ok(head) = read_head(p) else {
+                return;
+            };
+            search_source(
+                Some(p),
+                &Source::File(p),
+                &head,
+                matcher,
+                content_options,
+                read_options,
+                output,
+            );
+        }
+    }
+}
+
+/// 搜索已经在内存中的内容（如压缩包中的文件），path为None时表示标准输入
+fn search_bytes(
+    p: Option<&Path>,
+    bytes: &[u8],
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    read_options: &ReadOptions,
+    output: &mut String,
+) {
+    search_source(
+        p,
+        &Source::Bytes(bytes),
+        bytes,
+        matcher,
+        content_options,
+        read_options,
+        output,
+    );
+}
+
+/// 按二进制策略和编码处理内容后再搜索
+/// head为内容开头的字节，用于检测编码和二进制
+fn search_source(
+    p: Option<&Path>,
+    source: &Source,
+    head: &[u8],
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    read_options: &ReadOptions,
+    output: &mut String,
+) {
+    let label = p.map_or("(standard input)".to_string(), |p| p.display().to_string());
+    let encoding = detect_encoding(head, read_options.encoding);
+    if !is_utf16(encoding) && is_binary(head) {
+        match read_options.binary {
+            BinaryPolicy::Skip => return,
+            BinaryPolicy::Summary if !content_options.file_level() => {
+                let mut found = false;
+                let _ = for_each_line(source, encoding, |line| {
+                    found = !find_matches(&matcher.pattern, line).is_empty();
+                    !found
+                });
+                if found {
+                    output.push_str(&format!("二进制文件 {} 有匹配\n", label));
                 }
+                return;
             }
+            _ => {}
         }
     }
+
+    let matched = if matcher.needs_whole_content() {
+        whole_content_matches(p, source, encoding, matcher, content_options)
+    } else {
+        streaming_matches(source, encoding, matcher, content_options)
+    };
+    let Ok(matched) = matched else {
+        return;
+    };
+    if content_options.file_level() {
+        output.push_str(&file_level_result(&label, matched.len(), content_options));
+        return;
+    }
+    if matched.is_empty() {
+        return;
+    }
+    let _ = print_matched_lines(
+        p,
+        source,
+        encoding,
+        &matched,
+        matcher,
+        content_options,
+        output,
+    );
 }
 
-/// 合并搜索与高亮逻辑，返回（是否匹配，高亮后的字符串）
-fn highlight_keyword(
-    line: &str,
-    keyword: &str,
-    ignore_case: bool,
-    ext: &str,
-    regex: bool,
-) -> (bool, String) {
-    // 加载默认的语法集和主题集
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    let mut is_highlight = true;
-
-    // 根据文件扩展名查找对应的语法，找不到时标记不高亮
-    let syntax = match ps.find_syntax_by_extension(ext) {
-        Some(s) => s,
-        None => {
-            is_highlight = false;
-            // 回退到纯文本语法（实际不会应用复杂高亮）
-            ps.find_syntax_plain_text()
+/// 一个匹配行：行号（从0开始）和行内的匹配范围
+type MatchedLine = (usize, Vec<(usize, usize)>);
+
+/// 逐行查找匹配行，不保存文件内容；-l、-L只需要知道是否有匹配，找到第一个就停止
+fn streaming_matches(
+    source: &Source,
+    encoding: &'static Encoding,
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+) -> io::Result<Vec<MatchedLine>> {
+    let stop_at_first = content_options.file_level() && !content_options.count;
+    let mut matched = Vec::new();
+    let mut line_num = 0;
+    for_each_line(source, encoding, |line| {
+        let matches = find_matches(&matcher.pattern, line.trim_end_matches(['\r', '\n']));
+        if !matches.is_empty() {
+            matched.push((line_num, matches));
         }
+        line_num += 1;
+        let max_reached = content_options
+            .max_count
+            .is_some_and(|max_count| matched.len() >= max_count);
+        !max_reached && (!stop_at_first || matched.is_empty())
+    })?;
+    Ok(matched)
+}
+
+/// 跨行匹配、--and和按区域搜索需要完整的内容，先解码整个文件找出匹配行，输出时再逐行读取
+fn whole_content_matches(
+    p: Option<&Path>,
+    source: &Source,
+    encoding: &'static Encoding,
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+) -> io::Result<Vec<MatchedLine>> {
+    let mut content = String::new();
+    for_each_line(source, encoding, |line| {
+        content.push_str(line);
+        true
+    })?;
+    let lines: Vec<&str> = LinesWithEndings::from(&content).collect();
+    let mut matched: Vec<MatchedLine> = matcher
+        .line_matches(p, &content, &lines)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, matches)| !matches.is_empty())
+        .collect();
+    if let Some(max_count) = content_options.max_count {
+        matched.truncate(max_count);
+    }
+    Ok(matched)
+}
+
+/// 再次逐行读取内容，输出匹配行和上下文行，path为None时表示标准输入，输出中不带文件名和行号
+/// 语法高亮需要从第一行开始逐行处理才能保留跨行的状态，读到最后一个要输出的行为止
+fn print_matched_lines(
+    path: Option<&Path>,
+    source: &Source,
+    encoding: &'static Encoding,
+    matched: &[MatchedLine],
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    output: &mut String,
+) -> io::Result<()> {
+    let last_match = matched.last().map_or(0, |(i, _)| *i);
+    let last_shown = if content_options.json || content_options.only_matching {
+        last_match
+    } else {
+        last_match + content_options.after_context
     };
+    let has_context = content_options.has_context();
+    let metadata = content_options.json.then(|| file_metadata(path));
+    let mut highlighter = matcher
+        .syntax_for(path)
+        .filter(|_| use_color() && !content_options.json)
+        .map(FileHighlighter::for_syntax);
+    let mut last_printed: Option<usize> = None;
+    // 第一个满足 行号+after_context >= 当前行 的匹配行，用来判断当前行是否在某个匹配的上下文中
+    let mut next = 0;
+    let mut line_num = 0;
+    let mut offset = 0;
 
-    // 创建 HighlightLines 实例（即使不高亮也需要实例，但实际不会应用复杂高亮）
-    let mut highlighter = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
+    for_each_line(source, encoding, |line| {
+        let current = line_num;
+        let line_offset = offset;
+        line_num += 1;
+        offset += line.len();
+        if current > last_shown {
+            return false;
+        }
+        let ranges = highlighter.as_mut().map(|h| h.highlight_line(line));
+        let text = line.trim_end_matches(['\r', '\n']);
+        let matches = matched
+            .binary_search_by_key(&current, |(i, _)| *i)
+            .ok()
+            .map(|k| &matched[k].1);
 
-    let mut highlighted = String::new();
-    let mut last_index = 0;
-    let mut has_match = false;
+        if let Some(metadata) = &metadata {
+            if let Some(matches) = matches {
+                output.push_str(&json_records(
+                    path,
+                    current,
+                    line_offset,
+                    text,
+                    matches,
+                    &matcher.pattern,
+                    metadata,
+                ));
+            }
+            return true;
+        }
 
-    if regex {
-        let pattern = if ignore_case {
-            Regex::new(&format!("(?i){}", keyword)).unwrap_or_else(|_| Regex::new("").unwrap())
+        while next < matched.len() && matched[next].0 + content_options.after_context < current {
+            next += 1;
+        }
+        let in_context =
+            next < matched.len() && matched[next].0 <= current + content_options.before_context;
+        let is_shown = if content_options.only_matching {
+            matches.is_some()
         } else {
-            Regex::new(keyword).unwrap_or_else(|_| Regex::new("").unwrap())
+            in_context
         };
+        if !is_shown {
+            return true;
+        }
+        // 不连续的上下文块之间用--分隔
+        if has_context
+            && let Some(last) = last_printed
+            && current > last + 1
+        {
+            output.push_str("--\n");
+        }
+        last_printed = Some(current);
 
-        for mat in pattern.find_iter(line) {
-            let mat = mat.expect("Failed to get match");
-            let start = mat.start();
-            let end = mat.end();
-
-            // 处理未匹配部分（根据 is_highlight 决定是否语法高亮）
-            let unmatched_part = &line[last_index..start];
-            if is_highlight {
-                let unmatched_ranges: Vec<(Style, &str)> = highlighter
-                    .highlight_line(unmatched_part, &ps)
-                    .unwrap_or_default();
-                highlighted.push_str(&as_24_bit_terminal_escaped(&unmatched_ranges[..], true));
-            } else {
-                highlighted.push_str(unmatched_part); // 无语法高亮时直接拼接原始文本
+        let Some(matches) = matches else {
+            let prefix = line_prefix(path, current, None, '-');
+            output.push_str(&format!(
+                "{}{}\n",
+                prefix,
+                highlight_matches(text, &[], ranges.as_deref())
+            ));
+            return true;
+        };
+        if content_options.only_matching {
+            for &(start, end) in matches {
+                let column = content_options.column.then_some(start + 1);
+                let prefix = line_prefix(path, current, column, ':');
+                output.push_str(&format!("{}{}\n", prefix, text[start..end].on_yellow()));
             }
-
-            // 处理匹配部分（始终保留黄色背景高亮）
-            let matched_part = &line[start..end];
-            highlighted.push_str(&matched_part.on_yellow().to_string());
-            last_index = end;
-            has_match = true;
+        } else {
+            let column = content_options.column.then_some(matches[0].0 + 1);
+            let prefix = line_prefix(path, current, column, ':');
+            output.push_str(&format!(
+                "{}{}\n",
+                prefix,
+                highlight_matches(text, matches, ranges.as_deref())
+            ));
         }
+        true
+    })
+}
+
+/// -l、-L、--count模式下一个文件的输出
+fn file_level_result(label: &str, matched: usize, content_options: &ContentOptions) -> String {
+    if content_options.files_with_matches && matched > 0
+        || content_options.files_without_match && matched == 0
+    {
+        format!("{}{}", label, content_options.path_terminator())
+    } else if content_options.count && matched > 0 {
+        format!("{}:{}\n", label, matched)
     } else {
-        let (search_line, search_keyword) = if ignore_case {
-            (line.to_lowercase(), keyword.to_lowercase())
+        String::new()
+    }
+}
+
+/// 逐行流式搜索标准输入，不把全部内容读入内存
+/// 标准输入没有文件名，输出中不带文件名和行号，也不做语法高亮
+fn search_stdin(
+    matcher: &Matcher,
+    content_options: &ContentOptions,
+    read_options: &ReadOptions,
+) -> io::Result<()> {
+    // 跨行匹配、--and和按区域搜索需要完整的内容
+    if matcher.needs_whole_content() {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        let mut output = String::new();
+        search_bytes(
+            None,
+            &bytes,
+            matcher,
+            content_options,
+            read_options,
+            &mut output,
+        );
+        return io::stdout().lock().write_all(output.as_bytes());
+    }
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(read_options.encoding)
+        .build(io::stdin().lock());
+    let mut reader = BufReader::new(decoder);
+    let mut out = io::stdout().lock();
+
+    let has_context = content_options.has_context();
+    let metadata = file_metadata(None);
+    // 匹配行之前的上下文，只保留最近的before_context行
+    let mut before: VecDeque<(usize, String)> = VecDeque::new();
+    let mut after_left = 0;
+    let mut matched = 0;
+    let mut last_printed: Option<usize> = None;
+    let mut buf = Vec::new();
+    // 当前行在解码后内容中的字节偏移
+    let mut offset = 0;
+
+    let mut print_line = |out: &mut io::StdoutLock,
+                          line_num: usize,
+                          offset: usize,
+                          text: &str,
+                          matches: &[(usize, usize)]| {
+        if content_options.json {
+            let records = json_records(
+                None,
+                line_num,
+                offset,
+                text,
+                matches,
+                &matcher.pattern,
+                &metadata,
+            );
+            return write!(out, "{}", records);
+        }
+        if has_context
+            && let Some(last) = last_printed
+            && line_num > last + 1
+        {
+            writeln!(out, "--")?;
+        }
+        last_printed = Some(line_num);
+        if content_options.only_matching {
+            for &(start, end) in matches {
+                writeln!(out, "{}", text[start..end].on_yellow())?;
+            }
+            Ok(())
         } else {
-            (line.to_string(), keyword.to_string())
-        };
+            writeln!(out, "{}", highlight_matches(text, matches, None))
+        }
+    };
 
-        while let Some(start) = search_line[last_index..].find(&search_keyword) {
-            let start = last_index + start;
-            let end = start + keyword.len();
-
-            // 处理未匹配部分（根据 is_highlight 决定是否语法高亮）
-            let unmatched_part = &line[last_index..start];
-            if is_highlight {
-                let unmatched_ranges: Vec<(Style, &str)> = highlighter
-                    .highlight_line(unmatched_part, &ps)
-                    .unwrap_or_default();
-                highlighted.push_str(&as_24_bit_terminal_escaped(&unmatched_ranges[..], true));
-            } else {
-                highlighted.push_str(unmatched_part); // 无语法高亮时直接拼接原始文本
+    for line_num in 0.. {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        if buf.contains(&0) && read_options.binary != BinaryPolicy::Text {
+            if read_options.binary == BinaryPolicy::Summary {
+                writeln!(out, "(standard input): 检测到二进制内容，已停止搜索")?;
             }
+            break;
+        }
+        let line_offset = offset;
+        offset += buf.len();
+        let line = String::from_utf8_lossy(&buf);
+        let text = line.trim_end_matches(['\r', '\n']);
 
-            // 处理匹配部分（始终保留黄色背景高亮）
-            let matched_part = &line[start..end];
-            highlighted.push_str(&matched_part.on_yellow().to_string());
-            last_index = end;
-            has_match = true;
+        let max_reached = content_options
+            .max_count
+            .is_some_and(|max_count| matched >= max_count);
+        let matches = if max_reached {
+            Vec::new()
+        } else {
+            find_matches(&matcher.pattern, text)
+        };
+
+        if !matches.is_empty() {
+            matched += 1;
+            if content_options.file_level() {
+                continue;
+            }
+            while let Some((num, context)) = before.pop_front() {
+                if !content_options.only_matching {
+                    print_line(&mut out, num, 0, &context, &[])?;
+                }
+            }
+            print_line(&mut out, line_num, line_offset, text, &matches)?;
+            after_left = content_options.after_context;
+        } else if after_left > 0 && !content_options.file_level() {
+            after_left -= 1;
+            if !content_options.only_matching {
+                print_line(&mut out, line_num, line_offset, text, &[])?;
+            }
+        } else if max_reached {
+            break;
+        } else if content_options.before_context > 0 {
+            if before.len() == content_options.before_context {
+                before.pop_front();
+            }
+            before.push_back((line_num, text.to_string()));
         }
     }
 
-    // 处理剩余未匹配的文本（根据 is_highlight 决定是否语法高亮）
-    let remaining_part = &line[last_index..];
-    if is_highlight {
-        let remaining_ranges: Vec<(Style, &str)> = highlighter
-            .highlight_line(remaining_part, &ps)
+    if content_options.file_level() {
+        write!(
+            out,
+            "{}",
+            file_level_result("(standard input)", matched, content_options)
+        )?;
+    }
+    Ok(())
+}
+
+/// 一行中每个匹配各输出一条JSON记录
+/// 行号和列号从1开始，start、end是匹配在（解码后的）内容中的字节偏移，
+/// submatches是匹配中各个捕获组的内容和在行内的字节范围
+fn json_records(
+    path: Option<&Path>,
+    line_num: usize,
+    line_offset: usize,
+    text: &str,
+    matches: &[(usize, usize)],
+    pattern: &Regex,
+    metadata: &serde_json::Value,
+) -> String {
+    let mut records = String::new();
+    for &(start, end) in matches {
+        // 跨行匹配拆分到各行后，只有完整落在一行内的匹配才有捕获组
+        let submatches: Vec<serde_json::Value> = pattern
+            .captures_from_pos(text, start)
+            .ok()
+            .flatten()
+            .filter(|caps| {
+                caps.get(0)
+                    .is_some_and(|m| m.start() == start && m.end() == end)
+            })
+            .map(|caps| {
+                caps.iter()
+                    .skip(1)
+                    .flatten()
+                    .map(|m| json!({"text": m.as_str(), "start": m.start(), "end": m.end()}))
+                    .collect()
+            })
             .unwrap_or_default();
-        highlighted.push_str(&as_24_bit_terminal_escaped(&remaining_ranges[..], true));
-    } else {
-        highlighted.push_str(remaining_part); // 无语法高亮时直接拼接原始文本
+        let record = json!({
+            "type": "match",
+            "path": path.map(|p| p.display().to_string()),
+            "line": line_num + 1,
+            "column": start + 1,
+            "start": line_offset + start,
+            "end": line_offset + end,
+            "text": &text[start..end],
+            "line_text": text,
+            "submatches": submatches,
+            "metadata": metadata,
+        });
+        records.push_str(&format!("{}\n", record));
     }
+    records
+}
+
+/// JSON记录中的文件元数据，标准输入没有元数据
+fn file_metadata(path: Option<&Path>) -> serde_json::Value {
+    let Some(metadata) = path.and_then(|p| p.metadata().ok()) else {
+        return serde_json::Value::Null;
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .map(|t| chrono::DateTime::<chrono::Local>::from(t).to_rfc3339());
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(format!("{:o}", metadata.permissions().mode() & 0o7777))
+    };
+    #[cfg(not(unix))]
+    let mode: Option<String> = None;
+    json!({
+        "size": metadata.len(),
+        "modified": modified,
+        "mode": mode,
+        "readonly": metadata.permissions().readonly(),
+    })
+}
 
-    (has_match, highlighted)
+/// 结果行的前缀，如`path:12 - `、`path:12:5 - `；上下文行使用`-`代替`:`，如`path-11 - `
+fn line_prefix(
+    path: Option<&Path>,
+    line_num: usize,
+    column: Option<usize>,
+    separator: char,
+) -> String {
+    let Some(p) = path else {
+        return String::new();
+    };
+    match column {
+        Some(column) => format!(
+            "{}{}{}{}{} - ",
+            p.display(),
+            separator,
+            line_num + 1,
+            separator,
+            column
+        ),
+        None => format!("{}{}{} - ", p.display(), separator, line_num + 1),
+    }
+}
+
+/// 根据关键字构造正则，多个关键字之间是“或”的关系，非正则模式下按字面量匹配
+fn build_pattern(
+    keywords: &[String],
+    regex: bool,
+    ignore_case: bool,
+    word: bool,
+    multiline: bool,
+) -> anyhow::Result<Regex> {
+    let alternatives: Vec<String> = keywords
+        .iter()
+        .map(|keyword| {
+            if regex {
+                format!("(?:{})", keyword)
+            } else {
+                fancy_regex::escape(keyword).to_string()
+            }
+        })
+        .collect();
+    let mut pattern = alternatives.join("|");
+    if word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    // 跨行匹配时^和$匹配每一行的开头和结尾
+    if multiline {
+        pattern = format!("(?m){}", pattern);
+    }
+    if ignore_case {
+        pattern = format!("(?i){}", pattern);
+    }
+    Regex::new(&pattern).map_err(|e| anyhow::anyhow!("Invalid regex '{}': {}", pattern, e))
+}
+
+/// 返回一行中所有匹配的字节范围
+pub fn find_matches(pattern: &Regex, line: &str) -> Vec<(usize, usize)> {
+    pattern
+        .find_iter(line)
+        .filter_map(|m| m.ok())
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+/// 高亮一行：匹配部分使用黄色背景，其余部分使用语法高亮的结果
+/// ranges为这一行的语法高亮结果（可以包含行尾换行符），为None时不做语法高亮
+fn highlight_matches(
+    line: &str,
+    matches: &[(usize, usize)],
+    ranges: Option<&[(Style, &str)]>,
+) -> String {
+    let mut highlighted = String::new();
+    let mut last_index = 0;
+    for &(start, end) in matches {
+        push_unmatched(&mut highlighted, line, last_index, start, ranges);
+        highlighted.push_str(&line[start..end].on_yellow().to_string());
+        last_index = end;
+    }
+    push_unmatched(&mut highlighted, line, last_index, line.len(), ranges);
+    highlighted
+}
+
+/// 把line[start..end]按语法高亮结果着色后追加到highlighted
+fn push_unmatched(
+    highlighted: &mut String,
+    line: &str,
+    start: usize,
+    end: usize,
+    ranges: Option<&[(Style, &str)]>,
+) {
+    if start >= end {
+        return;
+    }
+    let Some(ranges) = ranges else {
+        // 无语法高亮时直接拼接原始文本
+        highlighted.push_str(&line[start..end]);
+        return;
+    };
+    // 取出与[start, end)重叠的语法片段
+    let mut offset = 0;
+    let mut pieces: Vec<(Style, &str)> = Vec::new();
+    for &(style, text) in ranges {
+        let piece_start = offset.max(start);
+        let piece_end = (offset + text.len()).min(end);
+        if piece_start < piece_end {
+            pieces.push((style, &line[piece_start..piece_end]));
+        }
+        offset += text.len();
+        if offset >= end {
+            break;
+        }
+    }
+    highlighted.push_str(&as_24_bit_terminal_escaped(&pieces[..], true));
 }
@@ -0,0 +1,321 @@
+use crate::rename::rename::{get_metadata, wait_for_yes_no};
+use crate::utils::query::Query;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use colored::Colorize;
+use fancy_regex::Regex;
+use filetime::FileTime;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 根据元数据、文件名或固定值设置文件的mtime/atime
+/// # 参数
+/// - `paths`: 要处理的文件或目录（目录只处理其中的文件，不递归）
+/// - `from`: 时间来源，格式见`parse_time_spec`
+/// - `only`: 只修改mtime或atime，默认两者都修改
+/// - `filters`: (字段名, 选项值)形式的筛选条件，与search的--size、--file-type等相同
+/// - `undo`: 撤销上一次的修改
+pub fn touch_command(
+    paths: Vec<String>,
+    from: Option<String>,
+    only: Option<String>,
+    filters: &[(&str, &Option<String>)],
+    undo: bool,
+) -> Result<bool> {
+    if undo {
+        return undo_last_touch();
+    }
+    let from = from.ok_or(anyhow!("缺少时间来源，请使用 --from 指定"))?;
+    let (set_mtime, set_atime) = match only.as_deref() {
+        None => (true, true),
+        Some("mtime") => (true, false),
+        Some("atime") => (false, true),
+        Some(other) => return Err(anyhow!("Invalid --only value: {}", other)),
+    };
+    let spec = TimeSpec::parse(&from)?;
+    let query = Query::from_options(filters, &None)?;
+
+    let mut files = Vec::new();
+    for p in paths {
+        let path = Path::new(&p);
+        if path.is_dir() {
+            for entry in fs::read_dir(path)?.filter_map(|e| e.ok()) {
+                if entry.path().is_file() {
+                    files.push(entry.path());
+                }
+            }
+        } else if path.is_file() {
+            files.push(path.to_path_buf());
+        } else {
+            println!("文件不存在: {}", path.display());
+        }
+    }
+    files.sort();
+
+    let selected = files
+        .into_iter()
+        .filter(|file| query.as_ref().is_none_or(|query| query.matches(file)))
+        .collect::<Vec<PathBuf>>();
+
+    // 预览
+    let mut changes = Vec::new();
+    println!("修改时间:");
+    for file in selected {
+        let old_mtime = FileTime::from_last_modification_time(&file.metadata()?);
+        match spec.resolve(&file) {
+            Ok(new_time) => {
+                println!(
+                    "{}: {} -> {}",
+                    file.display(),
+                    format_file_time(old_mtime),
+                    new_time.format("%Y-%m-%d %H:%M:%S").to_string().green()
+                );
+                changes.push((file, new_time));
+            }
+            Err(e) => {
+                println!("{}: {}", file.display(), format!("跳过 ({})", e).red());
+            }
+        }
+    }
+    if changes.is_empty() {
+        println!("Can not find the file!");
+        return Err(anyhow!("Can not find the file!"));
+    }
+    if !wait_for_yes_no("修改时间") {
+        return Ok(false);
+    }
+
+    // 先保存撤销记录，再修改
+    let mut journal = Vec::new();
+    for (file, _) in changes.iter() {
+        journal.push(TouchRecord::from_path(file)?);
+    }
+    save_journal(&journal)?;
+
+    let mut success = true;
+    for (file, new_time) in changes.iter() {
+        let file_time = FileTime::from_unix_time(new_time.timestamp(), new_time.timestamp_subsec_nanos());
+        let res = match (set_mtime, set_atime) {
+            (true, true) => filetime::set_file_times(file, file_time, file_time),
+            (true, false) => filetime::set_file_mtime(file, file_time),
+            _ => filetime::set_file_atime(file, file_time),
+        };
+        if let Err(e) = res {
+            println!("修改失败: {}: {}", file.display(), e);
+            success = false;
+        }
+    }
+    Ok(success)
+}
+
+/// 时间来源
+enum TimeSource {
+    /// 文件当前的mtime，只和偏移量一起使用，如"+2h"
+    Current,
+    /// 从文件名中解析日期，如IMG_20230501_123456.jpg
+    FileName,
+    /// 元数据键，如{image:create_date}、{video:creation_time}
+    Metadata(String),
+    /// 固定时间
+    Fixed(DateTime<Local>),
+}
+
+/// 时间规则：时间来源 + 偏移量
+/// 格式：[source][offset]
+/// - source: {image:create_date}、{video:creation_time}、{name}、固定时间（如2024-01-01 12:00:00），省略时为文件当前的mtime
+/// - offset: 如+2h、-1d30M，单位可以是w、d、h、M、s，表示周、日、时、分、秒
+///
+/// 例如：{image:create_date}-8h 表示使用图片拍摄时间并往前调整8小时
+struct TimeSpec {
+    source: TimeSource,
+    offset: Duration,
+}
+
+impl TimeSpec {
+    fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let re_offset = Regex::new(r"^(.*?)([+-](?:\d+[wdhMs])+)$")?;
+        let (source_str, offset) = match re_offset.captures(spec)? {
+            Some(caps) => (
+                caps.get(1).unwrap().as_str().trim(),
+                parse_offset(caps.get(2).unwrap().as_str())?,
+            ),
+            None => (spec, Duration::zero()),
+        };
+        let source = if source_str.is_empty() {
+            TimeSource::Current
+        } else if source_str == "{name}" {
+            TimeSource::FileName
+        } else if source_str.starts_with('{') && source_str.ends_with('}') {
+            TimeSource::Metadata(source_str.to_string())
+        } else {
+            TimeSource::Fixed(parse_datetime(source_str)?)
+        };
+        Ok(TimeSpec { source, offset })
+    }
+
+    /// 计算文件的新时间
+    fn resolve(&self, path: &Path) -> Result<DateTime<Local>> {
+        let base = match &self.source {
+            TimeSource::Current => {
+                let mtime: DateTime<Local> = path.metadata()?.modified()?.into();
+                mtime
+            }
+            TimeSource::FileName => {
+                let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                parse_date_from_name(name).ok_or(anyhow!("文件名中没有日期"))?
+            }
+            TimeSource::Metadata(key) => {
+                let value = get_metadata(path, key).ok_or(anyhow!("没有元数据 {}", key))?;
+                parse_datetime(&value)?
+            }
+            TimeSource::Fixed(time) => *time,
+        };
+        Ok(base + self.offset)
+    }
+}
+
+/// 解析偏移量，如"+2h"、"-1d30M"
+fn parse_offset(offset_str: &str) -> Result<Duration> {
+    let negative = offset_str.starts_with('-');
+    let re = Regex::new(r"(\d+)([wdhMs])")?;
+    let mut total = Duration::zero();
+    for caps in re.captures_iter(&offset_str[1..]) {
+        let caps = caps?;
+        let num = caps.get(1).unwrap().as_str().parse::<i64>()?;
+        total += match caps.get(2).unwrap().as_str() {
+            "w" => Duration::weeks(num),
+            "d" => Duration::days(num),
+            "h" => Duration::hours(num),
+            "M" => Duration::minutes(num),
+            _ => Duration::seconds(num),
+        };
+    }
+    Ok(if negative { -total } else { total })
+}
+
+/// 解析时间字符串，支持RFC3339、EXIF格式以及本地时间
+/// 例如：2024-01-01、2024-01-01 12:00、2024-01-01 12:00:00、2024:01:01 12:00:00、2024-01-01T12:00:00+08:00
+fn parse_datetime(time_str: &str) -> Result<DateTime<Local>> {
+    let time_str = time_str.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(time_str) {
+        return Ok(time.with_timezone(&Local));
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y:%m:%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(time_str, format) {
+            return naive_to_local(naive);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(time_str, "%Y-%m-%d") {
+        return naive_to_local(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    Err(anyhow!("Invalid datetime format: {}", time_str))
+}
+
+fn naive_to_local(naive: NaiveDateTime) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or(anyhow!("Invalid local time: {}", naive))
+}
+
+/// 从文件名中解析日期
+/// 支持常见的相机、手机截图命名，如IMG_20230501_123456、2023-05-01 12.30.00、Screenshot_2023-05-01-12-30-00
+fn parse_date_from_name(name: &str) -> Option<DateTime<Local>> {
+    let re = Regex::new(
+        r"(?<!\d)(\d{4})[-_.]?(\d{2})[-_.]?(\d{2})(?:[-_. T]?(\d{2})[-_.:]?(\d{2})[-_.:]?(\d{2}))?(?!\d)",
+    )
+    .ok()?;
+    for caps in re.captures_iter(name) {
+        let caps = match caps {
+            Ok(caps) => caps,
+            Err(_) => continue,
+        };
+        let num = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+        let date = match NaiveDate::from_ymd_opt(num(1)? as i32, num(2)?, num(3)?) {
+            Some(date) => date,
+            None => continue,
+        };
+        let time = date.and_hms_opt(num(4).unwrap_or(0), num(5).unwrap_or(0), num(6).unwrap_or(0));
+        if let Some(time) = time {
+            return naive_to_local(time).ok();
+        }
+    }
+    None
+}
+
+fn format_file_time(time: FileTime) -> String {
+    DateTime::from_timestamp(time.unix_seconds(), time.nanoseconds())
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// 撤销记录：修改前的时间
+#[derive(Serialize, Deserialize, Debug)]
+struct TouchRecord {
+    path: PathBuf,
+    mtime: (i64, u32),
+    atime: (i64, u32),
+}
+
+impl TouchRecord {
+    fn from_path(path: &Path) -> Result<Self> {
+        let metadata = path.metadata()?;
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        let atime = FileTime::from_last_access_time(&metadata);
+        Ok(TouchRecord {
+            path: fs::canonicalize(path)?,
+            mtime: (mtime.unix_seconds(), mtime.nanoseconds()),
+            atime: (atime.unix_seconds(), atime.nanoseconds()),
+        })
+    }
+}
+
+fn get_journal_path() -> Result<PathBuf> {
+    let exe_path = std::env::current_exe()?;
+    let exe_dir = exe_path
+        .parent()
+        .ok_or(anyhow!("Failed to get executable directory"))?;
+    Ok(exe_dir.join("touch_undo.json"))
+}
+
+fn save_journal(journal: &[TouchRecord]) -> Result<()> {
+    let json = serde_json::to_string_pretty(journal)?;
+    fs::write(get_journal_path()?, json)?;
+    Ok(())
+}
+
+/// 撤销上一次touch的修改
+fn undo_last_touch() -> Result<bool> {
+    let journal_path = get_journal_path()?;
+    let contents = fs::read_to_string(&journal_path).map_err(|_| anyhow!("没有可撤销的记录"))?;
+    let journal: Vec<TouchRecord> = serde_json::from_str(&contents)?;
+    println!("恢复时间:");
+    for record in journal.iter() {
+        println!(
+            "{}: {}",
+            record.path.display(),
+            format_file_time(FileTime::from_unix_time(record.mtime.0, record.mtime.1)).green()
+        );
+    }
+    if !wait_for_yes_no("撤销") {
+        return Ok(false);
+    }
+    let mut success = true;
+    for record in journal.iter() {
+        let mtime = FileTime::from_unix_time(record.mtime.0, record.mtime.1);
+        let atime = FileTime::from_unix_time(record.atime.0, record.atime.1);
+        if let Err(e) = filetime::set_file_times(&record.path, atime, mtime) {
+            println!("恢复失败: {}: {}", record.path.display(), e);
+            success = false;
+        }
+    }
+    fs::remove_file(journal_path)?;
+    Ok(success)
+}
@@ -0,0 +1,146 @@
+use lazy_static::lazy_static;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Style, Theme, ThemeSet};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+
+/// 默认使用的高亮主题
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// 语法集和主题集，加载一次的开销较大，整个进程共用一份
+pub struct HighlightContext {
+    pub syntax_set: SyntaxSet,
+    pub theme_set: ThemeSet,
+}
+
+lazy_static! {
+    static ref HIGHLIGHT_CONTEXT: HighlightContext = HighlightContext {
+        syntax_set: SyntaxSet::load_defaults_newlines(),
+        theme_set: ThemeSet::load_defaults(),
+    };
+}
+
+/// 获取进程内共享的高亮上下文，第一次调用时才加载
+pub fn highlight_context() -> &'static HighlightContext {
+    &HIGHLIGHT_CONTEXT
+}
+
+impl HighlightContext {
+    pub fn theme(&self) -> &Theme {
+        &self.theme_set.themes[DEFAULT_THEME]
+    }
+}
+
+/// 单个文件的高亮器
+/// 在行与行之间保留解析状态，块注释、多行字符串等跨行结构也能正确着色，
+/// 因此同一个文件的每一行都要按顺序交给同一个FileHighlighter
+pub struct FileHighlighter {
+    highlighter: HighlightLines<'static>,
+}
+
+impl FileHighlighter {
+    /// 根据扩展名创建高亮器，找不到对应语法时返回None
+    pub fn for_extension(ext: &str) -> Option<Self> {
+        let syntax = highlight_context()
+            .syntax_set
+            .find_syntax_by_extension(ext)?;
+        Some(Self::for_syntax(syntax))
+    }
+
+    pub fn for_syntax(syntax: &'static SyntaxReference) -> Self {
+        FileHighlighter {
+            highlighter: HighlightLines::new(syntax, highlight_context().theme()),
+        }
+    }
+
+    /// 高亮一行，line需要包含行尾的换行符
+    pub fn highlight_line<'a>(&mut self, line: &'a str) -> Vec<(Style, &'a str)> {
+        self.highlighter
+            .highlight_line(line, &highlight_context().syntax_set)
+            .unwrap_or_default()
+    }
+}
+
+/// 按语言名（如rust、python）或扩展名（如rs、py）查找语法
+pub fn find_syntax(lang: &str) -> Option<&'static SyntaxReference> {
+    highlight_context().syntax_set.find_syntax_by_token(lang)
+}
+
+/// 根据扩展名查找语法
+pub fn find_syntax_by_extension(ext: &str) -> Option<&'static SyntaxReference> {
+    highlight_context().syntax_set.find_syntax_by_extension(ext)
+}
+
+/// 代码中的区域，按syntect解析出的作用域划分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeRegion {
+    Code,
+    Comment,
+    String,
+}
+
+impl CodeRegion {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "code" => Ok(CodeRegion::Code),
+            "comment" | "comments" => Ok(CodeRegion::Comment),
+            "string" | "strings" => Ok(CodeRegion::String),
+            _ => Err(anyhow::anyhow!(
+                "Invalid region '{}', expected comments, strings or code",
+                s
+            )),
+        }
+    }
+}
+
+/// 逐行解析代码，划分出注释、字符串和其他代码所在的范围
+/// 与FileHighlighter一样，同一个文件的每一行都要按顺序交给同一个RegionClassifier
+pub struct RegionClassifier {
+    parse_state: ParseState,
+    stack: ScopeStack,
+    comment: Scope,
+    string: Scope,
+}
+
+impl RegionClassifier {
+    pub fn new(syntax: &SyntaxReference) -> Self {
+        RegionClassifier {
+            parse_state: ParseState::new(syntax),
+            stack: ScopeStack::new(),
+            comment: Scope::new("comment").unwrap(),
+            string: Scope::new("string").unwrap(),
+        }
+    }
+
+    /// 返回一行中各个区域的字节范围[start, end)，line需要包含行尾的换行符
+    pub fn classify_line(&mut self, line: &str) -> Vec<(usize, usize, CodeRegion)> {
+        let ops = self
+            .parse_state
+            .parse_line(line, &highlight_context().syntax_set)
+            .unwrap_or_default();
+        let mut regions = Vec::new();
+        let mut last = 0;
+        for (pos, op) in ops {
+            if pos > last {
+                regions.push((last, pos, self.current()));
+                last = pos;
+            }
+            let _ = self.stack.apply(&op);
+        }
+        if last < line.len() {
+            regions.push((last, line.len(), self.current()));
+        }
+        regions
+    }
+
+    /// 当前作用域栈所在的区域，注释优先于字符串（如注释中的引号）
+    fn current(&self) -> CodeRegion {
+        let scopes = self.stack.as_slice();
+        if scopes.iter().any(|s| self.comment.is_prefix_of(*s)) {
+            CodeRegion::Comment
+        } else if scopes.iter().any(|s| self.string.is_prefix_of(*s)) {
+            CodeRegion::String
+        } else {
+            CodeRegion::Code
+        }
+    }
+}
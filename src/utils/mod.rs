@@ -1 +1,3 @@
+pub mod highlight;
+pub mod query;
 pub mod utils;
\ No newline at end of file
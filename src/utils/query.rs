@@ -0,0 +1,681 @@
+use crate::utils::utils::{
+    FileInfo, datetime_matches, file_type_matches, get_extension, group_matches, kind_matches,
+    owner_matches, parse_kinds, parse_permission, parse_size_condition, parse_size_unit,
+    permission_matches, property_matches, size_matches, validate_datetime_condition,
+    validate_file_type,
+};
+use anyhow::{Result, anyhow};
+use fancy_regex::Regex;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// 筛选表达式，例如：
+/// `(type:image or type:video) and size>10m and not owner:root and mtime:this_month`
+///
+/// 语法：
+/// - 条件：`字段 操作符 值`，值中有空格时可以用引号包含，如`name:"my file*"`
+/// - 组合：`and`（或`&&`）、`or`（或`||`）、`not`（或`!`），优先级 not > and > or，可以用括号改变优先级
+///
+/// 支持的字段：
+/// - size: `size:100k-200m`（与--size格式相同），`size>10m`、`size>=1k`、`size<1g`、`size<=100`
+/// - type: 文件类型，与--file-type格式相同，如`type:image`
+/// - mtime、atime、ctime: 时间，与--modified格式相同，如`mtime:this_month`、`mtime:2024y`，
+///   相对时间可以直接用比较，如`mtime<3d`（3天以内）、`mtime>2w`（2周以前）、`mtime:"1h..6h ago"`
+/// - newer、older: 修改时间比另一个文件新或旧，如`newer:Cargo.lock`
+/// - perm: 权限，与--permission格式相同，如`perm:rwxr-xr-x`、`perm:755`，`perm:+--x--x--x`表示包含这些权限
+/// - kind: 条目类型，file、dir、symlink、socket、fifo、block、char、device，如`kind:symlink`
+/// - is: 属性，empty、broken、hardlink、setuid、setgid、sticky，如`is:empty`、`is:broken`
+/// - nlink: 硬链接数，支持比较，如`nlink>1`
+/// - owner、group: 所有者和所属组，用户名/组名或者uid/gid
+/// - name: 文件名，支持*和?通配符，如`name:*.rs`
+/// - ext: 扩展名，不区分大小写，如`ext:jpg`
+#[derive(Debug, Clone)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Condition(Condition),
+}
+
+/// 单个筛选条件
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Size(String),
+    SizeCompare(CompareOp, u64),
+    FileType(String),
+    Time(&'static str, String),
+    Newer(SystemTime),
+    Older(SystemTime),
+    Permission(String),
+    Kind(String),
+    Property(String),
+    NlinkCompare(CompareOp, u64),
+    Owner(String),
+    Group(String),
+    Name(Regex),
+    Extension(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// `:`或`=`
+    Match,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+impl CompareOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Match => ":",
+            CompareOp::Greater => ">",
+            CompareOp::GreaterEqual => ">=",
+            CompareOp::Less => "<",
+            CompareOp::LessEqual => "<=",
+        }
+    }
+
+    fn compare(&self, actual: u64, expected: u64) -> bool {
+        match self {
+            CompareOp::Match => actual == expected,
+            CompareOp::Greater => actual > expected,
+            CompareOp::GreaterEqual => actual >= expected,
+            CompareOp::Less => actual < expected,
+            CompareOp::LessEqual => actual <= expected,
+        }
+    }
+}
+
+/// is字段支持的属性
+const PROPERTIES: &[&str] = &["empty", "broken", "hardlink", "setuid", "setgid", "sticky"];
+
+impl Condition {
+    /// 根据字段名、操作符和值构造条件，并检查值的格式
+    /// 命令行中单独的--size、--modified等选项也通过这里转换为条件
+    pub fn new(field: &str, op: CompareOp, value: &str) -> Result<Self> {
+        let field = field.to_lowercase();
+        // mtime<3d、mtime>2w是相对时间的简写，等同于mtime:<3d、mtime:>2w
+        if matches!(field.as_str(), "mtime" | "atime" | "ctime") && op != CompareOp::Match {
+            return Condition::new(
+                &field,
+                CompareOp::Match,
+                &format!("{}{}", op.as_str(), value),
+            );
+        }
+        if op != CompareOp::Match && field != "size" && field != "nlink" {
+            return Err(anyhow!(
+                "operator '{}' is not supported for field '{}', use ':'",
+                op.as_str(),
+                field
+            ));
+        }
+        let condition = match field.as_str() {
+            "size" if op == CompareOp::Match => {
+                parse_size_condition(value)?;
+                Condition::Size(value.to_string())
+            }
+            "size" => {
+                let size = parse_size_unit(&value.to_lowercase())?
+                    .ok_or_else(|| anyhow!("missing size after '{}'", op.as_str()))?;
+                Condition::SizeCompare(op, size)
+            }
+            "type" => {
+                validate_file_type(value)?;
+                Condition::FileType(value.to_string())
+            }
+            "mtime" | "atime" | "ctime" => {
+                validate_datetime_condition(value)?;
+                let time_type = match field.as_str() {
+                    "mtime" => "mtime",
+                    "atime" => "atime",
+                    _ => "ctime",
+                };
+                Condition::Time(time_type, value.to_string())
+            }
+            "newer" | "older" => {
+                let time = std::fs::metadata(value)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| anyhow!("cannot read modification time of {}: {}", value, e))?;
+                if field == "newer" {
+                    Condition::Newer(time)
+                } else {
+                    Condition::Older(time)
+                }
+            }
+            "perm" | "permission" => {
+                if parse_permission(value.strip_prefix('+').unwrap_or(value)).is_err() {
+                    return Err(anyhow!("Invalid permission format: {}", value));
+                }
+                Condition::Permission(value.to_string())
+            }
+            "kind" => {
+                parse_kinds(value)?;
+                Condition::Kind(value.to_string())
+            }
+            "is" => {
+                if !PROPERTIES.contains(&value.to_lowercase().as_str()) {
+                    return Err(anyhow!(
+                        "unknown property '{}', expected one of {}",
+                        value,
+                        PROPERTIES.join(", ")
+                    ));
+                }
+                Condition::Property(value.to_string())
+            }
+            "nlink" => {
+                let nlink = value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid link count: {}", value))?;
+                Condition::NlinkCompare(op, nlink)
+            }
+            "owner" => {
+                if users::get_user_by_name(value).is_none() && value.parse::<u32>().is_err() {
+                    return Err(anyhow!("unknown user: {}", value));
+                }
+                Condition::Owner(value.to_string())
+            }
+            "group" => {
+                if users::get_group_by_name(value).is_none() && value.parse::<u32>().is_err() {
+                    return Err(anyhow!("unknown group: {}", value));
+                }
+                Condition::Group(value.to_string())
+            }
+            "name" => Condition::Name(glob_to_regex(value)?),
+            "ext" => Condition::Extension(value.trim_start_matches('.').to_lowercase()),
+            _ => {
+                return Err(anyhow!(
+                    "unknown field '{}', expected one of size, type, mtime, atime, ctime, newer, older, perm, kind, is, nlink, owner, group, name, ext",
+                    field
+                ));
+            }
+        };
+        Ok(condition)
+    }
+
+    /// 用已经读取的元数据判断条件是否满足，出错时视为不满足
+    pub fn matches_info(&self, path: &Path, info: &FileInfo) -> bool {
+        let result = match self {
+            Condition::Size(size) => size_matches(info.size, size),
+            Condition::SizeCompare(op, size) => Ok(op.compare(info.size, *size)),
+            Condition::FileType(file_type) => file_type_matches(path, info, file_type),
+            Condition::Time(time_type, time) => datetime_matches(info, time, time_type),
+            Condition::Newer(time) => Ok(info.modified.is_some_and(|modified| modified > *time)),
+            Condition::Older(time) => Ok(info.modified.is_some_and(|modified| modified < *time)),
+            Condition::Permission(permission) => permission_matches(info.mode, permission),
+            Condition::Kind(kind) => kind_matches(info, kind),
+            Condition::Property(property) => property_matches(path, info, property),
+            Condition::NlinkCompare(op, nlink) => Ok(op.compare(info.nlink, *nlink)),
+            Condition::Owner(owner) => owner_matches(info.uid, owner),
+            Condition::Group(group) => group_matches(info.gid, group),
+            Condition::Name(re) => {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                re.is_match(&name).map_err(anyhow::Error::from)
+            }
+            Condition::Extension(ext) => Ok(get_extension(path).to_lowercase() == *ext),
+        };
+        result.unwrap_or(false)
+    }
+}
+
+/// 把*、?通配符转换为正则
+fn glob_to_regex(glob: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&fancy_regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+impl Query {
+    /// 解析筛选表达式，出错时的信息中会标出出错的位置
+    pub fn parse(input: &str) -> Result<Query> {
+        let tokens = tokenize(input).map_err(|e| e.render(input))?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            input_len: input.len(),
+        };
+        let query = parser.parse_or().map_err(|e| e.render(input))?;
+        if let Some(token) = parser.peek() {
+            return Err(QueryError {
+                position: token.position,
+                message: format!("unexpected '{}'", token.text()),
+            }
+            .render(input));
+        }
+        Ok(query)
+    }
+
+    /// 把命令行中单独的筛选选项（如--size、--modified）和--where表达式用and连接起来
+    /// options为（字段名，选项值）的列表，没有任何条件时返回None
+    pub fn from_options(
+        options: &[(&str, &Option<String>)],
+        where_expr: &Option<String>,
+    ) -> Result<Option<Query>> {
+        let mut queries = Vec::new();
+        for (field, value) in options {
+            if let Some(value) = value {
+                let condition = Condition::new(field, CompareOp::Match, value)
+                    .map_err(|e| anyhow!("Invalid filter '{}:{}': {}", field, value, e))?;
+                queries.push(Query::Condition(condition));
+            }
+        }
+        if let Some(where_expr) = where_expr {
+            queries.push(Query::parse(where_expr)?);
+        }
+        Ok(queries
+            .into_iter()
+            .reduce(|a, b| Query::And(Box::new(a), Box::new(b))))
+    }
+
+    /// 读取一次元数据，再判断所有条件
+    pub fn matches(&self, path: &Path) -> bool {
+        match FileInfo::from_path(path) {
+            Ok(info) => self.matches_info(path, &info),
+            Err(_) => false,
+        }
+    }
+
+    /// 用已经读取的元数据（如索引中保存的元数据）判断，不访问文件系统
+    pub fn matches_info(&self, path: &Path, info: &FileInfo) -> bool {
+        match self {
+            Query::And(a, b) => a.matches_info(path, info) && b.matches_info(path, info),
+            Query::Or(a, b) => a.matches_info(path, info) || b.matches_info(path, info),
+            Query::Not(a) => !a.matches_info(path, info),
+            Query::Condition(condition) => condition.matches_info(path, info),
+        }
+    }
+}
+
+struct QueryError {
+    /// 出错位置（字节偏移）
+    position: usize,
+    message: String,
+}
+
+impl QueryError {
+    /// 生成带有位置标记的错误信息
+    fn render(&self, input: &str) -> anyhow::Error {
+        let column = input[..self.position.min(input.len())].chars().count();
+        anyhow!(
+            "Invalid filter expression at column {}: {}\n  {}\n  {}^",
+            column + 1,
+            self.message,
+            input,
+            " ".repeat(column)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    /// 字段、操作符和值
+    Condition(String, CompareOp, String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match &self.kind {
+            TokenKind::LeftParen => "(".to_string(),
+            TokenKind::RightParen => ")".to_string(),
+            TokenKind::And => "and".to_string(),
+            TokenKind::Or => "or".to_string(),
+            TokenKind::Not => "not".to_string(),
+            TokenKind::Condition(field, op, value) => format!("{}{}{}", field, op.as_str(), value),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> std::result::Result<Vec<Token>, QueryError> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let kind = match c {
+            '(' => Some(TokenKind::LeftParen),
+            ')' => Some(TokenKind::RightParen),
+            '!' => Some(TokenKind::Not),
+            '&' | '|' if chars.get(i + 1).map(|&(_, n)| n) == Some(c) => {
+                i += 1;
+                Some(if c == '&' {
+                    TokenKind::And
+                } else {
+                    TokenKind::Or
+                })
+            }
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            tokens.push(Token { kind, position });
+            i += 1;
+            continue;
+        }
+
+        // 读取一个单词：关键字或者字段名
+        let start = i;
+        while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+            i += 1;
+        }
+        if start == i {
+            return Err(QueryError {
+                position,
+                message: format!("unexpected '{}'", c),
+            });
+        }
+        let word: String = chars[start..i].iter().map(|&(_, c)| c).collect();
+        let keyword = match word.to_lowercase().as_str() {
+            "and" => Some(TokenKind::And),
+            "or" => Some(TokenKind::Or),
+            "not" => Some(TokenKind::Not),
+            _ => None,
+        };
+        if let Some(kind) = keyword {
+            tokens.push(Token { kind, position });
+            continue;
+        }
+
+        // 字段名后面必须是操作符
+        let op_position = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+        let next = chars.get(i).map(|&(_, c)| c);
+        let next2 = chars.get(i + 1).map(|&(_, c)| c);
+        let op = match (next, next2) {
+            (Some('>'), Some('=')) => CompareOp::GreaterEqual,
+            (Some('<'), Some('=')) => CompareOp::LessEqual,
+            (Some('>'), _) => CompareOp::Greater,
+            (Some('<'), _) => CompareOp::Less,
+            (Some(':'), _) | (Some('='), _) => CompareOp::Match,
+            _ => {
+                return Err(QueryError {
+                    position: op_position,
+                    message: format!(
+                        "expected ':', '=', '>', '>=', '<' or '<=' after field '{}'",
+                        word
+                    ),
+                });
+            }
+        };
+        i += if matches!(op, CompareOp::GreaterEqual | CompareOp::LessEqual) {
+            2
+        } else {
+            1
+        };
+
+        // 读取值：引号包含的字符串，或者到空白/不匹配的右括号为止
+        let value_position = chars.get(i).map(|&(p, _)| p).unwrap_or(input.len());
+        let mut value = String::new();
+        match chars.get(i).map(|&(_, c)| c) {
+            Some(quote @ ('"' | '\'')) => {
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some(&(_, c)) if c == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&(_, c)) => {
+                            value.push(c);
+                            i += 1;
+                        }
+                        None => {
+                            return Err(QueryError {
+                                position: value_position,
+                                message: "unterminated quote".to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {
+                let mut depth = 0;
+                while let Some(&(_, c)) = chars.get(i) {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    if c == '(' {
+                        depth += 1;
+                    } else if c == ')' {
+                        if depth == 0 {
+                            break;
+                        }
+                        depth -= 1;
+                    }
+                    value.push(c);
+                    i += 1;
+                }
+            }
+        }
+        if value.is_empty() {
+            return Err(QueryError {
+                position: value_position,
+                message: format!("expected a value after '{}{}'", word, op.as_str()),
+            });
+        }
+        tokens.push(Token {
+            kind: TokenKind::Condition(word, op, value),
+            position,
+        });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    input_len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_is(&self, kind: &TokenKind) -> bool {
+        self.peek().is_some_and(|token| token.kind == *kind)
+    }
+
+    fn parse_or(&mut self) -> std::result::Result<Query, QueryError> {
+        let mut left = self.parse_and()?;
+        while self.next_is(&TokenKind::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Query::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> std::result::Result<Query, QueryError> {
+        let mut left = self.parse_not()?;
+        while self.next_is(&TokenKind::And) {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            left = Query::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> std::result::Result<Query, QueryError> {
+        if self.next_is(&TokenKind::Not) {
+            self.pos += 1;
+            let query = self.parse_not()?;
+            return Ok(Query::Not(Box::new(query)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> std::result::Result<Query, QueryError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(QueryError {
+                position: self.input_len,
+                message: "unexpected end of expression, expected a condition".to_string(),
+            });
+        };
+        self.pos += 1;
+        match token.kind {
+            TokenKind::LeftParen => {
+                let query = self.parse_or()?;
+                if !self.next_is(&TokenKind::RightParen) {
+                    return Err(QueryError {
+                        position: self.peek().map(|t| t.position).unwrap_or(self.input_len),
+                        message: "missing ')'".to_string(),
+                    });
+                }
+                self.pos += 1;
+                Ok(query)
+            }
+            TokenKind::Condition(field, op, value) => Condition::new(&field, op, &value)
+                .map(Query::Condition)
+                .map_err(|e| QueryError {
+                    position: token.position,
+                    message: e.to_string(),
+                }),
+            _ => Err(QueryError {
+                position: token.position,
+                message: format!("unexpected '{}', expected a condition", token.text()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 把解析结果转换成带括号的字符串，方便比较结构
+    fn show(query: &Query) -> String {
+        match query {
+            Query::And(a, b) => format!("({} and {})", show(a), show(b)),
+            Query::Or(a, b) => format!("({} or {})", show(a), show(b)),
+            Query::Not(a) => format!("not {}", show(a)),
+            Query::Condition(Condition::Extension(ext)) => ext.clone(),
+            Query::Condition(condition) => format!("{:?}", condition),
+        }
+    }
+
+    fn parse(input: &str) -> String {
+        show(&Query::parse(input).unwrap())
+    }
+
+    fn error(input: &str) -> String {
+        Query::parse(input).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("ext:a or ext:b and ext:c"), "(a or (b and c))");
+        assert_eq!(parse("ext:a and ext:b or ext:c"), "((a and b) or c)");
+        assert_eq!(parse("not ext:a and ext:b"), "(not a and b)");
+        assert_eq!(
+            parse("!ext:a || ext:b && !ext:c"),
+            "(not a or (b and not c))"
+        );
+        assert_eq!(parse("ext:a and ext:b and ext:c"), "((a and b) and c)");
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(parse("(ext:a or ext:b) and ext:c"), "((a or b) and c)");
+        assert_eq!(parse("not (ext:a or ext:b)"), "not (a or b)");
+        assert_eq!(parse("((ext:a))"), "a");
+        assert_eq!(parse("(ext:a and(ext:b or ext:c))"), "(a and (b or c))");
+    }
+
+    #[test]
+    fn comparison_operators() {
+        let cases = [
+            ("size>10k", CompareOp::Greater, 10 * 1024),
+            ("size>=1k", CompareOp::GreaterEqual, 1024),
+            ("size<100", CompareOp::Less, 100),
+            ("size<=1m", CompareOp::LessEqual, 1024 * 1024),
+        ];
+        for (input, op, size) in cases {
+            match Query::parse(input).unwrap() {
+                Query::Condition(Condition::SizeCompare(o, s)) => {
+                    assert_eq!((o, s), (op, size), "{}", input)
+                }
+                other => panic!("{}: {:?}", input, other),
+            }
+        }
+        assert!(matches!(
+            Query::parse("nlink>1").unwrap(),
+            Query::Condition(Condition::NlinkCompare(CompareOp::Greater, 1))
+        ));
+        assert!(matches!(
+            Query::parse("size:1k-2k").unwrap(),
+            Query::Condition(Condition::Size(_))
+        ));
+        assert!(matches!(
+            Query::parse("ext=JPG").unwrap(),
+            Query::Condition(Condition::Extension(ext)) if ext == "jpg"
+        ));
+        assert!(matches!(
+            Query::parse("mtime<3d").unwrap(),
+            Query::Condition(Condition::Time("mtime", time)) if time == "<3d"
+        ));
+        assert!(error("ext>jpg").contains("operator '>' is not supported"));
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert!(matches!(
+            Query::parse(r#"name:"my file*" and ext:txt"#).unwrap(),
+            Query::And(a, _) if matches!(*a, Query::Condition(Condition::Name(_)))
+        ));
+        assert!(error(r#"name:"abc"#).contains("unterminated quote"));
+    }
+
+    #[test]
+    fn error_column() {
+        let column = |input: &str| {
+            let message = error(input);
+            let start = message.find("column ").unwrap() + "column ".len();
+            let end = start + message[start..].find(':').unwrap();
+            message[start..end].parse::<usize>().unwrap()
+        };
+        assert_eq!(column("ext:a and size>abc"), 11);
+        assert_eq!(column("ext:a or foo:b"), 10);
+        assert_eq!(column("ext:a and"), 10);
+        assert_eq!(column("(ext:a or ext:b"), 16);
+        assert_eq!(column("ext:a ext:b"), 7);
+        assert_eq!(column("ext:a and size"), 15);
+        assert_eq!(column("ext:a and type:nosuchtype"), 11);
+        // 列号按字符计算，而不是字节
+        assert_eq!(column("name:文件 and foo:b"), 13);
+
+        let message = error("ext:a and type:nosuchtype");
+        assert!(message.contains("Unsupported file type: nosuchtype"));
+        assert!(message.ends_with("\n  ext:a and type:nosuchtype\n            ^"));
+    }
+
+    #[test]
+    fn file_types() {
+        assert!(Query::parse("type:image").is_ok());
+        assert!(Query::parse("type:Image,!video").is_ok());
+        assert!(Query::parse("type:image,!nosuchtype").is_err());
+    }
+}
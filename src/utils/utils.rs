@@ -1,14 +1,180 @@
 use anyhow::Result;
-use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, Timelike, Utc};
 use fancy_regex::Regex;
 // use std::fmt::format;
 use std::path::Path;
-use std::time::SystemTime;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
 use std::{fs};
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
+use crate::utils::highlight::FileHighlighter;
 use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
+use serde::{Deserialize, Serialize};
+
+/// 筛选条件用到的文件元数据
+/// 可以从文件系统读取，也可以来自rt index建立的索引，这样按索引筛选时不需要再访问文件系统
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// 条目的类型，符号链接取其指向的文件的类型
+    pub kind: FileKind,
+    /// 符号链接指向的文件不存在
+    pub is_broken: bool,
+    /// 硬链接数，非Unix系统为1
+    pub nlink: u64,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    /// 权限位，非Unix系统为0
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl FileInfo {
+    /// 读取文件的元数据，符号链接取其指向的文件，指向的文件不存在时取链接本身
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let is_symlink = path.symlink_metadata()?.file_type().is_symlink();
+        let metadata = path.metadata().or_else(|_| path.symlink_metadata())?;
+        Ok(Self::from_metadata(&metadata, is_symlink))
+    }
+
+    /// metadata为符号链接本身的元数据时，表示链接指向的文件不存在
+    pub fn from_metadata(metadata: &fs::Metadata, is_symlink: bool) -> Self {
+        #[cfg(unix)]
+        let (mode, uid, gid, nlink) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.mode(), metadata.uid(), metadata.gid(), metadata.nlink())
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid, nlink) = (0, 0, 0, 1);
+        let kind = FileKind::of(&metadata.file_type());
+        FileInfo {
+            is_dir: metadata.is_dir(),
+            is_symlink,
+            kind,
+            is_broken: kind == FileKind::Symlink,
+            nlink,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            accessed: metadata.accessed().ok(),
+            created: metadata.created().ok(),
+            mode,
+            uid,
+            gid,
+        }
+    }
+}
+
+/// 文件系统中条目的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
+    Other,
+}
+
+impl FileKind {
+    pub fn of(file_type: &fs::FileType) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_socket() {
+                return FileKind::Socket;
+            }
+            if file_type.is_fifo() {
+                return FileKind::Fifo;
+            }
+            if file_type.is_block_device() {
+                return FileKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return FileKind::CharDevice;
+            }
+        }
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+/// 解析条目类型，可以用逗号分隔多个：file(f)、dir(d)、symlink(l)、socket(s)、fifo(p)、
+/// block(b)、char(c)、device（块设备或字符设备）
+pub fn parse_kinds(kind_str: &str) -> Result<Vec<FileKind>> {
+    let mut kinds = Vec::new();
+    for kind in kind_str.split(',').map(|k| k.trim().to_lowercase()) {
+        match kind.as_str() {
+            "file" | "f" => kinds.push(FileKind::File),
+            "dir" | "d" => kinds.push(FileKind::Dir),
+            "symlink" | "link" | "l" => kinds.push(FileKind::Symlink),
+            "socket" | "s" => kinds.push(FileKind::Socket),
+            "fifo" | "pipe" | "p" => kinds.push(FileKind::Fifo),
+            "block" | "b" => kinds.push(FileKind::BlockDevice),
+            "char" | "c" => kinds.push(FileKind::CharDevice),
+            "device" => kinds.extend([FileKind::BlockDevice, FileKind::CharDevice]),
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "Invalid kind '{}', expected file, dir, symlink, socket, fifo, block, char or device",
+                    kind
+                )));
+            }
+        }
+    }
+    Ok(kinds)
+}
+
+/// 检查条目类型是否匹配
+/// # 参数
+/// * `info` - 文件的元数据
+/// * `kind_str` - 类型，格式见parse_kinds
+/// # 返回值
+/// * `Result<bool>` - 是否匹配，类型名称错误时返回Err
+///
+/// 符号链接只匹配symlink，不按其指向的文件匹配其他类型，与find -type相同
+pub fn kind_matches(info: &FileInfo, kind_str: &str) -> Result<bool> {
+    let actual = if info.is_symlink { FileKind::Symlink } else { info.kind };
+    Ok(parse_kinds(kind_str)?.contains(&actual))
+}
+
+/// 检查文件是否具有某个属性
+/// # 参数
+/// * `path` - 文件路径，判断目录是否为空时需要读取目录
+/// * `info` - 文件的元数据
+/// * `property` - 属性：empty（空文件或空目录）、broken（指向的文件不存在的符号链接）、
+///   hardlink（硬链接数大于1的非目录文件）、setuid、setgid、sticky
+/// # 返回值
+/// * `Result<bool>` - 是否具有该属性，属性名称错误时返回Err
+pub fn property_matches(path: &Path, info: &FileInfo, property: &str) -> Result<bool> {
+    match property.to_lowercase().as_str() {
+        "empty" => Ok(match info.kind {
+            FileKind::File => info.size == 0,
+            FileKind::Dir => fs::read_dir(path)?.next().is_none(),
+            _ => false,
+        }),
+        "broken" => Ok(info.is_broken),
+        "hardlink" => Ok(!info.is_dir && info.nlink > 1),
+        "setuid" => Ok(info.mode & 0o4000 != 0),
+        "setgid" => Ok(info.mode & 0o2000 != 0),
+        "sticky" => Ok(info.mode & 0o1000 != 0),
+        _ => Err(anyhow::Error::msg(format!(
+            "Invalid property '{}', expected empty, broken, hardlink, setuid, setgid or sticky",
+            property
+        ))),
+    }
+}
+
 /// 获取文件的扩展名
 pub fn get_extension(path: &Path) -> String {
     path.extension()
@@ -17,6 +183,195 @@ pub fn get_extension(path: &Path) -> String {
         .to_string()
 }
 
+/// 通过文件头(magic number)识别出的文件类型
+pub struct MagicType {
+    /// 标准扩展名，为空表示这类文件通常没有扩展名（如ELF可执行文件）
+    pub ext: &'static str,
+    /// 与内容相符的其他扩展名
+    pub aliases: &'static [&'static str],
+}
+
+impl MagicType {
+    /// 检查扩展名是否与文件内容相符
+    pub fn accepts(&self, ext: &str) -> bool {
+        let ext = canonical_extension(ext);
+        ext == self.ext || self.aliases.contains(&ext.as_str())
+    }
+}
+
+/// 将扩展名转换为标准形式，如JPEG->jpg、jpe->jpg、TIFF->tif
+pub fn canonical_extension(ext: &str) -> String {
+    let ext = ext.to_lowercase();
+    match ext.as_str() {
+        "jpeg" | "jpe" | "jfif" => "jpg".to_string(),
+        "tiff" => "tif".to_string(),
+        "htm" => "html".to_string(),
+        "mpeg" | "mpe" => "mpg".to_string(),
+        "aif" => "aiff".to_string(),
+        "midi" => "mid".to_string(),
+        _ => ext,
+    }
+}
+
+/// 读取文件头，通过magic number识别文件类型
+/// 支持常见的图片、音视频、文档、压缩包和可执行文件格式
+/// # 返回值
+/// 无法识别时返回None
+pub fn detect_magic_type(path: &Path) -> Option<MagicType> {
+    use std::io::Read;
+    let file = fs::File::open(path).ok()?;
+    let mut head = Vec::with_capacity(64 * 1024);
+    file.take(64 * 1024).read_to_end(&mut head).ok()?;
+    let magic = |ext: &'static str, aliases: &'static [&'static str]| Some(MagicType { ext, aliases });
+    let h = head.as_slice();
+
+    if h.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return magic("jpg", &[]);
+    }
+    if h.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return magic("png", &["apng"]);
+    }
+    if h.starts_with(b"GIF87a") || h.starts_with(b"GIF89a") {
+        return magic("gif", &[]);
+    }
+    if h.starts_with(b"II*\0") || h.starts_with(b"MM\0*") {
+        // 很多相机RAW格式也基于TIFF
+        return magic("tif", &["dng", "cr2", "nef", "arw", "orf", "sr2", "raw"]);
+    }
+    if h.len() >= 12 && h.starts_with(b"RIFF") {
+        match &h[8..12] {
+            b"WEBP" => return magic("webp", &[]),
+            b"WAVE" => return magic("wav", &[]),
+            b"AVI " => return magic("avi", &[]),
+            _ => {}
+        }
+    }
+    if h.len() >= 12 && &h[4..8] == b"ftyp" {
+        return match &h[8..12] {
+            b"qt  " => magic("mov", &[]),
+            b"M4A " | b"M4B " => magic("m4a", &["m4b"]),
+            b"heic" | b"heix" | b"mif1" | b"msf1" => magic("heic", &["heif"]),
+            b"avif" => magic("avif", &[]),
+            b"crx " => magic("cr3", &[]),
+            brand if brand.starts_with(b"3g") => magic("3gp", &["3g2"]),
+            _ => magic("mp4", &["m4v", "mov"]),
+        };
+    }
+    if h.starts_with(b"%PDF-") {
+        return magic("pdf", &["ai"]);
+    }
+    if h.starts_with(b"PK\x03\x04") {
+        return detect_zip_type(h);
+    }
+    if h.starts_with(&[0x7F, b'E', b'L', b'F']) {
+        return magic("", &["elf", "so", "o", "ko", "bin", "out"]);
+    }
+    if h.starts_with(b"MZ") {
+        return magic("exe", &["dll", "sys", "msi", "com", "efi"]);
+    }
+    if h.starts_with(&[0x1F, 0x8B]) {
+        return magic("gz", &["tgz"]);
+    }
+    if h.starts_with(b"BZh") {
+        return magic("bz2", &["tbz2", "tbz"]);
+    }
+    if h.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+        return magic("xz", &["txz"]);
+    }
+    if h.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+        return magic("zst", &["tzst"]);
+    }
+    if h.starts_with(&[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C]) {
+        return magic("7z", &[]);
+    }
+    if h.starts_with(b"Rar!\x1a\x07") {
+        return magic("rar", &[]);
+    }
+    if h.len() > 262 && &h[257..262] == b"ustar" {
+        return magic("tar", &[]);
+    }
+    if h.starts_with(b"ID3") || h.starts_with(&[0xFF, 0xFB]) || h.starts_with(&[0xFF, 0xF3]) {
+        return magic("mp3", &[]);
+    }
+    if h.starts_with(b"fLaC") {
+        return magic("flac", &[]);
+    }
+    if h.starts_with(b"OggS") {
+        return magic("ogg", &["oga", "ogv", "opus"]);
+    }
+    if h.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return magic("mkv", &["webm", "mka"]);
+    }
+    if h.starts_with(b"SQLite format 3\0") {
+        return magic("sqlite", &["db", "sqlite3", "db3"]);
+    }
+    if h.starts_with(&[0xCA, 0xFE, 0xBA, 0xBE]) {
+        return magic("class", &[]);
+    }
+    if h.starts_with(b"\0asm") {
+        return magic("wasm", &[]);
+    }
+    if h.starts_with(b"8BPS") {
+        return magic("psd", &[]);
+    }
+    if h.starts_with(&[0x00, 0x00, 0x01, 0x00]) {
+        return magic("ico", &[]);
+    }
+    if h.starts_with(b"wOFF") {
+        return magic("woff", &[]);
+    }
+    if h.starts_with(b"wOF2") {
+        return magic("woff2", &[]);
+    }
+    if h.starts_with(b"OTTO") {
+        return magic("otf", &[]);
+    }
+    if h.starts_with(b"{\\rtf") {
+        return magic("rtf", &[]);
+    }
+    None
+}
+
+/// 识别基于ZIP的格式：Office文档、OpenDocument、EPUB、JAR、APK
+fn detect_zip_type(head: &[u8]) -> Option<MagicType> {
+    let contains = |needle: &[u8]| head.windows(needle.len()).any(|w| w == needle);
+    let magic = |ext: &'static str, aliases: &'static [&'static str]| Some(MagicType { ext, aliases });
+    // OpenDocument和EPUB的第一个条目是未压缩的mimetype
+    if head.len() > 38 && &head[30..38] == b"mimetype" {
+        if contains(b"application/epub+zip") {
+            return magic("epub", &[]);
+        }
+        if contains(b"opendocument.text") {
+            return magic("odt", &[]);
+        }
+        if contains(b"opendocument.spreadsheet") {
+            return magic("ods", &[]);
+        }
+        if contains(b"opendocument.presentation") {
+            return magic("odp", &[]);
+        }
+    }
+    if contains(b"word/") {
+        return magic("docx", &["docm", "dotx"]);
+    }
+    if contains(b"xl/") {
+        return magic("xlsx", &["xlsm", "xltx"]);
+    }
+    if contains(b"ppt/") {
+        return magic("pptx", &["pptm", "potx"]);
+    }
+    if contains(b"AndroidManifest.xml") {
+        return magic("apk", &[]);
+    }
+    if contains(b"META-INF/MANIFEST.MF") {
+        return magic("jar", &["war", "ear"]);
+    }
+    magic(
+        "zip",
+        &["jar", "war", "apk", "ipa", "whl", "xpi", "cbz", "nupkg", "kmz", "3mf", "vsix"],
+    )
+}
+
 pub fn print_highlighted_lines(file_path: &str, lines_to_show: usize) -> Result<()> {
     // 读取文件内容
     let code = fs::read_to_string(file_path)?;
@@ -27,30 +382,18 @@ pub fn print_highlighted_lines(file_path: &str, lines_to_show: usize) -> Result<
         .ok_or("File has no extension")
         .unwrap_or("");
 
-    // 加载语法和主题
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-
-    // 查找对应语法
-    let syntax = ps
-        .find_syntax_by_extension(extension)
-        .ok_or_else(|| format!("Unsupported file type: {}", extension));
-
-    match syntax {
-        Ok(s) => {
-            // 创建 HighlightLines 实例
-            let mut highlighter = HighlightLines::new(s, &ts.themes["base16-ocean.dark"]);
+    match FileHighlighter::for_extension(extension) {
+        Some(mut highlighter) => {
             // 按行分割代码
             let lines = LinesWithEndings::from(&code);
             // 遍历前 n 行
             for (i, line) in lines.take(lines_to_show).enumerate() {
-                let ranges: Vec<(Style, &str)> =
-                    highlighter.highlight_line(line, &ps).unwrap_or_default();
+                let ranges = highlighter.highlight_line(line);
                 let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
                 print!("{}: {}", i + 1, escaped);
             }
         }
-        Err(_) => {
+        None => {
             // 直接输出原始内容
             println!("{}", code);
         }
@@ -66,7 +409,7 @@ pub fn print_highlighted_lines(file_path: &str, lines_to_show: usize) -> Result<
 /// Z: 可以是k、m、g、t、p，表示KB、MB、GB、TB、PB
 /// 例如：100k-200m表示100KB到200MB之间的文件
 /// 范围可以叠加，用逗号分隔，例如：100k-200m,10g表示100KB到200MB之间的文件，或者10GB以上的文件
-fn parse_size_condition(size_str: &str) -> Result<Vec<(Option<u64>, Option<u64>)>> {
+pub fn parse_size_condition(size_str: &str) -> Result<Vec<(Option<u64>, Option<u64>)>> {
     let mut conditions = vec![];
     // 按逗号分割条件
     for condition in size_str.split(',') {
@@ -97,27 +440,18 @@ fn parse_size_condition(size_str: &str) -> Result<Vec<(Option<u64>, Option<u64>)
 /// 解析带单位的文件大小字符串
 /// 示例: "100k" -> Some(102400)
 /// 支持单位: k(KB), m(MB), g(GB), t(TB), p(PB)
-fn parse_size_unit(size_str: &str) -> Result<Option<u64>> {
+pub fn parse_size_unit(size_str: &str) -> Result<Option<u64>> {
     if size_str.is_empty() {
         return Ok(None);
     }
 
     let re = Regex::new(r"^(\d+)([kmgtp]?)$")?;
     let caps = re
-        .captures(size_str)
-        .or_else(|_| Err(anyhow::anyhow!("Invalid size format: {}", size_str)))?;
-
-    let num = caps
-        .as_ref()
-        .and_then(|c| c.get(1))
-        .unwrap()
-        .as_str()
-        .parse::<u64>()?;
-    let unit = caps
-        .as_ref()
-        .and_then(|c| c.get(2))
-        .map(|m| m.as_str())
-        .unwrap_or("");
+        .captures(size_str)?
+        .ok_or_else(|| anyhow::anyhow!("Invalid size format: {}", size_str))?;
+
+    let num = caps.get(1).unwrap().as_str().parse::<u64>()?;
+    let unit = caps.get(2).map(|m| m.as_str()).unwrap_or("");
 
     let multiplier = match unit.to_lowercase().as_str() {
         "k" => 1024,
@@ -131,10 +465,9 @@ fn parse_size_unit(size_str: &str) -> Result<Option<u64>> {
     Ok(Some(num * multiplier))
 }
 
-/// 最终检查函数
-pub fn check_size_condition(path: &Path, size_str: &str) -> Result<bool> {
+/// 检查文件大小是否满足大小条件
+pub fn size_matches(file_size: u64, size_str: &str) -> Result<bool> {
     let conditions = parse_size_condition(size_str)?;
-    let file_size = path.metadata()?.len();
 
     Ok(conditions.iter().any(|(min, max)| {
         let min_val = min.unwrap_or(0);
@@ -153,24 +486,209 @@ pub fn check_size_condition(path: &Path, size_str: &str) -> Result<bool> {
 /// :yyZ 表示yyZ及往前的所有时间范围
 /// xxZ 表示xxZ表示的时间范围，时间跨度与Z的单位相同
 /// special_datetime: 特殊时间，如today、yesterday、this_month、last_month、this_year、last_year
+/// 相对时间（滑动窗口，从当前时刻往前算）：<3d表示3天以内，>2w表示2周以前，1h..6h ago表示1到6小时之前
+/// 相对时间的单位：s秒、M或min分、h时、d天、w周、m月（30天）、y年（365天），ago可以省略
+/// 日历时间默认按UTC计算，--time-zone可以改为本地时间或指定的时区
 /// 范围可以用逗号分隔以取并集，例如：2021:2022y,10m表示在2021年到2022年或者在10月份
 /// 单个时间范围内的不同时间单位用“-”分隔，例如：2021y-7:8m-10:20d-:10h表示在2021年7月或8月的10日到20日，并且在00:00到10:00之间的时间范围
 /// 可以用括号来约定时间点，例如: (2021y-7m-10d-0h):(2021y-8m-20d-10h)表示在2021年7月10日00:00到2021年8月20日10:00之间的时间范围
-pub fn check_datetime_condition(path: &Path, datetime_str: &str, datetime_type: &str) -> Result<bool> {
-    let datetime_str = datetime_str.trim();
-    if datetime_str.is_empty() {
+///
+/// 检查元数据中的指定时间是否满足时间条件，datetime_type为mtime、atime或ctime
+pub fn datetime_matches(info: &FileInfo, datetime_str: &str, datetime_type: &str) -> Result<bool> {
+    let time_ranges = parse_datetime_condition(datetime_str)?;
+    if time_ranges.is_empty() {
         return Ok(true);
     }
-    let intersection = datetime_str.split(',').collect::<Vec<&str>>();
-    for datetime in intersection {
+    let time = match datetime_type {
+        "mtime" => info.modified,
+        "atime" => info.accessed,
+        "ctime" => info.created,
+        _ => None,
+    };
+    // 文件系统不支持的时间（如部分系统上的创建时间）不匹配任何条件
+    let Some(time) = time else {
+        return Ok(false);
+    };
+    Ok(time_ranges
+        .iter()
+        .any(|time_range| time_range.matches(time)))
+}
+
+/// 计算日历时间所用的时区
+#[derive(Debug, Clone, Copy)]
+pub enum TimeZoneSetting {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+static TIME_ZONE: OnceLock<TimeZoneSetting> = OnceLock::new();
+
+/// 设置计算日历时间（如2024y-3m、today）时使用的时区，只在启动时设置一次
+/// tz_str可以是utc、local，或者+08:00、-0530这样的偏移
+pub fn set_time_zone(tz_str: &str) -> Result<()> {
+    let tz = match tz_str.to_lowercase().as_str() {
+        "utc" | "z" => TimeZoneSetting::Utc,
+        "local" => TimeZoneSetting::Local,
+        _ => {
+            let offset: FixedOffset = tz_str.parse().map_err(|_| {
+                anyhow::anyhow!("Invalid time zone: {}, expected utc, local or +08:00", tz_str)
+            })?;
+            TimeZoneSetting::Fixed(offset)
+        }
+    };
+    let _ = TIME_ZONE.set(tz);
+    Ok(())
+}
+
+impl TimeZoneSetting {
+    fn current() -> Self {
+        *TIME_ZONE.get().unwrap_or(&TimeZoneSetting::Utc)
+    }
+
+    /// 转换为该时区的日历时间
+    fn naive(&self, time: SystemTime) -> NaiveDateTime {
+        let utc = DateTime::<Utc>::from(time);
+        match self {
+            TimeZoneSetting::Utc => utc.naive_utc(),
+            TimeZoneSetting::Local => utc.with_timezone(&Local).naive_local(),
+            TimeZoneSetting::Fixed(offset) => utc.with_timezone(offset).naive_local(),
+        }
+    }
+}
+
+/// 一个时间条件：日历时间范围，或者相对于当前时刻的时间段
+enum TimeCondition {
+    Calendar(TimeRange),
+    /// 距今的时长在min和max之间（包含两端）
+    Age {
+        min: Option<Duration>,
+        max: Option<Duration>,
+    },
+}
+
+impl TimeCondition {
+    fn matches(&self, time: SystemTime) -> bool {
+        match self {
+            TimeCondition::Calendar(time_range) => time_range.check_datatime(time),
+            TimeCondition::Age { min, max } => {
+                // 未来的时间距今为0
+                let age = SystemTime::now().duration_since(time).unwrap_or_default();
+                min.is_none_or(|min| age >= min) && max.is_none_or(|max| age <= max)
+            }
+        }
+    }
+}
+
+/// 解析相对时间：<3d、<=3d、>2w、>=2w、1h..6h ago，不是相对时间格式时返回None
+fn parse_relative_datetime(datetime: &str) -> Option<Result<TimeCondition>> {
+    let datetime = datetime.trim();
+    let datetime = datetime.strip_suffix("ago").unwrap_or(datetime).trim_end();
+    let condition = if let Some((min, max)) = datetime.split_once("..") {
+        parse_duration(min).and_then(|min| {
+            let max = parse_duration(max)?;
+            if min > max {
+                return Err(anyhow::anyhow!("Invalid relative time range: {}", datetime));
+            }
+            Ok(TimeCondition::Age {
+                min: Some(min),
+                max: Some(max),
+            })
+        })
+    } else if let Some(rest) = datetime.strip_prefix('<') {
+        parse_duration(rest.trim_start_matches('=')).map(|max| TimeCondition::Age {
+            min: None,
+            max: Some(max),
+        })
+    } else if let Some(rest) = datetime.strip_prefix('>') {
+        parse_duration(rest.trim_start_matches('=')).map(|min| TimeCondition::Age {
+            min: Some(min),
+            max: None,
+        })
+    } else {
+        return None;
+    };
+    Some(condition)
+}
+
+/// 解析时长，如30s、15M、15min、6h、3d、2w、1m、1y，月按30天、年按365天计算
+fn parse_duration(duration_str: &str) -> Result<Duration> {
+    let duration_str = duration_str.trim();
+    let split = duration_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration_str.len());
+    let (num, unit) = duration_str.split_at(split);
+    let num: u64 = num
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration: {}", duration_str))?;
+    let seconds = match unit.trim() {
+        "s" => 1,
+        "M" | "min" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        "m" => 30 * 86400,
+        "y" => 365 * 86400,
+        _ => return Err(anyhow::anyhow!("Invalid duration unit: {}", duration_str)),
+    };
+    num.checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow::anyhow!("Duration too large: {}", duration_str))
+}
+
+/// 检查时间筛选条件的格式是否正确
+pub fn validate_datetime_condition(datetime_str: &str) -> Result<()> {
+    parse_datetime_condition(datetime_str).map(|_| ())
+}
+
+/// 解析时间筛选条件，逗号分隔的每个时间范围取并集
+fn parse_datetime_condition(datetime_str: &str) -> Result<Vec<TimeCondition>> {
+    let mut time_ranges = Vec::new();
+    for datetime in datetime_str.trim().split(',') {
         let datetime = datetime.trim();
         if datetime.is_empty() {
             continue;
         }
-        let time_range = TimeRange::parser(datetime)?;
-        return Ok(time_range.check_datatime(path, datetime_type));
+        if let Some(condition) = parse_relative_datetime(datetime) {
+            time_ranges.push(condition?);
+            continue;
+        }
+        let datetime = special_datetime(datetime).unwrap_or(datetime.to_string());
+        let time_range = TimeRange::parser(&datetime)
+            .map_err(|_| anyhow::anyhow!("Invalid datetime format: {}", datetime))?;
+        time_ranges.push(TimeCondition::Calendar(time_range));
+    }
+    Ok(time_ranges)
+}
+
+/// 把today、this_month等特殊时间转换为普通的时间格式
+fn special_datetime(datetime: &str) -> Option<String> {
+    let now = TimeZoneSetting::current().naive(SystemTime::now());
+    let (year, month) = (now.year(), now.month());
+    match datetime {
+        "today" => Some(format!("{}y-{}m-{}d", year, month, now.day())),
+        "yesterday" => {
+            let yesterday = now - chrono::Duration::days(1);
+            Some(format!(
+                "{}y-{}m-{}d",
+                yesterday.year(),
+                yesterday.month(),
+                yesterday.day()
+            ))
+        }
+        "this_month" => Some(format!("{}y-{}m", year, month)),
+        "last_month" => {
+            let (year, month) = if month == 1 {
+                (year - 1, 12)
+            } else {
+                (year, month - 1)
+            };
+            Some(format!("{}y-{}m", year, month))
+        }
+        "this_year" => Some(format!("{}y", year)),
+        "last_year" => Some(format!("{}y", year - 1)),
+        _ => None,
     }
-    Err(anyhow::anyhow!("Invalid datetime format: {}", datetime_str))
 }
 
 struct TimeRange {
@@ -329,8 +847,8 @@ impl TimeRange {
             Ok(caps) => match caps {
                 Some(caps) => {
                     let num1 = caps.get(1).unwrap().as_str();
-                    let num2 = caps.get(3).unwrap().as_str();
-                    let unit = caps.get(4).unwrap().as_str();
+                    let num2 = caps.get(2).unwrap().as_str();
+                    let unit = caps.get(3).unwrap().as_str();
                     self.set_datatime_unit(num1, unit, true);
                     self.set_datatime_unit(num2, unit, false);
                     return Ok(true);
@@ -380,6 +898,7 @@ impl TimeRange {
                     let unit = caps.get(2).unwrap().as_str();
                     self.set_datatime_unit(num, unit, true);
                     self.set_datatime_unit(num, unit, false);
+                    return Ok(true);
                 }
                 None => {}
             },
@@ -387,38 +906,22 @@ impl TimeRange {
         }
         Err(anyhow::Error::msg("Invalid time format"))
     }
-    /// 检查Path指定的File的指定时间是否在范围内
+    /// 检查时间是否在范围内
     /// # 参数
-    /// * `path` - File的路径
-    /// * `time_type` - 时间类型，可选值为：mtime、ctime、atime
+    /// * `time` - 文件的修改、创建或访问时间
     /// # 返回值
     /// * `bool` - 如果在范围内返回true，否则返回false
-    /// # 示例
-    /// ```
-    fn check_datatime(&self, path: &Path, datetime_type: &str) -> bool {
-        let metadata = path.metadata().unwrap();
-        let modified = metadata.modified();
-        let created = metadata.created();
-        let accessed = metadata.accessed();
-        let mut time = SystemTime::now();
-        if datetime_type == "mtime" {
-            time = modified.unwrap();
-        }
-        if datetime_type == "ctime" {
-            time = created.unwrap();
-        }
-        if datetime_type == "atime" {
-            time = accessed.unwrap();
-        }
-        let flex_time = chrono::DateTime::<Utc>::from(time);
+    fn check_datatime(&self, time: SystemTime) -> bool {
+        // 按--time-zone指定的时区（默认UTC）取日历时间
+        let flex_time = TimeZoneSetting::current().naive(time);
         let year = flex_time.year() as u32;
         let month = flex_time.month();
         let day = flex_time.day();
         let hour = flex_time.hour();
         let minute = flex_time.minute();
         let second = flex_time.second();
-        let millisecond = flex_time.timestamp_millis();
-        let nanosecond = flex_time.timestamp_nanos_opt().unwrap_or(0);
+        let millisecond = flex_time.nanosecond() / 1_000_000;
+        let nanosecond = flex_time.nanosecond();
         if !self.fragment {
             // 整体时间范围判断
             let (start_time, end_time) = self.to_datetime();
@@ -434,12 +937,12 @@ impl TimeRange {
                 && self.cmp_datetime(
                     &self.millisecond_start,
                     &self.millisecond_end,
-                    millisecond.try_into().unwrap(),
+                    millisecond,
                 )
                 && self.cmp_datetime(
                     &self.nanosecond_start,
                     &self.nanosecond_end,
-                    nanosecond.try_into().unwrap(),
+                    nanosecond,
                 );
         }
     }
@@ -449,28 +952,34 @@ impl TimeRange {
         let end = end.unwrap_or(u32::MAX);
         value >= start && value <= end
     }
-    fn to_datetime(&self) -> (DateTime<Utc>, DateTime<Utc>) {
-        let start = chrono::Utc
-            .with_ymd_and_hms(
-                self.year_start.unwrap_or(1970) as i32,
-                self.month_start.unwrap_or(1),
-                self.day_start.unwrap_or(1),
+    fn to_datetime(&self) -> (NaiveDateTime, NaiveDateTime) {
+        let start = NaiveDate::from_ymd_opt(
+            self.year_start.unwrap_or(1970) as i32,
+            self.month_start.unwrap_or(1),
+            self.day_start.unwrap_or(1),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
                 self.hour_start.unwrap_or(0),
                 self.minute_start.unwrap_or(0),
                 self.second_start.unwrap_or(0),
             )
-            .unwrap();
+        })
+        .unwrap();
 
-        let end = chrono::Utc
-            .with_ymd_and_hms(
-                self.year_end.unwrap_or(9999) as i32,
-                self.month_end.unwrap_or(12),
-                self.day_end.unwrap_or(31),
+        let end = NaiveDate::from_ymd_opt(
+            self.year_end.unwrap_or(9999) as i32,
+            self.month_end.unwrap_or(12),
+            self.day_end.unwrap_or(31),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
                 self.hour_end.unwrap_or(23),
                 self.minute_end.unwrap_or(59),
                 self.second_end.unwrap_or(59),
             )
-            .unwrap();
+        })
+        .unwrap();
         (start, end)
     }
 }
@@ -489,29 +998,39 @@ pub fn check_file_type(path: &Path, file_type: &str) -> Result<bool> {
             path.display()
         )));
     }
+    file_type_matches(path, &FileInfo::from_path(path)?, file_type)
+}
 
-    if path.is_dir() {
-        return Ok(false);
-    }
+/// 文件类型的识别方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeDetection {
+    /// 只看扩展名
+    Extension,
+    /// 读取文件头：magic number、shebang，以及判断是否为UTF-8/ASCII文本，无法识别时再看扩展名
+    Content,
+}
 
-    let extension = match path.extension() {
-        Some(ext) => ext.to_str().ok_or_else(|| {
-            anyhow::Error::msg(format!(
-                "Failed to convert extension to string for path: {}",
-                path.display()
-            ))
-        })?,
-        None => return Ok(false), // 无扩展名的文件默认不匹配任何类型
-    }
-    .to_lowercase();
+static TYPE_DETECTION: OnceLock<TypeDetection> = OnceLock::new();
 
-    let mut is_match = false;
-    let mut exclude_match = false;
-    let mut is_executable_by_extension = false;
-    let mut is_executable_by_metadata = false;
+/// 设置--file-type的识别方式，只在启动时设置一次，detect_str为ext或content
+pub fn set_type_detection(detect_str: &str) -> Result<()> {
+    let detection = match detect_str {
+        "ext" | "extension" => TypeDetection::Extension,
+        "content" => TypeDetection::Content,
+        _ => {
+            return Err(anyhow::Error::msg(format!(
+                "Invalid detect mode: {}, expected ext or content",
+                detect_str
+            )));
+        }
+    };
+    let _ = TYPE_DETECTION.set(detection);
+    Ok(())
+}
 
-    // 定义文件类型与扩展名的映射
-    let type_mapping: std::collections::HashMap<&str, Vec<&str>> = [
+/// 内置的文件类型与扩展名的映射
+fn builtin_type_table() -> Vec<(&'static str, Vec<&'static str>)> {
+    vec![
         (
             "text",
             vec![
@@ -662,10 +1181,163 @@ pub fn check_file_type(path: &Path, file_type: &str) -> Result<bool> {
             ],
         ),
     ]
-    .iter()
-    .cloned()
-    .collect();
-    
+}
+
+/// 配置文件中扩展的文件类型，格式为{"类型": ["扩展名", ...]}
+/// 已有的类型会追加扩展名，新的类型可以直接用在-t中
+#[derive(Deserialize, Default)]
+struct TypeConfig {
+    #[serde(default)]
+    file_types: std::collections::BTreeMap<String, Vec<String>>,
+}
+
+/// 文件类型与扩展名的映射：内置的映射加上color_config.json中file_types的扩展，只读取一次
+fn type_table() -> &'static std::collections::HashMap<String, Vec<String>> {
+    static TABLE: OnceLock<std::collections::HashMap<String, Vec<String>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table: std::collections::HashMap<String, Vec<String>> = builtin_type_table()
+            .into_iter()
+            .map(|(name, exts)| {
+                let exts = exts.iter().map(|e| e.to_string()).collect();
+                (name.to_string(), exts)
+            })
+            .collect();
+        let config = std::env::current_exe()
+            .ok()
+            .and_then(|exe| fs::read_to_string(exe.with_file_name("color_config.json")).ok())
+            .and_then(|contents| serde_json::from_str::<TypeConfig>(&contents).ok())
+            .unwrap_or_default();
+        for (name, exts) in config.file_types {
+            table
+                .entry(name.to_lowercase())
+                .or_default()
+                .extend(exts.iter().map(|e| e.trim_start_matches('.').to_lowercase()));
+        }
+        table
+    })
+}
+
+/// 按文件内容识别出的类型
+struct ContentType {
+    /// 通过magic number识别出的标准扩展名，ELF等没有扩展名的格式为空
+    ext: Option<&'static str>,
+    /// UTF-8或ASCII文本（包括带shebang的脚本）
+    text: bool,
+    /// 可执行程序或带shebang的脚本
+    executable: bool,
+}
+
+/// 读取文件头识别文件类型，无法识别时返回None
+fn detect_content_type(path: &Path) -> Option<ContentType> {
+    use std::io::Read;
+    if let Some(magic) = detect_magic_type(path) {
+        let executable = magic.ext.is_empty() || magic.ext == "exe";
+        return Some(ContentType {
+            ext: Some(magic.ext),
+            text: false,
+            executable,
+        });
+    }
+    let file = fs::File::open(path).ok()?;
+    let mut head = Vec::with_capacity(8 * 1024);
+    file.take(8 * 1024).read_to_end(&mut head).ok()?;
+    if head.is_empty() {
+        return None;
+    }
+    // Mach-O
+    let mach_o: [[u8; 4]; 4] = [
+        [0xFE, 0xED, 0xFA, 0xCE],
+        [0xFE, 0xED, 0xFA, 0xCF],
+        [0xCE, 0xFA, 0xED, 0xFE],
+        [0xCF, 0xFA, 0xED, 0xFE],
+    ];
+    if mach_o.iter().any(|magic| head.starts_with(magic)) {
+        return Some(ContentType {
+            ext: None,
+            text: false,
+            executable: true,
+        });
+    }
+    if head.starts_with(b"#!") {
+        return Some(ContentType {
+            ext: None,
+            text: true,
+            executable: true,
+        });
+    }
+    if is_text(&head) {
+        return Some(ContentType {
+            ext: None,
+            text: true,
+            executable: false,
+        });
+    }
+    None
+}
+
+/// 不含NUL且是合法UTF-8（结尾被截断的字符不算错误）的内容视为文本，ASCII是UTF-8的子集
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // error_len为None表示只是结尾的字符不完整
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// 检查文件类型条件（如`image,!video`）中的类型是否都存在，包括color_config.json中添加的类型
+pub fn validate_file_type(file_type: &str) -> Result<()> {
+    let type_mapping = type_table();
+    for condition in file_type.split(',') {
+        let type_str = condition.trim().trim_start_matches('!').to_lowercase();
+        if type_str.is_empty() || type_mapping.contains_key(&type_str) {
+            continue;
+        }
+        let mut names = type_mapping.keys().map(|name| name.as_str()).collect::<Vec<&str>>();
+        names.sort();
+        return Err(anyhow::Error::msg(format!(
+            "Unsupported file type: {}, expected one of {}",
+            type_str,
+            names.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// 根据路径的扩展名和元数据检查文件类型
+/// 使用--detect content时会读取文件头，根据内容判断，没有扩展名和扩展名不对的文件也能识别
+pub fn file_type_matches(path: &Path, info: &FileInfo, file_type: &str) -> Result<bool> {
+    if info.is_dir {
+        return Ok(false);
+    }
+
+    let extension = match path.extension() {
+        Some(ext) => ext.to_str().ok_or_else(|| {
+            anyhow::Error::msg(format!(
+                "Failed to convert extension to string for path: {}",
+                path.display()
+            ))
+        })?,
+        None => "",
+    }
+    .to_lowercase();
+
+    let detection = *TYPE_DETECTION.get().unwrap_or(&TypeDetection::Extension);
+    let content = match detection {
+        TypeDetection::Content => detect_content_type(path),
+        TypeDetection::Extension => None,
+    };
+    // 无扩展名且无法按内容识别的文件默认不匹配任何类型
+    if extension.is_empty() && content.is_none() {
+        return Ok(false);
+    }
+
+    let mut is_match = false;
+    let mut exclude_match = false;
+    let type_mapping = type_table();
+
     // 处理每个类型条件
     for condition in file_type.split(',') {
         let condition = condition.trim();
@@ -678,46 +1350,33 @@ pub fn check_file_type(path: &Path, file_type: &str) -> Result<bool> {
         } else {
             (false, condition)
         };
+        let type_str = type_str.to_lowercase();
 
-        if type_str == "executable" {
-            // 检查扩展名
-            if let Some(extensions) = type_mapping.get("executable") {
-                if extensions.contains(&extension.as_str()) {
-                    is_executable_by_extension = true;
-                }
-            }
-
-            // 检查文件元数据执行权限 (Linux/Unix系统)
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if let Ok(metadata) = path.metadata() {
-                    let mode = metadata.permissions().mode();
-                    is_executable_by_metadata = mode & 0o111 != 0; // 检查任何执行位
-                }
-            }
-
-            let is_exec = is_executable_by_extension || is_executable_by_metadata;
-            if is_exec {
-                if negate {
-                    exclude_match = true;
-                } else {
-                    is_match = true;
-                }
-            }
-        } else if let Some(extensions) = type_mapping.get(type_str) {
-            if extensions.contains(&extension.as_str()) {
-                if negate {
-                    exclude_match = true;
-                } else {
-                    is_match = true;
-                }
-            }
-        } else {
+        let Some(extensions) = type_mapping.get(&type_str) else {
             return Err(anyhow::Error::msg(format!(
                 "Unsupported file type: {}",
                 type_str
             )));
+        };
+        let matches = match &content {
+            // 按magic number识别出具体格式时以内容为准，扩展名不对的文件不会按扩展名匹配
+            // 普通文本等没有识别出具体格式时再看扩展名，svg、yml、tex这类文本格式只能靠扩展名区分
+            Some(content) => {
+                content.ext.is_some_and(|ext| extensions.iter().any(|e| e == ext))
+                    || (type_str == "text" && content.text)
+                    || (type_str == "executable" && content.executable)
+                    || (content.ext.is_none() && extensions.contains(&extension))
+            }
+            None => extensions.contains(&extension),
+        };
+        // 检查文件元数据执行权限 (Linux/Unix系统，其他系统上mode为0)
+        let is_executable_by_metadata = type_str == "executable" && info.mode & 0o111 != 0;
+        if matches || is_executable_by_metadata {
+            if negate {
+                exclude_match = true;
+            } else {
+                is_match = true;
+            }
         }
     }
 
@@ -727,30 +1386,30 @@ pub fn check_file_type(path: &Path, file_type: &str) -> Result<bool> {
 
 /// 检查文件权限是否匹配
 /// # 参数
-/// * `path` - 文件路径
-/// * `permission_str` - 权限字符串，格式为rwxrwxrwx
+/// * `mode` - 文件的权限位
+/// * `permission_str` - 权限字符串，格式为rwxrwxrwx或八进制（如755）
+///   前面加+表示包含：列出的权限都有即可匹配，如+--x--x--x、+111、+s（setuid或setgid）
 /// # 返回值
 /// * `Result<bool>` - 是否匹配
-pub fn check_permission(path: &Path, permission_str: &str) -> Result<bool> {
+pub fn permission_matches(mode: u32, permission_str: &str) -> Result<bool> {
     #[cfg(unix)]
     {
-        use std::os::unix::fs::PermissionsExt;
-        let metadata = path.metadata()?;
-        let mode = metadata.permissions().mode();
-        
-        // 将权限字符串转换为9位二进制掩码
-        let mut expected = 0;
-        for (i, c) in permission_str.chars().enumerate().take(9) {
-            match c {
-                'r' if i % 3 == 0 => expected |= 0o400 >> (i / 3 * 3),
-                'w' if i % 3 == 1 => expected |= 0o200 >> (i / 3 * 3),
-                'x' if i % 3 == 2 => expected |= 0o100 >> (i / 3 * 3),
-                '-' => {},
-                _ => return Err(anyhow::Error::msg("Invalid permission format")),
-            }
+        let (contains, permission_str) = match permission_str.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, permission_str),
+        };
+        let expected = parse_permission(permission_str)?;
+        if contains && permission_str == "s" {
+            // +s：设置了setuid或setgid中的任意一个
+            Ok(mode & 0o6000 != 0)
+        } else if contains {
+            Ok(mode & expected == expected)
+        } else if expected & 0o7000 != 0 {
+            Ok((mode & 0o7777) == expected)
+        } else {
+            // 没有指定特殊权限位时只比较rwx
+            Ok((mode & 0o777) == expected)
         }
-        
-        Ok((mode & 0o777) == expected)
     }
     #[cfg(not(unix))]
     {
@@ -758,19 +1417,50 @@ pub fn check_permission(path: &Path, permission_str: &str) -> Result<bool> {
     }
 }
 
+/// 把rwxrwxrwx格式或八进制的权限字符串转换为权限位
+/// rwx格式中x的位置可以用s、t表示同时设置了setuid、setgid或sticky位（与ls -l相同）
+/// 单独的s、t表示setuid或setgid、sticky位，只用于+s、+t
+pub fn parse_permission(permission_str: &str) -> Result<u32> {
+    if !permission_str.is_empty() && permission_str.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(permission_str, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .ok_or_else(|| anyhow::Error::msg("Invalid permission format"));
+    }
+    match permission_str {
+        "s" => return Ok(0o6000),
+        "t" => return Ok(0o1000),
+        _ => {}
+    }
+    if permission_str.chars().count() > 9 {
+        return Err(anyhow::Error::msg("Invalid permission format"));
+    }
+    // 将权限字符串转换为二进制掩码
+    let mut expected = 0;
+    for (i, c) in permission_str.chars().enumerate() {
+        let shift = 6 - i / 3 * 3;
+        match (i % 3, c) {
+            (0, 'r') => expected |= 0o4 << shift,
+            (1, 'w') => expected |= 0o2 << shift,
+            (2, 'x') => expected |= 0o1 << shift,
+            (2, 's') if i < 6 => expected |= (0o1 << shift) | (0o4000 >> (i / 3)),
+            (2, 't') if i == 8 => expected |= 0o1001,
+            (_, '-') => {}
+            _ => return Err(anyhow::Error::msg("Invalid permission format")),
+        }
+    }
+    Ok(expected)
+}
+
 /// 检查文件所有者是否匹配
 /// # 参数
-/// * `path` - 文件路径
+/// * `uid` - 文件所有者的UID
 /// * `owner_str` - 所有者字符串，可以是用户名或UID
 /// # 返回值
 /// * `Result<bool>` - 是否匹配
-pub fn check_owner(path: &Path, owner_str: &str) -> Result<bool> {
+pub fn owner_matches(uid: u32, owner_str: &str) -> Result<bool> {
     #[cfg(unix)]
     {
-        use std::os::unix::fs::MetadataExt;
-        let metadata = path.metadata()?;
-        let uid = metadata.uid();
-        
         // 先尝试匹配用户名
         if let Some(user) = users::get_user_by_name(owner_str) {
             return Ok(user.uid() == uid);
@@ -791,17 +1481,13 @@ pub fn check_owner(path: &Path, owner_str: &str) -> Result<bool> {
 
 /// 检查文件所属组是否匹配
 /// # 参数
-/// * `path` - 文件路径
+/// * `gid` - 文件所属组的GID
 /// * `group_str` - 组字符串，可以是组名或GID
 /// # 返回值
 /// * `Result<bool>` - 是否匹配
-pub fn check_group(path: &Path, group_str: &str) -> Result<bool> {
+pub fn group_matches(gid: u32, group_str: &str) -> Result<bool> {
     #[cfg(unix)]
     {
-        use std::os::unix::fs::MetadataExt;
-        let metadata = path.metadata()?;
-        let gid = metadata.gid();
-        
         // 先尝试匹配组名
         if let Some(group) = users::get_group_by_name(group_str) {
             return Ok(group.gid() == gid);
@@ -0,0 +1,146 @@
+use ignore::{Error, WalkBuilder, WalkState};
+use std::path::{Path, PathBuf};
+
+/// rt自己的忽略文件名，语法与.gitignore相同
+pub const RT_IGNORE_FILENAME: &str = ".rtignore";
+
+/// 目录遍历选项，search、ls -R和文件树共用
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /// 最大深度，根目录本身的深度为0，其中的条目深度为1；None表示不限制
+    pub max_depth: Option<usize>,
+    /// 最小深度，深度小于它的条目不交给回调，但仍会进入其中的目录
+    pub min_depth: usize,
+    /// 是否跟随符号链接进入目录；按设备号和inode检测循环，出现循环的链接会被跳过并提示
+    pub follow_links: bool,
+    /// 是否不进入其他文件系统中的目录（如挂载点）
+    pub same_file_system: bool,
+    /// 是否包含隐藏文件
+    pub hidden: bool,
+    /// 是否忽略.gitignore、.ignore和.rtignore规则
+    pub no_ignore: bool,
+    /// 是否按路径排序；排序时单线程遍历，结果顺序固定
+    pub sort: bool,
+}
+
+impl WalkOptions {
+    pub fn new() -> Self {
+        WalkOptions {
+            max_depth: None,
+            min_depth: 0,
+            follow_links: false,
+            same_file_system: false,
+            hidden: false,
+            no_ignore: false,
+            sort: false,
+        }
+    }
+
+    fn builder(&self, root: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .max_depth(self.max_depth)
+            .hidden(!self.hidden)
+            .follow_links(self.follow_links)
+            .same_file_system(self.same_file_system)
+            // 不在git仓库中时也使用.gitignore
+            .require_git(false);
+        if self.no_ignore {
+            builder
+                .ignore(false)
+                .git_ignore(false)
+                .git_global(false)
+                .git_exclude(false)
+                .parents(false);
+        } else {
+            builder.add_custom_ignore_filename(RT_IGNORE_FILENAME);
+        }
+        if self.sort {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+        builder
+    }
+}
+
+/// 遍历root下的所有条目（包括root本身，深度为0）
+/// 默认使用线程池并行遍历（工作窃取），visit会在多个线程中被调用；
+/// 开启sort时按路径顺序单线程遍历，visit的调用顺序是固定的
+/// # 参数
+/// - `root`: 起始路径，可以是文件或目录
+/// - `options`: 遍历选项
+/// - `visit`: 回调，参数为条目路径和深度
+pub fn walk<F>(root: &Path, options: &WalkOptions, visit: F)
+where
+    F: Fn(&Path, usize) + Sync,
+{
+    walk_while(root, options, |p, depth| {
+        visit(p, depth);
+        true
+    });
+}
+
+/// 与walk相同，但visit返回false时尽快停止遍历
+/// 并行遍历时其他线程正在处理的条目仍可能被交给visit
+pub fn walk_while<F>(root: &Path, options: &WalkOptions, visit: F)
+where
+    F: Fn(&Path, usize) -> bool + Sync,
+{
+    let builder = options.builder(root);
+    if options.sort {
+        for entry in builder.build() {
+            match entry {
+                Ok(entry) if entry.depth() < options.min_depth => {}
+                Ok(entry) => {
+                    if !visit(entry.path(), entry.depth()) {
+                        return;
+                    }
+                }
+                Err(e) => report_loop(&e),
+            }
+        }
+        return;
+    }
+    builder.build_parallel().run(|| {
+        Box::new(|entry| match entry {
+            Ok(entry) if entry.depth() < options.min_depth => WalkState::Continue,
+            Ok(entry) => {
+                if visit(entry.path(), entry.depth()) {
+                    WalkState::Continue
+                } else {
+                    WalkState::Quit
+                }
+            }
+            Err(e) => {
+                report_loop(&e);
+                WalkState::Continue
+            }
+        })
+    });
+}
+
+/// 跟随符号链接时遇到循环给出提示，其他错误（如没有权限）和之前一样忽略
+fn report_loop(err: &Error) {
+    match err {
+        Error::Loop { ancestor, child } => eprintln!(
+            "{}: 符号链接循环，指向上级目录 {}，已跳过",
+            child.display(),
+            ancestor.display()
+        ),
+        Error::WithPath { err, .. } | Error::WithDepth { err, .. } => report_loop(err),
+        _ => {}
+    }
+}
+
+/// 列出目录下一层中未被忽略的条目，按文件名排序
+pub fn list_dir(dir: &Path, options: &WalkOptions) -> Vec<PathBuf> {
+    let mut options = options.clone();
+    options.max_depth = Some(1);
+    options.sort = true;
+    options
+        .builder(dir)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.depth() == 1)
+        .map(|e| e.into_path())
+        .collect()
+}